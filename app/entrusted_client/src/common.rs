@@ -5,12 +5,21 @@ use serde::{Deserialize, Serialize};
 
 use crate::l10n;
 
+use entrusted_common::limits;
+
 pub const CONTAINER_IMAGE_EXE: &str = "/usr/local/bin/entrusted-container";
 pub const ENV_VAR_ENTRUSTED_DOC_PASSWD: &str = "ENTRUSTED_DOC_PASSWD";
 pub const LOG_FORMAT_JSON: &str = "json";
 
 pub const IMAGE_QUALITY_CHOICES: [&str; 3] = ["low", "medium", "high"];
 pub const IMAGE_QUALITY_CHOICE_DEFAULT_INDEX: usize = 1;
+pub const OCR_SIDECAR_CHOICES: [&str; 3] = ["txt", "hocr", "alto"];
+pub const OCR_SIDECAR_EXTENSIONS: [&str; 3] = ["txt", "hocr", "alto.xml"];
+pub const TEXT_LAYER_CHOICES: [&str; 2] = ["ocr", "original"];
+pub const IMAGE_ENCODING_CHOICES: [&str; 3] = ["lossless", "jpeg", "bilevel"];
+// Only used in the GUI Desktop client
+#[allow(dead_code)]
+pub const IMAGE_ENCODING_CHOICE_DEFAULT_INDEX: usize = 0;
 pub const DEFAULT_FILE_SUFFIX: &str  = "entrusted";
 
 #[macro_export]
@@ -122,10 +131,82 @@ pub struct ReleaseInfo {
     pub tag_name: String,
 }
 
+// Progress events of entrusted-container, see its events module
+// Events without any version come from older container images, and they only have the data and percent_complete fields
+pub const EVENT_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    PhaseStarted,
+    #[default]
+    Progress,
+    PageDone,
+    Warning,
+    Error,
+    Result,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ConversionResult {
+    pub page_count: usize,
+    pub output_size: u64,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct LogMessage {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub event: EventType,
     pub data: String,
     pub percent_complete: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_num: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<usize>,
+    #[serde(default)]
+    pub elapsed_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ConversionResult>,
+}
+
+impl LogMessage {
+    pub fn new(event: EventType, percent_complete: usize, data: String) -> Self {
+        Self {
+            version: EVENT_PROTOCOL_VERSION,
+            event,
+            data,
+            percent_complete,
+            phase: None,
+            page_num: None,
+            page_index: None,
+            page_count: None,
+            elapsed_ms: 0,
+            error_code: None,
+            result: None,
+        }
+    }
+
+    // Pages are followed by their position in the phase, and failure messages by their error code
+    // i.e. 'Processing page 3 (3/10)' or 'Conversion failed with reason: ... [WRONG_PASSWORD]'
+    pub fn display_text(&self) -> String {
+        if let Some(error_code) = self.error_code.as_ref() {
+            format!("{} [{}]", self.data, error_code)
+        } else if let (EventType::PageDone, Some(page_index), Some(page_count)) = (self.event, self.page_index, self.page_count) {
+            format!("{} ({}/{})", self.data, page_index, page_count)
+        } else {
+            self.data.clone()
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -136,6 +217,18 @@ pub struct ConvertOptions {
     pub opt_ocr_lang: Option<String>,
     pub opt_passwd: Option<String>,
     pub seccomp_profile_enabled: bool,
+    pub opt_page_ranges: Option<String>,
+    pub resource_limits: limits::ResourceLimits,
+    pub preserve_page_size: bool,
+    pub opt_dpi: Option<u64>,
+    pub opt_image_size: Option<String>,
+    pub opt_image_encoding: Option<String>,
+    pub opt_jpeg_quality: Option<u8>,
+    pub pdfa: bool,
+    pub strip_metadata: bool,
+    pub opt_text_layer: Option<String>,
+    pub ocr_sidecars: Vec<String>,
+    pub inspect: bool,
 }
 
 impl ConvertOptions {
@@ -152,7 +245,19 @@ impl ConvertOptions {
             visual_quality,
             opt_ocr_lang,
             opt_passwd,
-            seccomp_profile_enabled
+            seccomp_profile_enabled,
+            opt_page_ranges: None,
+            resource_limits: limits::ResourceLimits::default(),
+            preserve_page_size: false,
+            opt_dpi: None,
+            opt_image_size: None,
+            opt_image_encoding: None,
+            opt_jpeg_quality: None,
+            pdfa: false,
+            strip_metadata: false,
+            opt_text_layer: None,
+            ocr_sidecars: Vec::new(),
+            inspect: false,
         }
     }
 }
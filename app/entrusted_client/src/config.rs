@@ -23,6 +23,28 @@ pub struct AppConfig {
     pub visual_quality: Option<String>,
     #[serde(rename(serialize = "seccomp-profile-disabled", deserialize = "seccomp-profile-disabled"))]
     pub seccomp_profile_disabled: Option<bool>,
+    #[serde(rename(serialize = "preserve-page-size", deserialize = "preserve-page-size"))]
+    pub preserve_page_size: Option<bool>,
+    #[serde(rename(serialize = "image-encoding", deserialize = "image-encoding"))]
+    pub image_encoding: Option<String>,
+    #[serde(rename(serialize = "jpeg-quality", deserialize = "jpeg-quality"))]
+    pub jpeg_quality: Option<u8>,
+    #[serde(rename(serialize = "pdfa", deserialize = "pdfa"))]
+    pub pdfa: Option<bool>,
+    #[serde(rename(serialize = "strip-metadata", deserialize = "strip-metadata"))]
+    pub strip_metadata: Option<bool>,
+    #[serde(rename(serialize = "text-layer", deserialize = "text-layer"))]
+    pub text_layer: Option<String>,
+    #[serde(rename(serialize = "ocr-sidecars", deserialize = "ocr-sidecars"))]
+    pub ocr_sidecars: Option<Vec<String>>,
+    #[serde(rename(serialize = "max-input-size", deserialize = "max-input-size"))]
+    pub max_input_size: Option<u64>,
+    #[serde(rename(serialize = "max-pages", deserialize = "max-pages"))]
+    pub max_pages: Option<u64>,
+    #[serde(rename(serialize = "max-page-area", deserialize = "max-page-area"))]
+    pub max_page_area: Option<u64>,
+    #[serde(rename(serialize = "max-total-pixels", deserialize = "max-total-pixels"))]
+    pub max_total_pixels: Option<u64>,
 }
 
 pub fn default_container_image_name() -> String {
@@ -40,6 +62,17 @@ impl Default for AppConfig {
             openwith_appname: None,
             visual_quality: None,
             seccomp_profile_disabled: None,
+            preserve_page_size: None,
+            image_encoding: None,
+            jpeg_quality: None,
+            pdfa: None,
+            strip_metadata: None,
+            text_layer: None,
+            ocr_sidecars: None,
+            max_input_size: None,
+            max_pages: None,
+            max_page_area: None,
+            max_total_pixels: None,
         }
     }
 }
@@ -95,6 +95,66 @@ impl <'a> SanitizerRt for NativeSanitizerRt<'a> {
             convert_args.append(&mut vec![
                 "--ocr-lang".to_string(), ocr_language
             ]);
+
+            if !convert_options.ocr_sidecars.is_empty() {
+                convert_args.append(&mut vec![
+                    "--ocr-sidecars".to_string(), convert_options.ocr_sidecars.join(",")
+                ]);
+            }
+        }
+
+        if let Some(page_ranges) = convert_options.opt_page_ranges {
+            convert_args.append(&mut vec![
+                "--pages".to_string(), page_ranges
+            ]);
+        }
+
+        convert_args.append(&mut convert_options.resource_limits.to_args());
+
+        if convert_options.preserve_page_size {
+            convert_args.push("--preserve-page-size".to_string());
+        }
+
+        if let Some(dpi) = convert_options.opt_dpi {
+            convert_args.append(&mut vec![
+                "--dpi".to_string(), dpi.to_string()
+            ]);
+        }
+
+        if let Some(image_size) = convert_options.opt_image_size {
+            convert_args.append(&mut vec![
+                "--image-size".to_string(), image_size
+            ]);
+        }
+
+        if let Some(image_encoding) = convert_options.opt_image_encoding {
+            convert_args.append(&mut vec![
+                "--image-encoding".to_string(), image_encoding
+            ]);
+        }
+
+        if let Some(jpeg_quality) = convert_options.opt_jpeg_quality {
+            convert_args.append(&mut vec![
+                "--jpeg-quality".to_string(), jpeg_quality.to_string()
+            ]);
+        }
+
+        if convert_options.pdfa {
+            convert_args.push("--pdfa".to_string());
+        }
+
+        if convert_options.strip_metadata {
+            convert_args.push("--strip-metadata".to_string());
+        }
+
+        if let Some(text_layer) = convert_options.opt_text_layer {
+            convert_args.append(&mut vec![
+                "--text-layer".to_string(), text_layer
+            ]);
+        }
+
+        if convert_options.inspect {
+            convert_args.push("--inspect".to_string());
         }
 
         convert_args.append(&mut vec![
@@ -105,11 +165,14 @@ impl <'a> SanitizerRt for NativeSanitizerRt<'a> {
         ]);
 
         if exec_crt_command(trans.gettext("Starting document processing"), self.container_program.clone(), env_vars, convert_args, tx.clone_box(), true, printer.clone_box(), trans.clone()).is_ok() {
-            let atime = FileTime::now();
-            let output_file = fs::File::open(&output_path)?;
+            // There's no PDF result when only inspecting the document
+            if !convert_options.inspect {
+                let atime = FileTime::now();
+                let output_file = fs::File::open(&output_path)?;
 
-            // This seems to fail on Microsoft Windows with permission denied errors
-            let _ = filetime::set_file_handle_times(&output_file, Some(atime), Some(atime));
+                // This seems to fail on Microsoft Windows with permission denied errors
+                let _ = filetime::set_file_handle_times(&output_file, Some(atime), Some(atime));
+            }
 
             success = true;
         } else {
@@ -277,10 +340,77 @@ impl <'a> SanitizerRt for ContainerizedSanitizerRt<'a>  {
             common::CONTAINER_IMAGE_EXE.to_string()
         ]);
 
+        // The input is mounted without its extension, text formats such as CSV are only recognized from it
+        if let Some(input_ext) = input_path.extension().and_then(|v| v.to_str()) {
+            convert_args.append(&mut vec![
+                "--input-extension".to_string(), input_ext.to_string()
+            ]);
+        }
+
         if let Some(ocr_language) = convert_options.opt_ocr_lang {
             convert_args.append(&mut vec![
                 "--ocr-lang".to_string(), ocr_language
             ]);
+
+            if !convert_options.ocr_sidecars.is_empty() {
+                convert_args.append(&mut vec![
+                    "--ocr-sidecars".to_string(), convert_options.ocr_sidecars.join(",")
+                ]);
+            }
+        }
+
+        if let Some(page_ranges) = convert_options.opt_page_ranges {
+            convert_args.append(&mut vec![
+                "--pages".to_string(), page_ranges
+            ]);
+        }
+
+        convert_args.append(&mut convert_options.resource_limits.to_args());
+
+        if convert_options.preserve_page_size {
+            convert_args.push("--preserve-page-size".to_string());
+        }
+
+        if let Some(dpi) = convert_options.opt_dpi {
+            convert_args.append(&mut vec![
+                "--dpi".to_string(), dpi.to_string()
+            ]);
+        }
+
+        if let Some(image_size) = convert_options.opt_image_size {
+            convert_args.append(&mut vec![
+                "--image-size".to_string(), image_size
+            ]);
+        }
+
+        if let Some(image_encoding) = convert_options.opt_image_encoding {
+            convert_args.append(&mut vec![
+                "--image-encoding".to_string(), image_encoding
+            ]);
+        }
+
+        if let Some(jpeg_quality) = convert_options.opt_jpeg_quality {
+            convert_args.append(&mut vec![
+                "--jpeg-quality".to_string(), jpeg_quality.to_string()
+            ]);
+        }
+
+        if convert_options.pdfa {
+            convert_args.push("--pdfa".to_string());
+        }
+
+        if convert_options.strip_metadata {
+            convert_args.push("--strip-metadata".to_string());
+        }
+
+        if let Some(text_layer) = convert_options.opt_text_layer {
+            convert_args.append(&mut vec![
+                "--text-layer".to_string(), text_layer
+            ]);
+        }
+
+        if convert_options.inspect {
+            convert_args.push("--inspect".to_string());
         }
 
         convert_args.append(&mut vec![
@@ -296,6 +426,13 @@ impl <'a> SanitizerRt for ContainerizedSanitizerRt<'a>  {
                 let _ = fs::remove_file(tmp_input_loc);
             }
 
+            // The inspection report is part of the command output, there's no PDF result
+            if convert_options.inspect {
+                let _ = cleanup_dir(&dz_tmp_safe);
+
+                return Ok(());
+            }
+
             if output_path.exists() {
                 if let Err(ex) = fs::remove_file(&output_path) {
                     eprintln!("{}", trans.gettext_fmt("Cannot remove output file: {0}. {1}.", vec![&output_path.display().to_string(), &ex.to_string()]));
@@ -313,7 +450,16 @@ impl <'a> SanitizerRt for ContainerizedSanitizerRt<'a>  {
                 let atime = FileTime::now();
 
                 fs::copy(&container_output_file_path, &output_path)?;
-                fs::remove_file(container_output_file_path)?;
+                fs::remove_file(&container_output_file_path)?;
+
+                // OCR text files are named after the PDF result, they're only present if requested
+                for sidecar_extension in common::OCR_SIDECAR_EXTENSIONS {
+                    let container_sidecar_path = container_output_file_path.with_extension(sidecar_extension);
+
+                    if container_sidecar_path.exists() {
+                        fs::copy(&container_sidecar_path, output_path.with_extension(sidecar_extension))?;
+                    }
+                }
 
                 let output_file = fs::File::open(&output_path)?;
 
@@ -463,10 +609,7 @@ fn exec_crt_command (cmd_desc: String, container_program: common::ContainerProgr
                             explanation = trans.gettext("Container process terminated abruptly potentially due to a memory access fault. Please report the issue at: https://github.com/rimerosolutions/entrusted/issues");
                         }
 
-                        let lm = common::LogMessage {
-                            data: format!("{} {}", trans.gettext("Conversion failed!"), explanation),
-                            percent_complete: 100
-                        };
+                        let lm = common::LogMessage::new(common::EventType::Error, 100, format!("{} {}", trans.gettext("Conversion failed!"), explanation));
 
                         if let Ok(lm_string) = serde_json::to_string(&lm) {
                             let _ = tx.send(common::AppEvent::ConversionProgressEvent(lm_string));
@@ -487,6 +630,8 @@ fn exec_crt_command (cmd_desc: String, container_program: common::ContainerProgr
 trait LogPrinter: Send + Sync {
     fn print(&self, percent_complete: usize, data: String) -> String;
 
+    fn print_error(&self, percent_complete: usize, data: String) -> String;
+
     fn clone_box(&self) -> Box<dyn LogPrinter>;
 }
 
@@ -507,6 +652,10 @@ impl LogPrinter for PlainLogPrinter {
         format!("{}% {}", percent_complete, data)
     }
 
+    fn print_error(&self, percent_complete: usize, data: String) -> String {
+        self.print(percent_complete, data)
+    }
+
     fn clone_box(&self) -> Box<dyn LogPrinter> {
         Box::new(*self)
     }
@@ -514,9 +663,12 @@ impl LogPrinter for PlainLogPrinter {
 
 impl LogPrinter for JsonLogPrinter {
     fn print(&self, percent_complete: usize, data: String) -> String {
-        let log_msg = &common::LogMessage {
-            percent_complete, data
-        };
+        let log_msg = &common::LogMessage::new(common::EventType::Progress, percent_complete, data);
+        serde_json::to_string(log_msg).unwrap()
+    }
+
+    fn print_error(&self, percent_complete: usize, data: String) -> String {
+        let log_msg = &common::LogMessage::new(common::EventType::Error, percent_complete, data);
         serde_json::to_string(log_msg).unwrap()
     }
 
@@ -560,7 +712,7 @@ pub fn convert(input_path: PathBuf, output_path: PathBuf, convert_options: commo
 
         if let Err(ex) = rt.process(input_path, output_path, convert_options, tx.clone_box(), printer.clone_box(), trans) {
             err_msg.push_str(&ex.to_string());
-            tx.send(common::AppEvent::ConversionProgressEvent(printer.print(100, err_msg.clone())))?;
+            tx.send(common::AppEvent::ConversionProgressEvent(printer.print_error(100, err_msg.clone())))?;
         } else {
             success = true;
         }
@@ -576,7 +728,7 @@ pub fn convert(input_path: PathBuf, output_path: PathBuf, convert_options: commo
             err_msg.push_str(&trans.gettext("Please install Docker or Podman, and make sure that it's running."));
         }
 
-        tx.send(common::AppEvent::ConversionProgressEvent(printer.print(100, err_msg.clone())))?;
+        tx.send(common::AppEvent::ConversionProgressEvent(printer.print_error(100, err_msg.clone())))?;
     }
 
     if success {
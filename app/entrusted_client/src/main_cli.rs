@@ -1,4 +1,4 @@
-use clap::{Command, Arg, ArgAction, builder::PossibleValue};
+use clap::{Command, Arg, ArgAction, ArgMatches, builder::PossibleValue};
 use std::env;
 use std::error::Error;
 use std::path::PathBuf;
@@ -11,6 +11,7 @@ use indicatif::ProgressBar;
 use std::collections::HashMap;
 use once_cell::sync::OnceCell;
 use entrusted_l10n as l10n;
+use entrusted_common::{errors, limits};
 
 mod common;
 mod config;
@@ -79,6 +80,21 @@ fn main() -> Result<(), Box<dyn Error>> {
     let help_file_suffix = trans.gettext("Default file suffix (entrusted)");
     let help_password_prompt = trans.gettext("Prompt for document password");
     let help_update_checks = trans.gettext("Check for updates");
+    let help_pages = trans.gettext("Optional selection of pages to sanitize (i.e. '1-5,9,12-')");
+    let help_preserve_page_size = trans.gettext("Keep the original size and orientation of each page");
+    let help_dpi = trans.gettext("Optional rendering resolution in DPI, between 70 and 2400 (overrides the visual quality)");
+    let help_image_size = trans.gettext("Optional pixel box to fit pages into, as width x height (i.e. '2480x3508', overrides the visual quality)");
+    let help_image_encoding = trans.gettext("Optional encoding of the page images in the PDF result (lossless, jpeg or bilevel for text documents)");
+    let help_jpeg_quality = trans.gettext("Optional JPEG quality between 1 and 100 for the jpeg image encoding");
+    let help_pdfa = trans.gettext("Produce a PDF/A-2b document for long-term archiving");
+    let help_text_layer = trans.gettext("Optional source of the searchable text (ocr or original, which falls back to OCR for pages without text)");
+    let help_strip_metadata = trans.gettext("Do not carry over the document title, author, subject and creation date");
+    let help_ocr_sidecars = trans.gettext("Optional comma-separated list of OCR text files to save next to the PDF result (txt, hocr, alto)");
+    let help_max_input_size = trans.gettext("Optional maximum input file size in megabytes");
+    let help_max_pages = trans.gettext("Optional maximum number of pages to process");
+    let help_max_page_area = trans.gettext("Optional maximum page area in square inches");
+    let help_max_total_pixels = trans.gettext("Optional maximum number of pixels to render for all pages");
+    let help_inspect = trans.gettext("Print facts about a document as JSON (type, pages, encryption, text) without converting it");
 
     let cmd_help_template = trans.gettext(&format!("{}\n{}\n{}\n\n{}\n\n{}\n{}",
                                                    "{bin} {version}",
@@ -97,6 +113,29 @@ fn main() -> Result<(), Box<dyn Error>> {
         .help_template(cmd_help_template)
         .author(option_env!("CARGO_PKG_AUTHORS").unwrap_or("Unknown"))
         .about(option_env!("CARGO_PKG_DESCRIPTION").unwrap_or("Unknown"))
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("inspect")
+                .about(help_inspect)
+                .arg(
+                    Arg::new("input-filename")
+                        .long("input-filename")
+                        .help(help_input_filename.clone())
+                        .required(true)
+                ).arg(
+                    Arg::new("container-image-name")
+                        .long("container-image-name")
+                        .help(help_container_image_name.clone())
+                        .default_value(default_container_image_to_str())
+                        .required(false)
+                ).arg(
+                    Arg::new("passwd-prompt")
+                        .long("passwd-prompt")
+                        .help(help_password_prompt.clone())
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                )
+        )
         .arg(
             Arg::new("output-filename")
                 .long("output-filename")
@@ -157,6 +196,103 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .help(help_password_prompt)
                 .required(false)
                 .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("pages")
+                .long("pages")
+                .help(help_pages)
+                .required(false)
+        ).arg(
+            Arg::new("preserve-page-size")
+                .long("preserve-page-size")
+                .help(help_preserve_page_size)
+                .required(false)
+                .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("dpi")
+                .long("dpi")
+                .help(help_dpi)
+                .value_parser(clap::value_parser!(u64).range(70..=2400))
+                .conflicts_with("image-size")
+                .required(false)
+        ).arg(
+            Arg::new("image-size")
+                .long("image-size")
+                .help(help_image_size)
+                .conflicts_with("preserve-page-size")
+                .required(false)
+        ).arg(
+            Arg::new("image-encoding")
+                .long("image-encoding")
+                .help(help_image_encoding)
+                .value_parser([
+                    PossibleValue::new(common::IMAGE_ENCODING_CHOICES[0]),
+                    PossibleValue::new(common::IMAGE_ENCODING_CHOICES[1]),
+                    PossibleValue::new(common::IMAGE_ENCODING_CHOICES[2]),
+                ])
+                .required(false)
+        ).arg(
+            Arg::new("jpeg-quality")
+                .long("jpeg-quality")
+                .help(help_jpeg_quality)
+                .value_parser(clap::value_parser!(u8).range(1..=100))
+                .required(false)
+        ).arg(
+            Arg::new("pdfa")
+                .long("pdfa")
+                .help(help_pdfa)
+                .required(false)
+                .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("strip-metadata")
+                .long("strip-metadata")
+                .help(help_strip_metadata)
+                .required(false)
+                .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("text-layer")
+                .long("text-layer")
+                .help(help_text_layer)
+                .value_parser([
+                    PossibleValue::new(common::TEXT_LAYER_CHOICES[0]),
+                    PossibleValue::new(common::TEXT_LAYER_CHOICES[1]),
+                ])
+                .required(false)
+        ).arg(
+            Arg::new("ocr-sidecars")
+                .long("ocr-sidecars")
+                .help(help_ocr_sidecars)
+                .value_parser([
+                    PossibleValue::new(common::OCR_SIDECAR_CHOICES[0]),
+                    PossibleValue::new(common::OCR_SIDECAR_CHOICES[1]),
+                    PossibleValue::new(common::OCR_SIDECAR_CHOICES[2]),
+                ])
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .required(false)
+        ).arg(
+            Arg::new("max-input-size")
+                .long("max-input-size")
+                .help(help_max_input_size)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("max-pages")
+                .long("max-pages")
+                .help(help_max_pages)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("max-page-area")
+                .long("max-page-area")
+                .help(help_max_page_area)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("max-total-pixels")
+                .long("max-total-pixels")
+                .help(help_max_total_pixels)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
         );
 
     let run_matches= app.get_matches();
@@ -179,6 +315,10 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
+    if let Some(inspect_matches) = run_matches.subcommand_matches("inspect") {
+        return inspect(inspect_matches, trans);
+    }
+
     let mut input_filename = "";
     let mut output_filename = PathBuf::from("");
 
@@ -224,18 +364,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         ocr_lang = Some(proposed_ocr_lang.to_string());
     }
 
-    // Deal transparently with Windows UNC path returned by fs::canonicalize
-    // std::fs::canonicalize returns UNC paths on Windows, and a lot of software doesn't support UNC paths
-    // This is problematic with Docker and mapped volumes for this application
-    // See https://github.com/rust-lang/rust/issues/42869
-    let src_path = {
-        #[cfg(not(target_os = "windows"))] {
-            std::fs::canonicalize(input_filename)?
-        }
-        #[cfg(target_os = "windows")] {
-            dunce::canonicalize(input_filename)?
-        }
-    };
+    let src_path = canonical_input_path(input_filename)?;
 
     let file_suffix = if let Some(proposed_file_suffix) = &run_matches.get_one::<String>("file-suffix") {
         proposed_file_suffix.to_string()
@@ -271,34 +400,57 @@ fn main() -> Result<(), Box<dyn Error>> {
     };
 
     let opt_passwd = if run_matches.get_flag("passwd-prompt") {
-        // Simplification for password entry from the Web interface and similar non-TTY use-cases
-        if let Ok(env_passwd) = env::var("ENTRUSTED_AUTOMATED_PASSWORD_ENTRY") {
-            Some(env_passwd)
-        } else {
-            println!("{}", trans.gettext("Please enter the password for the document"));
-
-            if let Ok(passwd) = rpassword::read_password() {
-                Some(passwd)
-            } else {
-                return Err(trans.gettext("Failed to read password!").into());
-            }
-        }
-    }  else {
+        Some(read_document_password(&trans)?)
+    } else {
         None
     };
 
-    let seccomp_profile_disabled = if let Ok(env_seccomp_enablement) = env::var("ENTRUSTED_AUTOMATED_SECCOMP_ENABLEMENT") {
-        env_seccomp_enablement.to_lowercase() == "false" || env_seccomp_enablement.to_lowercase() == "no"
+    let opt_page_ranges = run_matches.get_one::<String>("pages").cloned();
+    let opt_dpi = run_matches.get_one::<u64>("dpi").copied();
+    let opt_image_size = run_matches.get_one::<String>("image-size").cloned();
+
+    // A custom pixel box takes precedence over the saved page size preference
+    let preserve_page_size = opt_image_size.is_none() && (run_matches.get_flag("preserve-page-size") || app_config.preserve_page_size.unwrap_or(false));
+
+    let opt_image_encoding = run_matches.get_one::<String>("image-encoding").cloned().or(app_config.image_encoding.clone());
+    let opt_jpeg_quality = run_matches.get_one::<u8>("jpeg-quality").copied().or(app_config.jpeg_quality);
+    let pdfa = run_matches.get_flag("pdfa") || app_config.pdfa.unwrap_or(false);
+    let opt_text_layer = run_matches.get_one::<String>("text-layer").cloned().or(app_config.text_layer.clone());
+    let strip_metadata = run_matches.get_flag("strip-metadata") || app_config.strip_metadata.unwrap_or(false);
+
+    // OCR text files are only produced when OCR is enabled
+    let ocr_sidecars: Vec<String> = if let Some(v) = run_matches.get_many::<String>("ocr-sidecars") {
+        v.cloned().collect()
     } else {
-        false
+        app_config.ocr_sidecars.clone().unwrap_or_default()
+    };
+
+    let resource_limits = limits::ResourceLimits {
+        opt_max_input_size: run_matches.get_one::<u64>("max-input-size").copied().or(app_config.max_input_size),
+        opt_max_pages: run_matches.get_one::<u64>("max-pages").copied().or(app_config.max_pages),
+        opt_max_page_area: run_matches.get_one::<u64>("max-page-area").copied().or(app_config.max_page_area),
+        opt_max_total_pixels: run_matches.get_one::<u64>("max-total-pixels").copied().or(app_config.max_total_pixels),
     };
 
+    let seccomp_profile_disabled = seccomp_profile_disabled();
+
     let (exec_handle, rx) = {
         let (tx, rx) = mpsc::channel::<common::AppEvent>();
 
         let exec_handle = thread::spawn({
             move || {
-                let convert_options = common::ConvertOptions::new(container_image_name, common::LOG_FORMAT_JSON.to_string(), image_quality, ocr_lang, opt_passwd, !seccomp_profile_disabled);
+                let mut convert_options = common::ConvertOptions::new(container_image_name, common::LOG_FORMAT_JSON.to_string(), image_quality, ocr_lang, opt_passwd, !seccomp_profile_disabled);
+                convert_options.opt_page_ranges = opt_page_ranges;
+                convert_options.resource_limits = resource_limits;
+                convert_options.preserve_page_size = preserve_page_size;
+                convert_options.opt_dpi = opt_dpi;
+                convert_options.opt_image_size = opt_image_size;
+                convert_options.opt_image_encoding = opt_image_encoding;
+                convert_options.opt_jpeg_quality = opt_jpeg_quality;
+                convert_options.pdfa = pdfa;
+                convert_options.strip_metadata = strip_metadata;
+                convert_options.opt_text_layer = opt_text_layer;
+                convert_options.ocr_sidecars = ocr_sidecars;
                 let eventer = Box::new(CliEventSender {
                     tx
                 });
@@ -313,6 +465,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         (exec_handle, rx)
     };
 
+    // The error code of a failed conversion determines the exit code
+    let mut opt_error_code: Option<String> = None;
+
     // Rendering a progressbar in plain mode
     if log_format == LOG_FORMAT_PLAIN {
         let pb = ProgressBar::new(100);
@@ -321,23 +476,140 @@ fn main() -> Result<(), Box<dyn Error>> {
             if let common::AppEvent::ConversionProgressEvent(msg) = line {
                 if let Ok(log_msg) = serde_json::from_slice::<common::LogMessage>(msg.as_bytes()) {
                     pb.set_position(log_msg.percent_complete as u64);
-                    pb.println(&log_msg.data);
+                    pb.println(log_msg.display_text());
+
+                    if log_msg.event == common::EventType::Error && log_msg.error_code.is_some() {
+                        opt_error_code = log_msg.error_code;
+                    }
                 }
             }
         }
     } else {
         for line in rx {
             if let common::AppEvent::ConversionProgressEvent(msg) = line {
+                if let Ok(log_msg) = serde_json::from_slice::<common::LogMessage>(msg.as_bytes()) {
+                    if log_msg.event == common::EventType::Error && log_msg.error_code.is_some() {
+                        opt_error_code = log_msg.error_code;
+                    }
+                }
+
                 println!("{}", msg);
             }
         }
     }
 
-    let exit_code = if let Ok(exec_result) = exec_handle.join() {
-        i32::from(exec_result.is_some())
+    let exit_code = match exec_handle.join() {
+        Ok(None) => 0,
+        Ok(Some(_)) => error_exit_code(opt_error_code.as_deref()),
+        Err(_) => 1,
+    };
+
+    std::process::exit(exit_code);
+}
+
+// The inspection report is the only output on stdout, progress messages are printed on stderr
+fn inspect(run_matches: &ArgMatches, trans: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    let input_filename = run_matches.get_one::<String>("input-filename").cloned().unwrap_or_default();
+
+    if fs::metadata(&input_filename).is_err() {
+        return Err(trans.gettext_fmt("The selected file does not exists! {0}", vec![&input_filename]).into());
+    }
+
+    let src_path = canonical_input_path(&input_filename)?;
+    let output_path = common::default_output_path(src_path.clone(), common::DEFAULT_FILE_SUFFIX.to_string())?;
+    let container_image_name = run_matches.get_one::<String>("container-image-name").cloned().unwrap_or_else(config::default_container_image_name);
+
+    let opt_passwd = if run_matches.get_flag("passwd-prompt") {
+        Some(read_document_password(&trans)?)
     } else {
-        1
+        None
+    };
+
+    let (exec_handle, rx) = {
+        let (tx, rx) = mpsc::channel::<common::AppEvent>();
+        let image_quality = common::IMAGE_QUALITY_CHOICES[common::IMAGE_QUALITY_CHOICE_DEFAULT_INDEX].to_string();
+        let seccomp_profile_enabled = !seccomp_profile_disabled();
+
+        let exec_handle = thread::spawn({
+            move || {
+                let mut convert_options = common::ConvertOptions::new(container_image_name, common::LOG_FORMAT_JSON.to_string(), image_quality, None, opt_passwd, seccomp_profile_enabled);
+                convert_options.inspect = true;
+                let eventer = Box::new(CliEventSender {
+                    tx
+                });
+
+                container::convert(src_path, output_path, convert_options, eventer, trans).is_ok()
+            }
+        });
+        (exec_handle, rx)
+    };
+
+    let mut opt_error_code: Option<String> = None;
+
+    for line in rx {
+        if let common::AppEvent::ConversionProgressEvent(msg) = line {
+            if let Ok(log_msg) = serde_json::from_slice::<common::LogMessage>(msg.as_bytes()) {
+                eprintln!("{}", log_msg.display_text());
+
+                if log_msg.event == common::EventType::Error && log_msg.error_code.is_some() {
+                    opt_error_code = log_msg.error_code;
+                }
+            } else {
+                println!("{}", msg);
+            }
+        }
+    }
+
+    let exit_code = match exec_handle.join() {
+        Ok(true) => 0,
+        Ok(false) => error_exit_code(opt_error_code.as_deref()),
+        Err(_) => 1,
     };
 
     std::process::exit(exit_code);
 }
+
+// Same exit codes as entrusted-container, failures without any known error code exit with 1
+fn error_exit_code(opt_error_code: Option<&str>) -> i32 {
+    opt_error_code
+        .and_then(errors::ErrorCode::from_name)
+        .unwrap_or(errors::ErrorCode::ConversionFailed)
+        .exit_code()
+}
+
+// Deal transparently with Windows UNC path returned by fs::canonicalize
+// std::fs::canonicalize returns UNC paths on Windows, and a lot of software doesn't support UNC paths
+// This is problematic with Docker and mapped volumes for this application
+// See https://github.com/rust-lang/rust/issues/42869
+fn canonical_input_path(input_filename: &str) -> Result<PathBuf, Box<dyn Error>> {
+    #[cfg(not(target_os = "windows"))] {
+        Ok(std::fs::canonicalize(input_filename)?)
+    }
+    #[cfg(target_os = "windows")] {
+        Ok(dunce::canonicalize(input_filename)?)
+    }
+}
+
+fn read_document_password(trans: &l10n::Translations) -> Result<String, Box<dyn Error>> {
+    // Simplification for password entry from the Web interface and similar non-TTY use-cases
+    if let Ok(env_passwd) = env::var("ENTRUSTED_AUTOMATED_PASSWORD_ENTRY") {
+        return Ok(env_passwd);
+    }
+
+    // The prompt isn't part of the output, i.e. the inspection report is the only content of stdout
+    eprintln!("{}", trans.gettext("Please enter the password for the document"));
+
+    if let Ok(passwd) = rpassword::read_password() {
+        Ok(passwd)
+    } else {
+        Err(trans.gettext("Failed to read password!").into())
+    }
+}
+
+fn seccomp_profile_disabled() -> bool {
+    if let Ok(env_seccomp_enablement) = env::var("ENTRUSTED_AUTOMATED_SECCOMP_ENABLEMENT") {
+        env_seccomp_enablement.to_lowercase() == "false" || env_seccomp_enablement.to_lowercase() == "no"
+    } else {
+        false
+    }
+}
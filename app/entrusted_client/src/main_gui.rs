@@ -19,6 +19,7 @@ use fltk::{
 };
 
 use entrusted_l10n as l10n;
+use entrusted_common::limits;
 mod common;
 mod config;
 mod container;
@@ -262,7 +263,8 @@ fn paint_underline<W: WidgetExt>(wid: &mut W) {
     }
 }
 
-fn row_to_task(active_ociimage_option: &String, image_quality: String,  active_ocrlang_option: &Option<String>, active_file_suffix: &str, active_seccomp: bool, active_row: &FileListRow) -> ConversionTask {
+#[allow(clippy::too_many_arguments)]
+fn row_to_task(active_ociimage_option: &String, image_quality: String, active_preserve_page_size: bool, active_ocrlang_option: &Option<String>, active_page_ranges_option: &Option<String>, active_file_suffix: &str, active_seccomp: bool, active_row: &FileListRow) -> ConversionTask {
     let input_path = active_row.file.clone();
 
     let output_path = if let Some(custom_output_path) = active_row.opt_output_file.borrow().clone() {
@@ -274,7 +276,7 @@ fn row_to_task(active_ociimage_option: &String, image_quality: String,  active_o
     active_row.opt_output_file.replace(Some(output_path.display().to_string()));
 
     let opt_row_passwd = active_row.opt_passwd.borrow().clone();
-    let options = common::ConvertOptions::new(
+    let mut options = common::ConvertOptions::new(
         active_ociimage_option.to_owned(),
         common::LOG_FORMAT_JSON.to_string(),
         image_quality,
@@ -282,6 +284,8 @@ fn row_to_task(active_ociimage_option: &String, image_quality: String,  active_o
         opt_row_passwd,
         active_seccomp
     );
+    options.opt_page_ranges = active_page_ranges_option.to_owned();
+    options.preserve_page_size = active_preserve_page_size;
 
     ConversionTask {
         input_path,
@@ -418,17 +422,18 @@ fn show_dialog_help(parent_window_bounds: (i32, i32, i32, i32), trans: l10n::Tra
         .with_label(&trans.gettext(&label_container_solution))
         .with_align(enums::Align::Inside | enums::Align::Left);
 
-    let label_supported_docs = format!("Supported document types: \n- {}\n- {}\n- {}\n- {}\n- {}\n- {}",
-                                       "Images (.jpg, .jpeg, .gif, .png, .tif, .tiff)",
+    let label_supported_docs = format!("Supported document types: \n- {}\n- {}\n- {}\n- {}\n- {}\n- {}\n- {}",
+                                       "Images (.jpg, .jpeg, .gif, .png, .tif, .tiff, .webp, .bmp, .ico, .pnm)",
                                        "Document Graphics (.odg)",
-                                       "Text Documents (.rtf, .doc, .docx, .odt)",
-                                       "Spreadsheets (.xls, .xlsx, .ods)",
+                                       "Text Documents (.rtf, .doc, .docx, .odt, .txt, .md, .html, .epub, .fb2, .eml, .msg)",
+                                       "Spreadsheets (.xls, .xlsx, .ods, .csv)",
                                        "Presentations (.ppt, .pptx, .odp)",
+                                       "Archives (.zip, .tar, .gz)",
                                        "PDF files (.pdf)"
     );
 
     frame::Frame::default()
-        .with_size(350, 130)
+        .with_size(350, 145)
         .with_label(&trans.gettext(&label_supported_docs))
         .with_align(enums::Align::Inside | enums::Align::Left);
 
@@ -1213,6 +1218,14 @@ fn main() -> Result<(), Box<dyn Error>> {
     let appconfig_ret = config::load_config();
     let appconfig: config::AppConfig = appconfig_ret.unwrap_or_default();
 
+    // Resource limits are only configurable from the configuration file
+    let resource_limits = limits::ResourceLimits {
+        opt_max_input_size: appconfig.max_input_size,
+        opt_max_pages: appconfig.max_pages,
+        opt_max_page_area: appconfig.max_page_area,
+        opt_max_total_pixels: appconfig.max_total_pixels,
+    };
+
     let current_row_idx = Arc::new(AtomicI32::new(0));
     let converstion_stop_requested = Arc::new(AtomicBool::new(false));
     let is_converting = Arc::new(AtomicBool::new(false));
@@ -1473,6 +1486,155 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     result_visual_quality_pack.end();
 
+    // User settings - Original page size and orientation
+    let mut preservepagesize_pack = group::Pack::default()
+        .with_size(570, 30)
+        .with_type(group::PackType::Horizontal);
+    preservepagesize_pack.set_spacing(WIDGET_GAP);
+    let mut preservepagesize_checkbutton = button::CheckButton::default()
+        .with_size(300, 20)
+        .with_label(&trans.gettext("Keep the original page size and orientation"));
+    preservepagesize_checkbutton.set_tooltip(&trans.gettext("Pages are rendered at the resolution of the visual quality, instead of being scaled to fit an A4 page"));
+
+    if appconfig.preserve_page_size.unwrap_or(false) {
+        preservepagesize_checkbutton.set_checked(true);
+    }
+
+    preservepagesize_pack.end();
+
+    // User settings - Encoding of the page images in the PDF result
+    let mut imageencoding_pack = group::Pack::default()
+        .with_size(570, 40)
+        .with_type(group::PackType::Horizontal);
+    imageencoding_pack.set_spacing(WIDGET_GAP);
+    let mut imageencoding_checkbutton = button::CheckButton::default()
+        .with_size(100, 40)
+        .with_label(&trans.gettext("Custom page image encoding"))
+        .with_align(enums::Align::Left | enums::Align::Inside);
+    imageencoding_checkbutton.set_tooltip(&trans.gettext("JPEG produces smaller files for photos and scans, bilevel for text documents"));
+    let imageencoding_menuchoice_rc = Rc::new(RefCell::new(
+        menu::Choice::default().with_size(240, 40),
+    ));
+
+    for item in common::IMAGE_ENCODING_CHOICES.iter() {
+        let item_translated = trans.gettext(item);
+        imageencoding_menuchoice_rc.borrow_mut().add_choice(&item_translated);
+    }
+
+    let image_encoding_idx = {
+        let mut ret = common::IMAGE_ENCODING_CHOICE_DEFAULT_INDEX as i32;
+
+        if let Some(v) = &appconfig.image_encoding {
+            for (idx, item) in common::IMAGE_ENCODING_CHOICES.iter().enumerate() {
+                if item == v {
+                    ret = idx as i32;
+                    break;
+                }
+            }
+        }
+
+        ret
+    };
+
+    imageencoding_menuchoice_rc.borrow_mut().set_value(image_encoding_idx);
+
+    if image_encoding_idx == common::IMAGE_ENCODING_CHOICE_DEFAULT_INDEX as i32 {
+        imageencoding_menuchoice_rc.borrow_mut().deactivate();
+    } else {
+        imageencoding_checkbutton.set_checked(true);
+    }
+
+    imageencoding_checkbutton.set_callback({
+        let imageencoding_menuchoice_rc_ref = imageencoding_menuchoice_rc.clone();
+
+        move |wid| {
+            if !wid.is_checked() {
+                imageencoding_menuchoice_rc_ref.borrow_mut().deactivate();
+                imageencoding_menuchoice_rc_ref.borrow_mut().set_value(common::IMAGE_ENCODING_CHOICE_DEFAULT_INDEX as i32);
+            } else {
+                imageencoding_menuchoice_rc_ref.borrow_mut().activate();
+            }
+        }
+    });
+
+    imageencoding_pack.end();
+
+    // User settings - PDF/A archival output
+    let mut pdfa_pack = group::Pack::default()
+        .with_size(570, 30)
+        .with_type(group::PackType::Horizontal);
+    pdfa_pack.set_spacing(WIDGET_GAP);
+    let mut pdfa_checkbutton = button::CheckButton::default()
+        .with_size(300, 20)
+        .with_label(&trans.gettext("Produce PDF/A documents"));
+    pdfa_checkbutton.set_tooltip(&trans.gettext("PDF/A-2b documents are meant for long-term archiving"));
+
+    if appconfig.pdfa.unwrap_or(false) {
+        pdfa_checkbutton.set_checked(true);
+    }
+
+    pdfa_pack.end();
+
+    // User settings - Document properties carry-over
+    let mut stripmetadata_pack = group::Pack::default()
+        .with_size(570, 30)
+        .with_type(group::PackType::Horizontal);
+    stripmetadata_pack.set_spacing(WIDGET_GAP);
+    let mut stripmetadata_checkbutton = button::CheckButton::default()
+        .with_size(300, 20)
+        .with_label(&trans.gettext("Remove document properties"));
+    stripmetadata_checkbutton.set_tooltip(&trans.gettext("Do not carry over the document title, author, subject and creation date"));
+
+    if appconfig.strip_metadata.unwrap_or(false) {
+        stripmetadata_checkbutton.set_checked(true);
+    }
+
+    stripmetadata_pack.end();
+
+    // User settings - Original text layer
+    let mut originaltext_pack = group::Pack::default()
+        .with_size(570, 30)
+        .with_type(group::PackType::Horizontal);
+    originaltext_pack.set_spacing(WIDGET_GAP);
+    let mut originaltext_checkbutton = button::CheckButton::default()
+        .with_size(300, 20)
+        .with_label(&trans.gettext("Keep the original text when available"));
+    originaltext_checkbutton.set_tooltip(&trans.gettext("Faster and more accurate than OCR for digital documents, OCR is still used for pages without text"));
+
+    if appconfig.text_layer.as_deref() == Some(common::TEXT_LAYER_CHOICES[1]) {
+        originaltext_checkbutton.set_checked(true);
+    }
+
+    originaltext_pack.end();
+
+    // User settings - Page selection, this is not saved as it depends on the documents at hand
+    let mut pageranges_pack = group::Pack::default()
+        .with_size(570, 40)
+        .with_type(group::PackType::Horizontal);
+    pageranges_pack.set_spacing(WIDGET_GAP);
+    let mut pageranges_checkbutton = button::CheckButton::default()
+        .with_size(160, 20)
+        .with_label(&trans.gettext("Custom page selection"));
+    pageranges_checkbutton.set_tooltip(&trans.gettext("Optional selection of pages to sanitize (i.e. '1-5,9,12-')"));
+
+    let pageranges_input_rc = Rc::new(RefCell::new(input::Input::default().with_size(290, 20)));
+    pageranges_input_rc.borrow_mut().deactivate();
+
+    pageranges_checkbutton.set_callback({
+        let pageranges_input_rc_ref = pageranges_input_rc.clone();
+
+        move|b| {
+            if b.is_checked() {
+                pageranges_input_rc_ref.borrow_mut().activate();
+            } else {
+                pageranges_input_rc_ref.borrow_mut().set_value("");
+                pageranges_input_rc_ref.borrow_mut().deactivate();
+            }
+        }
+    });
+
+    pageranges_pack.end();
+
     // User settings - OCR
     let mut ocrlang_pack = group::Pack::default()
         .with_size(570, 60)
@@ -1695,6 +1857,14 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     savesettings_button.set_callback({
         let result_visual_quality_menuchoice_rc_ref = result_visual_quality_menuchoice_rc.clone();
+        let resource_limits_ref = resource_limits.clone();
+        let preservepagesize_checkbutton_ref = preservepagesize_checkbutton.clone();
+        let imageencoding_menuchoice_rc_ref = imageencoding_menuchoice_rc.clone();
+        let jpeg_quality = appconfig.jpeg_quality;
+        let ocr_sidecars = appconfig.ocr_sidecars.clone();
+        let pdfa_checkbutton_ref = pdfa_checkbutton.clone();
+        let stripmetadata_checkbutton_ref = stripmetadata_checkbutton.clone();
+        let originaltext_checkbutton_ref = originaltext_checkbutton.clone();
         let ocrlang_checkbutton_ref = ocrlang_checkbutton.clone();
         let ocrlang_holdbrowser_rc_ref = ocrlang_holdbrowser_rc.clone();
         let filesuffix_input_rc_ref = filesuffix_input_rc.clone();
@@ -1756,6 +1926,36 @@ fn main() -> Result<(), Box<dyn Error>> {
                 new_appconfig.openwith_appname = openwith_inputchoice_rc_ref.borrow().value();
             }
 
+            if preservepagesize_checkbutton_ref.is_checked() {
+                new_appconfig.preserve_page_size = Some(true);
+            }
+
+            let image_encoding_idx = imageencoding_menuchoice_rc_ref.borrow().value();
+
+            if image_encoding_idx != common::IMAGE_ENCODING_CHOICE_DEFAULT_INDEX as i32 {
+                new_appconfig.image_encoding = Some(common::IMAGE_ENCODING_CHOICES[image_encoding_idx as usize].to_string());
+            }
+
+            new_appconfig.jpeg_quality = jpeg_quality;
+            new_appconfig.ocr_sidecars = ocr_sidecars.clone();
+
+            if pdfa_checkbutton_ref.is_checked() {
+                new_appconfig.pdfa = Some(true);
+            }
+
+            if stripmetadata_checkbutton_ref.is_checked() {
+                new_appconfig.strip_metadata = Some(true);
+            }
+
+            if originaltext_checkbutton_ref.is_checked() {
+                new_appconfig.text_layer = Some(common::TEXT_LAYER_CHOICES[1].to_string());
+            }
+
+            new_appconfig.max_input_size = resource_limits_ref.opt_max_input_size;
+            new_appconfig.max_pages = resource_limits_ref.opt_max_pages;
+            new_appconfig.max_page_area = resource_limits_ref.opt_max_page_area;
+            new_appconfig.max_total_pixels = resource_limits_ref.opt_max_total_pixels;
+
             if let Err(ex) = config::save_config(new_appconfig) {
                 let err_text = ex.to_string();
                 dialog::alert(wind_ref.x(), wind_ref.y() + wind_ref.height() / 2, &err_text);
@@ -2014,6 +2214,15 @@ fn main() -> Result<(), Box<dyn Error>> {
         let mut overall_progress_progressbar_ref  = overall_progress_progressbar.clone();
         let openwith_checkbutton_ref = openwith_checkbutton.clone();
         let pdf_viewer_list_ref = openwith_inputchoice_rc;
+        let pageranges_checkbutton_ref = pageranges_checkbutton.clone();
+        let pageranges_input_rc_ref = pageranges_input_rc.clone();
+        let preservepagesize_checkbutton_ref = preservepagesize_checkbutton.clone();
+        let imageencoding_menuchoice_rc_ref = imageencoding_menuchoice_rc.clone();
+        let jpeg_quality = appconfig.jpeg_quality;
+        let ocr_sidecars = appconfig.ocr_sidecars.clone();
+        let pdfa_checkbutton_ref = pdfa_checkbutton.clone();
+        let stripmetadata_checkbutton_ref = stripmetadata_checkbutton.clone();
+        let originaltext_checkbutton_ref = originaltext_checkbutton.clone();
 
         move |b| {
             b.deactivate();
@@ -2030,6 +2239,20 @@ fn main() -> Result<(), Box<dyn Error>> {
 
             let image_quality_value_index = result_visual_quality_menuchoice_rc.borrow().value();
             let image_quality = common::IMAGE_QUALITY_CHOICES[image_quality_value_index as usize].to_lowercase();
+            let preserve_page_size = preservepagesize_checkbutton_ref.is_checked();
+            let pdfa = pdfa_checkbutton_ref.is_checked();
+            let strip_metadata = stripmetadata_checkbutton_ref.is_checked();
+            let opt_text_layer = if originaltext_checkbutton_ref.is_checked() {
+                Some(common::TEXT_LAYER_CHOICES[1].to_string())
+            } else {
+                None
+            };
+            let image_encoding_value_index = imageencoding_menuchoice_rc_ref.borrow().value();
+            let opt_image_encoding = if image_encoding_value_index != common::IMAGE_ENCODING_CHOICE_DEFAULT_INDEX as i32 {
+                Some(common::IMAGE_ENCODING_CHOICES[image_encoding_value_index as usize].to_string())
+            } else {
+                None
+            };
 
             let opt_viewer_app = if openwith_checkbutton_ref.is_checked() {
                 let viewer_app_name = pdf_viewer_list_ref.borrow_mut().input().value();
@@ -2055,6 +2278,18 @@ fn main() -> Result<(), Box<dyn Error>> {
                 None
             };
 
+            let opt_page_ranges = if pageranges_checkbutton_ref.is_checked() {
+                let page_ranges_text = pageranges_input_rc_ref.borrow().value();
+
+                if page_ranges_text.trim().is_empty() {
+                    None
+                } else {
+                    Some(page_ranges_text.trim().to_owned())
+                }
+            } else {
+                None
+            };
+
             let oci_image_text = ociimage_input_rc_ref.borrow().value();
             let opt_oci_image  = if oci_image_text.trim().is_empty() {
                 config::default_container_image_name()
@@ -2074,13 +2309,23 @@ fn main() -> Result<(), Box<dyn Error>> {
             };
 
             let tasks: Vec<ConversionTask> = filelist_widget_ref.rows.borrow().iter().map(|row| {                
-                row_to_task(&opt_oci_image,
-                            image_quality.clone(),
-                            &opt_ocr_lang,
-                            &file_suffix,
-                            !seccomp_disabled,
-                            row
-                )
+                let mut task = row_to_task(&opt_oci_image,
+                                           image_quality.clone(),
+                                           preserve_page_size,
+                                           &opt_ocr_lang,
+                                           &opt_page_ranges,
+                                           &file_suffix,
+                                           !seccomp_disabled,
+                                           row
+                );
+                task.options.resource_limits = resource_limits.clone();
+                task.options.opt_image_encoding = opt_image_encoding.clone();
+                task.options.opt_jpeg_quality = jpeg_quality;
+                task.options.pdfa = pdfa;
+                task.options.strip_metadata = strip_metadata;
+                task.options.opt_text_layer = opt_text_layer.clone();
+                task.options.ocr_sidecars = ocr_sidecars.clone().unwrap_or_default();
+                task
             }).collect();
 
             filelist_widget_ref.deactivate_controls();
@@ -2528,6 +2773,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         let mut result_visual_quality_pack_ref = result_visual_quality_pack.clone();
         let mut result_visual_quality_checkbutton_ref = result_visual_quality_checkbutton.clone();
 
+        let mut imageencoding_pack_ref = imageencoding_pack.clone();
+        let mut imageencoding_checkbutton_ref = imageencoding_checkbutton.clone();
+
         let mut ocrlang_pack_ref = ocrlang_pack.clone();
         let mut ocrlang_checkbutton_ref = ocrlang_checkbutton.clone();
 
@@ -2670,6 +2918,20 @@ fn main() -> Result<(), Box<dyn Error>> {
                     result_visual_quality_checkbutton_ref.h()
                 );
 
+                imageencoding_pack_ref.resize(
+                    imageencoding_pack_ref.x(),
+                    imageencoding_pack_ref.y(),
+                    wid.w() - (WIDGET_GAP * 2),
+                    imageencoding_pack_ref.h()
+                );
+
+                imageencoding_checkbutton_ref.resize(
+                    imageencoding_checkbutton_ref.x(),
+                    imageencoding_checkbutton_ref.y(),
+                    ocrlang_checkbutton.w(),
+                    imageencoding_checkbutton_ref.h()
+                );
+
                 let xx = ocrlang_holdbrowser_rc.borrow_mut().x();
 
                 ociimage_pack_ref.resize(
@@ -2866,9 +3128,10 @@ fn main() -> Result<(), Box<dyn Error>> {
                     let log_msg_ret: serde_json::Result<common::LogMessage> = serde_json::from_slice(msg.as_bytes());
 
                     if let Ok(log_msg) = log_msg_ret {
-                        messages_frame.set_label(&clip_text(&log_msg.data, messages_frame.w()));
+                        let log_text = log_msg.display_text();
+                        messages_frame.set_label(&clip_text(&log_text, messages_frame.w()));
                         let row_idx = current_row_idx.load(Ordering::Relaxed) as usize;
-                        filelist_widget.update_progress(row_idx, log_msg.data, log_msg.percent_complete);
+                        filelist_widget.update_progress(row_idx, log_text, log_msg.percent_complete);
                     }
 
                     app::awake();
@@ -3,22 +3,37 @@ Feature: Default test data
   Scenario: When we convert default test files
     Given a set of files to convert
       | filename         |
+      | sample-bmp.bmp   |
+      | sample-csv.csv   |
       | sample-doc.doc   |
       | sample-docx.docx |
+      | sample-eml.eml   |
+      | sample-epub.epub |
+      | sample-fb2.fb2   |
       | sample-gif.gif   |
+      | sample-html.html |
+      | sample-ico.ico   |
       | sample-jpeg.jpeg |
       | sample-jpg.jpg   |
+      | sample-md.md     |
+      | sample-msg.msg   |
       | sample-odg.odg   |
       | sample-odp.odp   |
       | sample-ods.ods   |
       | sample-odt.odt   |
+      | sample-pbm.pbm   |
       | sample-pdf.pdf   |
       | sample-png.png   |
+      | sample-ppm.ppm   |
       | sample-ppt.ppt   |
       | sample-pptx.pptx |
+      | sample-tar.tar   |
       | sample-tiff.tiff |
+      | sample-txt.txt   |
+      | sample-webp.webp |
       | sample-xls.xls   |
       | sample-xlsx.xlsx |
+      | sample-zip.zip   |
 
     When files are converted
     Then the conversion is successful
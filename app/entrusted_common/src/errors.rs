@@ -0,0 +1,100 @@
+// Error codes are part of the JSON log stream and of the process exit status, they must not be renamed nor renumbered
+// They're reported by entrusted-container, entrusted-client exits with the same codes and entrusted-webserver maps them to HTTP statuses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    ConversionFailed,
+    InvalidOption,
+    InputNotFound,
+    UnsupportedMime,
+    PolyglotInput,
+    WrongPassword,
+    ResourceLimit,
+    LibreofficeExportFailed,
+    OcrInitFailed,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 9] = [
+        ErrorCode::ConversionFailed,
+        ErrorCode::InvalidOption,
+        ErrorCode::InputNotFound,
+        ErrorCode::UnsupportedMime,
+        ErrorCode::PolyglotInput,
+        ErrorCode::WrongPassword,
+        ErrorCode::ResourceLimit,
+        ErrorCode::LibreofficeExportFailed,
+        ErrorCode::OcrInitFailed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::ConversionFailed        => "CONVERSION_FAILED",
+            ErrorCode::InvalidOption           => "INVALID_OPTION",
+            ErrorCode::InputNotFound           => "INPUT_NOT_FOUND",
+            ErrorCode::UnsupportedMime         => "UNSUPPORTED_MIME",
+            ErrorCode::PolyglotInput           => "POLYGLOT_INPUT",
+            ErrorCode::WrongPassword           => "WRONG_PASSWORD",
+            ErrorCode::ResourceLimit           => "RESOURCE_LIMIT",
+            ErrorCode::LibreofficeExportFailed => "LIBREOFFICE_EXPORT_FAILED",
+            ErrorCode::OcrInitFailed           => "OCR_INIT_FAILED",
+        }
+    }
+
+    // Exit code 2 is left to command-line parsing errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorCode::ConversionFailed        => 1,
+            ErrorCode::InvalidOption           => 3,
+            ErrorCode::InputNotFound           => 4,
+            ErrorCode::UnsupportedMime         => 5,
+            ErrorCode::PolyglotInput           => 6,
+            ErrorCode::WrongPassword           => 7,
+            ErrorCode::ResourceLimit           => 8,
+            ErrorCode::LibreofficeExportFailed => 9,
+            ErrorCode::OcrInitFailed           => 10,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|code| code.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adding a variant fails to compile here until it's listed in ErrorCode::ALL as well
+    fn variant_index(code: ErrorCode) -> usize {
+        match code {
+            ErrorCode::ConversionFailed        => 0,
+            ErrorCode::InvalidOption           => 1,
+            ErrorCode::InputNotFound           => 2,
+            ErrorCode::UnsupportedMime         => 3,
+            ErrorCode::PolyglotInput           => 4,
+            ErrorCode::WrongPassword           => 5,
+            ErrorCode::ResourceLimit           => 6,
+            ErrorCode::LibreofficeExportFailed => 7,
+            ErrorCode::OcrInitFailed           => 8,
+        }
+    }
+
+    #[test]
+    fn test_all_codes_are_mapped() {
+        let indices: Vec<usize> = ErrorCode::ALL.iter().map(|code| variant_index(*code)).collect();
+        assert_eq!(indices, (0..ErrorCode::ALL.len()).collect::<Vec<usize>>());
+
+        for (idx, code) in ErrorCode::ALL.iter().enumerate() {
+            assert_eq!(ErrorCode::from_name(code.name()), Some(*code));
+            assert_ne!(code.exit_code(), 0);
+            assert_ne!(code.exit_code(), 2);
+
+            for other in ErrorCode::ALL.iter().skip(idx + 1) {
+                assert_ne!(code.name(), other.name());
+                assert_ne!(code.exit_code(), other.exit_code());
+            }
+        }
+
+        assert_eq!(ErrorCode::from_name("UNKNOWN"), None);
+    }
+}
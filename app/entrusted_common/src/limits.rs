@@ -0,0 +1,30 @@
+// Resource limits of the container, shared by entrusted-client and entrusted-webserver
+// Unset limits are not passed to the container, which then applies its own defaults
+#[derive(Clone, Debug, Default)]
+pub struct ResourceLimits {
+    pub opt_max_input_size: Option<u64>,
+    pub opt_max_pages: Option<u64>,
+    pub opt_max_page_area: Option<u64>,
+    pub opt_max_total_pixels: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn to_args(&self) -> Vec<String> {
+        let mut ret = Vec::new();
+        let limit_args = [
+            ("--max-input-size", self.opt_max_input_size),
+            ("--max-pages", self.opt_max_pages),
+            ("--max-page-area", self.opt_max_page_area),
+            ("--max-total-pixels", self.opt_max_total_pixels),
+        ];
+
+        for (arg_name, opt_arg_value) in limit_args {
+            if let Some(arg_value) = opt_arg_value {
+                ret.push(arg_name.to_string());
+                ret.push(arg_value.to_string());
+            }
+        }
+
+        ret
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lopdf::{Dictionary, Object};
+use serde::Serialize;
+
+use entrusted_l10n as l10n;
+use crate::embedded;
+use crate::metadata;
+use crate::mimetypes;
+
+// The report is saved next to the PDF result, i.e. 'document.analysis.json' for 'document.pdf'
+pub const ANALYSIS_REPORT_EXTENSION: &str = "analysis.json";
+
+// Document parts are only scanned up to this size, so that compressed parts can't exhaust the memory
+const MAX_SCANNED_PART_SIZE: u64 = 16 * 1024 * 1024;
+
+// Repeated findings (i.e. hundreds of links) are counted, only the first details are listed
+const MAX_FINDING_DETAILS: usize = 50;
+
+// Nested PDF objects are inspected up to this depth, indirect objects are inspected on their own
+const MAX_OBJECT_DEPTH: usize = 32;
+
+// Field codes only need a few characters to be recognized
+const FIELD_CODE_PREFIX_LEN: usize = 64;
+
+const CFB_MACRO_STORAGES: [&str; 3] = ["Macros", "_VBA_PROJECT_CUR", "VBA"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl RiskLevel {
+    pub fn display_name(&self, l10n: l10n::Translations) -> String {
+        match self {
+            RiskLevel::None   => l10n.gettext("none"),
+            RiskLevel::Low    => l10n.gettext("low"),
+            RiskLevel::Medium => l10n.gettext("medium"),
+            RiskLevel::High   => l10n.gettext("high"),
+        }
+    }
+}
+
+// Kinds are serialized with stable names, descriptions are translated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FindingKind {
+    PdfJavascript,
+    PdfLaunchAction,
+    PdfOpenAction,
+    PdfAdditionalActions,
+    PdfRemoteAction,
+    PdfXfaForm,
+    PdfEmbeddedFile,
+    OfficeMacros,
+    OfficeActivex,
+    OfficeDdeField,
+    OfficeExternalTemplate,
+    OfficeExternalReference,
+    OleObject,
+    ExternalUri,
+    EncryptedContent,
+}
+
+impl FindingKind {
+    pub fn risk_level(&self) -> RiskLevel {
+        match self {
+            FindingKind::PdfJavascript
+                | FindingKind::PdfLaunchAction
+                | FindingKind::OfficeMacros
+                | FindingKind::OfficeActivex
+                | FindingKind::OfficeDdeField
+                | FindingKind::OfficeExternalTemplate => RiskLevel::High,
+            FindingKind::PdfOpenAction
+                | FindingKind::PdfAdditionalActions
+                | FindingKind::PdfRemoteAction
+                | FindingKind::PdfXfaForm
+                | FindingKind::PdfEmbeddedFile
+                | FindingKind::OfficeExternalReference
+                | FindingKind::OleObject => RiskLevel::Medium,
+            FindingKind::ExternalUri
+                | FindingKind::EncryptedContent => RiskLevel::Low,
+        }
+    }
+
+    pub fn description(&self, l10n: l10n::Translations) -> String {
+        match self {
+            FindingKind::PdfJavascript           => l10n.gettext("JavaScript code"),
+            FindingKind::PdfLaunchAction         => l10n.gettext("Actions launching external programs or files"),
+            FindingKind::PdfOpenAction           => l10n.gettext("Action performed when opening the document"),
+            FindingKind::PdfAdditionalActions    => l10n.gettext("Actions triggered by document, page or form events"),
+            FindingKind::PdfRemoteAction         => l10n.gettext("Actions opening, submitting or importing remote data"),
+            FindingKind::PdfXfaForm              => l10n.gettext("XFA form"),
+            FindingKind::PdfEmbeddedFile         => l10n.gettext("Embedded files"),
+            FindingKind::OfficeMacros            => l10n.gettext("Macros"),
+            FindingKind::OfficeActivex           => l10n.gettext("ActiveX controls"),
+            FindingKind::OfficeDdeField          => l10n.gettext("DDE fields or links"),
+            FindingKind::OfficeExternalTemplate  => l10n.gettext("External template references"),
+            FindingKind::OfficeExternalReference => l10n.gettext("External resources loaded by the document"),
+            FindingKind::OleObject               => l10n.gettext("OLE objects"),
+            FindingKind::ExternalUri             => l10n.gettext("External links"),
+            FindingKind::EncryptedContent        => l10n.gettext("Encrypted content that can't be fully analyzed"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub risk_level: RiskLevel,
+    pub description: String,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>, // i.e. file names or URIs, without control characters
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AnalysisReport {
+    pub mime_type: Option<String>,
+    pub risk_level: RiskLevel,
+    pub findings: Vec<Finding>,
+}
+
+impl AnalysisReport {
+    // Sidecar file named after the PDF result
+    pub fn save(&self, pdf_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let dest_path = pdf_path.with_extension(ANALYSIS_REPORT_EXTENSION);
+        fs::write(&dest_path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(dest_path)
+    }
+}
+
+// Findings are grouped by kind, details are kept in the order of their first occurrence
+#[derive(Default)]
+struct Findings {
+    items: BTreeMap<FindingKind, (usize, Vec<String>)>,
+}
+
+impl Findings {
+    fn add(&mut self, kind: FindingKind, opt_detail: Option<String>) {
+        let (count, details) = self.items.entry(kind).or_default();
+        *count += 1;
+
+        if let Some(detail) = opt_detail.as_deref().and_then(metadata::sanitize_text) {
+            if details.len() < MAX_FINDING_DETAILS && !details.contains(&detail) {
+                details.push(detail);
+            }
+        }
+    }
+
+    fn into_report(self, mime_type: Option<String>, l10n: l10n::Translations) -> AnalysisReport {
+        let mut findings: Vec<Finding> = self.items
+            .into_iter()
+            .map(|(kind, (count, details))| Finding { kind, risk_level: kind.risk_level(), description: kind.description(l10n.clone()), count, details })
+            .collect();
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.risk_level));
+
+        let risk_level = findings.first().map(|finding| finding.risk_level).unwrap_or(RiskLevel::None);
+
+        AnalysisReport { mime_type, risk_level, findings }
+    }
+}
+
+// Active content and external references of the original document, before its conversion
+// Embedded files are reported by name, their own content isn't analyzed
+pub fn analyze(path: &Path, opt_file_ext: Option<&str>, l10n: l10n::Translations) -> Result<AnalysisReport, Box<dyn Error>> {
+    let opt_mime_type = mimetypes::detect(path, opt_file_ext, l10n.clone())?.map(|detection| detection.mime_type);
+    let mut findings = Findings::default();
+
+    match opt_mime_type {
+        Some("application/pdf") => analyze_pdf(path, &mut findings)?,
+        Some("application/rtf") => analyze_rtf(path, &mut findings)?,
+        Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+            | Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+            | Some("application/vnd.openxmlformats-officedocument.presentationml.presentation") => analyze_ooxml(path, &mut findings)?,
+        Some("application/vnd.oasis.opendocument.text")
+            | Some("application/vnd.oasis.opendocument.spreadsheet")
+            | Some("application/vnd.oasis.opendocument.presentation")
+            | Some("application/vnd.oasis.opendocument.graphics") => analyze_opendocument(path, &mut findings)?,
+        Some("application/msword") | Some("application/vnd.ms-excel") | Some("application/vnd.ms-powerpoint") => analyze_cfb(path, &mut findings)?,
+        // Password-protected OOXML documents are compound files without any known class identifier
+        None if cfb::open(path).is_ok() => analyze_cfb(path, &mut findings)?,
+        _ => {}
+    }
+
+    Ok(findings.into_report(opt_mime_type.map(|v| v.to_string()), l10n))
+}
+
+// Every object is inspected, whether it's reachable from the document catalog or not
+// Strings of encrypted documents can't be decrypted, only the structure is reported for them
+fn analyze_pdf(path: &Path, findings: &mut Findings) -> Result<(), Box<dyn Error>> {
+    let doc = lopdf::Document::load(path)?;
+    let encrypted = doc.trailer.has(b"Encrypt");
+
+    if encrypted {
+        findings.add(FindingKind::EncryptedContent, None);
+    }
+
+    for object in doc.objects.values() {
+        inspect_pdf_object(&doc, object, encrypted, 0, findings);
+    }
+
+    Ok(())
+}
+
+fn inspect_pdf_object(doc: &lopdf::Document, object: &Object, encrypted: bool, depth: usize, findings: &mut Findings) {
+    if depth > MAX_OBJECT_DEPTH {
+        return;
+    }
+
+    match object {
+        Object::Dictionary(dict) => inspect_pdf_dict(doc, dict, encrypted, depth, findings),
+        Object::Stream(stream) => inspect_pdf_dict(doc, &stream.dict, encrypted, depth, findings),
+        Object::Array(items) => {
+            for item in items {
+                inspect_pdf_object(doc, item, encrypted, depth + 1, findings);
+            }
+        },
+        _ => {}
+    }
+}
+
+fn inspect_pdf_dict(doc: &lopdf::Document, dict: &Dictionary, encrypted: bool, depth: usize, findings: &mut Findings) {
+    let text_value = |key: &[u8]| -> Option<String> {
+        if encrypted {
+            return None;
+        }
+
+        match dict.get(key).and_then(|v| doc.dereference(v)).map(|(_, v)| v) {
+            Ok(Object::String(data, _)) => Some(embedded::pdf_text_string(data)),
+            Ok(Object::Dictionary(filespec)) => filespec
+                .get(b"UF")
+                .or_else(|_| filespec.get(b"F"))
+                .and_then(Object::as_str)
+                .ok()
+                .map(embedded::pdf_text_string),
+            _ => None
+        }
+    };
+
+    // Document level scripts are found in the JavaScript name tree, the other ones in actions
+    if dict.has(b"JS") || dict.has(b"JavaScript") {
+        findings.add(FindingKind::PdfJavascript, None);
+    }
+
+    // Opening the document at a given page is harmless, any other action is reported
+    if let Ok((_, Object::Dictionary(action))) = dict.get(b"OpenAction").and_then(|v| doc.dereference(v)) {
+        if action.get(b"S").and_then(Object::as_name).ok() != Some(b"GoTo".as_slice()) {
+            findings.add(FindingKind::PdfOpenAction, None);
+        }
+    }
+
+    if dict.has(b"AA") {
+        findings.add(FindingKind::PdfAdditionalActions, None);
+    }
+
+    if dict.has(b"XFA") {
+        findings.add(FindingKind::PdfXfaForm, None);
+    }
+
+    // File specifications refer to the embedded file streams
+    if dict.has(b"EF") {
+        findings.add(FindingKind::PdfEmbeddedFile, text_value(b"UF").or_else(|| text_value(b"F")));
+    }
+
+    match dict.get(b"S").and_then(Object::as_name) {
+        Ok(b"Launch") => {
+            let opt_target = text_value(b"F").or_else(|| {
+                dict.get(b"Win").and_then(Object::as_dict).ok().and_then(|win| {
+                    win.get(b"F").and_then(Object::as_str).ok().filter(|_| !encrypted).map(embedded::pdf_text_string)
+                })
+            });
+            findings.add(FindingKind::PdfLaunchAction, opt_target);
+        },
+        Ok(b"URI") => findings.add(FindingKind::ExternalUri, text_value(b"URI")),
+        Ok(b"GoToR") | Ok(b"GoToE") | Ok(b"SubmitForm") | Ok(b"ImportData") => {
+            findings.add(FindingKind::PdfRemoteAction, text_value(b"F"));
+        },
+        _ => {}
+    }
+
+    for (_, value) in dict.iter() {
+        inspect_pdf_object(doc, value, encrypted, depth + 1, findings);
+    }
+}
+
+// Macros and ActiveX controls are parts of the package, external references are declared in relationships
+fn analyze_ooxml(path: &Path, findings: &mut Findings) -> Result<(), Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(path)?)?;
+
+    for idx in 0..zip.len() {
+        let mut entry = zip.by_index(idx)?;
+        let name = entry.name().to_string();
+        let lowercase_name = name.to_lowercase();
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        if lowercase_name.ends_with("vbaproject.bin") {
+            findings.add(FindingKind::OfficeMacros, Some(name));
+        } else if lowercase_name.contains("/activex/") && lowercase_name.ends_with(".xml") {
+            // Each control has a XML part, binary parts only hold the persisted state of some of them
+            findings.add(FindingKind::OfficeActivex, Some(name));
+        } else if embedded::is_ooxml_embedding(&name) {
+            findings.add(FindingKind::OleObject, Some(name));
+        } else if name == "[Content_Types].xml" {
+            // Macro-enabled documents can still be renamed to '.docx', their content type gives them away
+            if read_part(&mut entry)?.contains("macroEnabled") {
+                findings.add(FindingKind::OfficeMacros, None);
+            }
+        } else if lowercase_name.ends_with(".rels") {
+            inspect_relationships(&read_part(&mut entry)?, findings);
+        } else if name.starts_with("xl/externalLinks/") && name.ends_with(".xml") {
+            if read_part(&mut entry)?.contains("<ddeLink") {
+                findings.add(FindingKind::OfficeDdeField, None);
+            }
+        } else if name.starts_with("word/") && name.ends_with(".xml") {
+            inspect_word_fields(&read_part(&mut entry)?, findings);
+        }
+    }
+
+    Ok(())
+}
+
+// Only external relationships matter, internal ones point to parts of the package
+fn inspect_relationships(xml: &str, findings: &mut Findings) {
+    for element in xml.split("<Relationship ").skip(1) {
+        let element = element.split('>').next().unwrap_or_default();
+
+        if xml_attribute(element, "TargetMode").as_deref() != Some("External") {
+            continue;
+        }
+
+        let rel_type = xml_attribute(element, "Type").unwrap_or_default();
+        let opt_target = xml_attribute(element, "Target");
+
+        if rel_type.ends_with("/hyperlink") {
+            findings.add(FindingKind::ExternalUri, opt_target);
+        } else if rel_type.ends_with("/attachedTemplate") {
+            findings.add(FindingKind::OfficeExternalTemplate, opt_target);
+        } else {
+            findings.add(FindingKind::OfficeExternalReference, opt_target);
+        }
+    }
+}
+
+// Field instructions are either the text of 'instrText' elements or the 'instr' attribute of simple fields
+fn inspect_word_fields(xml: &str, findings: &mut Findings) {
+    let instr_texts = xml
+        .split("<w:instrText")
+        .skip(1)
+        .filter_map(|v| v.split_once('>').map(|(_, text)| text.split('<').next().unwrap_or_default().to_string()));
+    let instr_attributes = xml
+        .split("<w:fldSimple ")
+        .skip(1)
+        .filter_map(|v| xml_attribute(v.split('>').next().unwrap_or_default(), "w:instr"));
+
+    if instr_texts.chain(instr_attributes).any(|instr| is_dde_field(&instr)) {
+        findings.add(FindingKind::OfficeDdeField, None);
+    }
+}
+
+// Basic macros and scripts are stored in dedicated folders, event listeners call them from the content
+fn analyze_opendocument(path: &Path, findings: &mut Findings) -> Result<(), Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(path)?)?;
+
+    for idx in 0..zip.len() {
+        let mut entry = zip.by_index(idx)?;
+        let name = entry.name().to_string();
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        // LibreOffice writes library indexes for documents without any macro
+        if (name.starts_with("Basic/") && !name.ends_with("/script-lc.xml") && !name.ends_with("/script-lb.xml")) || name.starts_with("Scripts/") {
+            findings.add(FindingKind::OfficeMacros, Some(name));
+        } else if embedded::is_opendocument_embedding(&name) {
+            findings.add(FindingKind::OleObject, Some(name));
+        } else if name == "content.xml" || name == "styles.xml" {
+            let xml = read_part(&mut entry)?;
+
+            if xml.contains("<script:event-listener") {
+                findings.add(FindingKind::OfficeMacros, None);
+            }
+
+            if xml.contains("<text:dde-connection-decl") || xml.contains("<table:dde-link") {
+                findings.add(FindingKind::OfficeDdeField, None);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Legacy Office documents keep macros and OLE objects in storages of the compound file
+fn analyze_cfb(path: &Path, findings: &mut Findings) -> Result<(), Box<dyn Error>> {
+    let mut comp = cfb::open(path)?;
+    let entries: Vec<(PathBuf, String, bool)> = comp
+        .walk()
+        .map(|entry| (entry.path().to_path_buf(), entry.name().to_string(), entry.is_storage()))
+        .collect();
+    let mut has_macros = false;
+
+    for (entry_path, name, is_storage) in entries {
+        let parent = entry_path.parent().unwrap_or_else(|| Path::new("/"));
+
+        if name == "EncryptedPackage" && parent == Path::new("/") {
+            findings.add(FindingKind::EncryptedContent, None);
+        } else if is_storage && CFB_MACRO_STORAGES.contains(&name.as_str()) {
+            has_macros = true;
+        } else if is_storage && (parent == Path::new("/ObjectPool") || (parent == Path::new("/") && name.starts_with("MBD"))) {
+            let opt_user_type = embedded::ole_user_type(&mut comp, &entry_path);
+            findings.add(FindingKind::OleObject, Some(opt_user_type.unwrap_or(name)));
+        } else if name == "WordDocument" && parent == Path::new("/") {
+            let mut data = Vec::new();
+            comp.open_stream(&entry_path)?.take(MAX_SCANNED_PART_SIZE).read_to_end(&mut data)?;
+
+            if has_binary_dde_field(&data) {
+                findings.add(FindingKind::OfficeDdeField, None);
+            }
+        }
+    }
+
+    if has_macros {
+        findings.add(FindingKind::OfficeMacros, None);
+    }
+
+    Ok(())
+}
+
+// RTF documents are plain text, control words are searched directly
+fn analyze_rtf(path: &Path, findings: &mut Findings) -> Result<(), Box<dyn Error>> {
+    let mut data = Vec::new();
+    fs::File::open(path)?.take(MAX_SCANNED_PART_SIZE).read_to_end(&mut data)?;
+    let text = String::from_utf8_lossy(&data);
+
+    for (pos, _) in text.match_indices("\\object") {
+        if !text[pos + "\\object".len()..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            findings.add(FindingKind::OleObject, None);
+        }
+    }
+
+    for (pos, _) in text.match_indices("\\fldinst") {
+        let instr: String = text[pos + "\\fldinst".len()..].chars().take(FIELD_CODE_PREFIX_LEN).collect();
+
+        if is_dde_field(instr.trim_start_matches(|c: char| c.is_whitespace() || c == '{' || c == '}')) {
+            findings.add(FindingKind::OfficeDdeField, None);
+        }
+    }
+
+    for (pos, _) in text.match_indices("\\*\\template") {
+        let target = text[pos + "\\*\\template".len()..].split('}').next().unwrap_or_default().trim().to_string();
+        findings.add(FindingKind::OfficeExternalTemplate, Some(target));
+    }
+
+    Ok(())
+}
+
+// 'DDE' and 'DDEAUTO' fields start other applications, field names are case-insensitive
+fn is_dde_field(instr: &str) -> bool {
+    let field_name = instr.trim_start().split(|c: char| !c.is_ascii_alphabetic()).next().unwrap_or_default();
+    field_name.eq_ignore_ascii_case("DDE") || field_name.eq_ignore_ascii_case("DDEAUTO")
+}
+
+// Binary Word documents delimit field codes with the 0x13 character, the text is either 8-bit or UTF-16
+fn has_binary_dde_field(data: &[u8]) -> bool {
+    let field_prefix = |start: usize, step: usize| -> String {
+        data[start..]
+            .iter()
+            .step_by(step)
+            .take(FIELD_CODE_PREFIX_LEN)
+            .map(|b| *b as char)
+            .collect()
+    };
+
+    data.iter().enumerate().any(|(pos, b)| {
+        *b == 0x13 && (is_dde_field(&field_prefix(pos + 1, 1)) || (data.get(pos + 1) == Some(&0) && is_dde_field(&field_prefix(pos + 2, 2))))
+    })
+}
+
+fn read_part<R: Read>(reader: &mut R) -> Result<String, Box<dyn Error>> {
+    let mut data = Vec::new();
+    reader.take(MAX_SCANNED_PART_SIZE).read_to_end(&mut data)?;
+
+    Ok(String::from_utf8_lossy(&data).to_string())
+}
+
+// Attribute values of a start tag, XML entities are only decoded for ampersands
+fn xml_attribute(element: &str, name: &str) -> Option<String> {
+    let attributes = format!(" {}", element);
+
+    ['"', '\''].iter().find_map(|quote| {
+        let prefix = format!(" {}={}", name, quote);
+        let pos = attributes.find(&prefix)?;
+        attributes[pos + prefix.len()..].split(*quote).next().map(|v| v.replace("&amp;", "&"))
+    })
+}
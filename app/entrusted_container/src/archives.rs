@@ -0,0 +1,411 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use entrusted_l10n as l10n;
+use crate::limits::ResourceLimits;
+use crate::metadata;
+use crate::mimetypes;
+
+pub const MIME_TYPE_ZIP: &str  = "application/zip";
+pub const MIME_TYPE_TAR: &str  = "application/x-tar";
+pub const MIME_TYPE_GZIP: &str = "application/gzip";
+
+const TAR_BLOCK_SIZE: usize = 512;
+const TAR_MAGIC_POS: usize  = 257;
+
+// GNU and PAX extended headers only carry entry names here, larger ones are rejected
+const MAX_TAR_EXTENDED_HEADER_SIZE: u64 = 64 * 1024;
+
+// An archive entry, either extracted to its own file or skipped with a reason (i.e. an encrypted entry)
+pub struct ArchiveEntry {
+    pub name: String,
+    pub extracted: Result<PathBuf, String>,
+}
+
+// Only regular files are extracted, directories and links are ignored
+// Entries are written with generated file names, their names are only displayed and never used as paths
+// The expanded size is shared by nested archives, so that archive bombs can't multiply the size limit
+pub fn extract_entries(mime_type: &str, src_path: &Path, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    match mime_type {
+        MIME_TYPE_ZIP  => extract_zip(src_path, dest_dir, limits, expanded_size, l10n),
+        MIME_TYPE_TAR  => extract_tar(BufReader::new(fs::File::open(src_path)?), dest_dir, limits, expanded_size, l10n),
+        MIME_TYPE_GZIP => extract_gzip(src_path, dest_dir, limits, expanded_size, l10n),
+        _              => Err(l10n.gettext_fmt("Unsupported mime type: {0}", vec![mime_type]).into())
+    }
+}
+
+// POSIX and GNU archives have a magic string after the entry name, older archives aren't recognized
+pub fn is_tar(data: &[u8]) -> bool {
+    data.len() >= TAR_BLOCK_SIZE && data[TAR_MAGIC_POS..].starts_with(b"ustar") && tar_checksum_matches(&data[..TAR_BLOCK_SIZE])
+}
+
+fn extract_zip(src_path: &Path, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(src_path)?)?;
+    limits.check_archive_entry_count(zip.len(), l10n.clone())?;
+
+    let mut ret = Vec::new();
+
+    for idx in 0..zip.len() {
+        // Encrypted entries can't be opened, their name is still available from the raw entry
+        let name = match zip.by_index_raw(idx) {
+            Ok(entry) if entry.is_dir() => continue,
+            Ok(entry) => entry_display_name(entry.name(), idx + 1),
+            Err(ex) => return Err(ex.into())
+        };
+
+        match zip.by_index(idx) {
+            Ok(mut entry) => {
+                let compressed_size = entry.compressed_size();
+                let dest_path = entry_dest_path(dest_dir, &name);
+                let max_entry_size = limits.max_expanded_entry_size(compressed_size);
+                let entry_size = write_entry(&mut entry, &dest_path, max_entry_size, limits, expanded_size, l10n.clone())?;
+                limits.check_compression_ratio(&name, compressed_size, entry_size, l10n.clone())?;
+
+                ret.push(ArchiveEntry { name, extracted: Ok(dest_path) });
+            },
+            Err(zip::result::ZipError::UnsupportedArchive(reason)) => {
+                ret.push(ArchiveEntry { name, extracted: Err(reason.to_string()) });
+            },
+            Err(ex) => return Err(ex.into())
+        }
+    }
+
+    Ok(ret)
+}
+
+// Entries are read sequentially, so that compressed TAR archives don't need to be decompressed first
+fn extract_tar<R: Read>(mut reader: R, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let mut ret = Vec::new();
+    let mut header = [0u8; TAR_BLOCK_SIZE];
+    let mut opt_long_name: Option<String> = None;
+    let mut entry_count = 0;
+
+    loop {
+        if !read_tar_block(&mut reader, &mut header)? || header.iter().all(|b| *b == 0) {
+            break;
+        }
+
+        if !tar_checksum_matches(&header) {
+            return Err(l10n.gettext("Invalid TAR archive header").into());
+        }
+
+        let size = match tar_number(&header[124..136]) {
+            Some(v) => v,
+            None => return Err(l10n.gettext("Invalid TAR archive header").into())
+        };
+        let padding = (TAR_BLOCK_SIZE as u64 - size % TAR_BLOCK_SIZE as u64) % TAR_BLOCK_SIZE as u64;
+
+        // Skipped entries still have to be read, they count towards the expanded size as well
+        *expanded_size = expanded_size.saturating_add(size + padding);
+        limits.check_expanded_size(*expanded_size, l10n.clone())?;
+
+        match header[156] {
+            b'L' | b'x' => {
+                if size > MAX_TAR_EXTENDED_HEADER_SIZE {
+                    return Err(l10n.gettext("Invalid TAR archive header").into());
+                }
+
+                let mut data = Vec::new();
+                reader.by_ref().take(size).read_to_end(&mut data)?;
+
+                opt_long_name = if header[156] == b'L' {
+                    Some(String::from_utf8_lossy(&data).trim_end_matches('\0').to_string())
+                } else {
+                    pax_path(&data).or(opt_long_name)
+                };
+            },
+            b'0' | b'7' | 0 => {
+                entry_count += 1;
+                limits.check_archive_entry_count(entry_count, l10n.clone())?;
+
+                let name = entry_display_name(&opt_long_name.take().unwrap_or_else(|| tar_header_name(&header)), entry_count);
+                let dest_path = entry_dest_path(dest_dir, &name);
+                let mut file = fs::File::create(&dest_path)?;
+
+                if io::copy(&mut reader.by_ref().take(size), &mut file)? != size {
+                    return Err(l10n.gettext("The TAR archive is truncated").into());
+                }
+
+                ret.push(ArchiveEntry { name, extracted: Ok(dest_path) });
+            },
+            _ => {
+                opt_long_name = None;
+                io::copy(&mut reader.by_ref().take(size), &mut io::sink())?;
+            }
+        }
+
+        io::copy(&mut reader.by_ref().take(padding), &mut io::sink())?;
+    }
+
+    Ok(ret)
+}
+
+// A compressed file has a single entry, named after the original file name when it's available
+fn extract_gzip(src_path: &Path, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<ArchiveEntry>, Box<dyn Error>> {
+    let compressed_size = fs::metadata(src_path)?.len();
+    let mut decoder = flate2::read::MultiGzDecoder::new(BufReader::new(fs::File::open(src_path)?));
+
+    let tmp_path = dest_dir.join(format!("entry-{}", Uuid::new_v4()));
+    let max_entry_size = limits.max_expanded_entry_size(compressed_size);
+    let entry_size = write_entry(&mut decoder, &tmp_path, max_entry_size, limits, expanded_size, l10n.clone())?;
+
+    let original_name = decoder.header()
+        .and_then(|header| header.filename())
+        .map(|v| String::from_utf8_lossy(v).to_string())
+        .or_else(|| src_path.file_stem().and_then(|v| v.to_str()).map(|v| v.to_string()))
+        .unwrap_or_default();
+    let name = entry_display_name(&original_name, 1);
+    limits.check_compression_ratio(&name, compressed_size, entry_size, l10n)?;
+
+    let dest_path = entry_dest_path(dest_dir, &name);
+    fs::rename(tmp_path, &dest_path)?;
+
+    Ok(vec![ArchiveEntry { name, extracted: Ok(dest_path) }])
+}
+
+// Declared entry sizes can't be trusted, entries are read up to the remaining expanded size
+// Reading one more byte than allowed is enough for the size checks to fail
+pub fn write_entry<R: Read>(reader: &mut R, dest_path: &Path, max_entry_size: u64, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<u64, Box<dyn Error>> {
+    let max_size = limits.max_expanded_size().saturating_sub(*expanded_size).min(max_entry_size);
+    let mut file = fs::File::create(dest_path)?;
+    let entry_size = io::copy(&mut reader.take(max_size.saturating_add(1)), &mut file)?;
+
+    *expanded_size = expanded_size.saturating_add(entry_size);
+    limits.check_expanded_size(*expanded_size, l10n)?;
+
+    Ok(entry_size)
+}
+
+// The original file extension is kept, text formats are recognized from it
+pub fn entry_dest_path(dest_dir: &Path, name: &str) -> PathBuf {
+    match mimetypes::safe_file_extension(name) {
+        Some(ext) => dest_dir.join(format!("entry-{}.{}", Uuid::new_v4(), ext)),
+        None => dest_dir.join(format!("entry-{}", Uuid::new_v4())),
+    }
+}
+
+pub fn entry_display_name(name: &str, entry_num: usize) -> String {
+    metadata::sanitize_text(name.trim_start_matches(['/', '\\']))
+        .unwrap_or_else(|| format!("entry-{}", entry_num))
+}
+
+// Returns false at the end of the input, a partial block is an error
+fn read_tar_block<R: Read>(reader: &mut R, block: &mut [u8; TAR_BLOCK_SIZE]) -> Result<bool, Box<dyn Error>> {
+    let mut len = 0;
+
+    while len < TAR_BLOCK_SIZE {
+        match reader.read(&mut block[len..])? {
+            0 if len == 0 => return Ok(false),
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            n => len += n,
+        }
+    }
+
+    Ok(true)
+}
+
+// The checksum is the sum of the header bytes, with the checksum field itself counted as spaces
+fn tar_checksum_matches(header: &[u8]) -> bool {
+    let expected = match tar_number(&header[148..156]) {
+        Some(v) => v,
+        None => return false
+    };
+
+    let actual: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(idx, b)| if (148..156).contains(&idx) { b' ' as u64 } else { *b as u64 })
+        .sum();
+
+    actual == expected
+}
+
+// Octal numbers padded with spaces or NUL characters, the binary encoding of large numbers isn't supported
+fn tar_number(field: &[u8]) -> Option<u64> {
+    let text = std::str::from_utf8(field).ok()?.trim_matches(|c: char| c == '\0' || c == ' ');
+
+    if text.is_empty() {
+        return Some(0);
+    }
+
+    u64::from_str_radix(text, 8).ok()
+}
+
+// POSIX archives split long names between a prefix and the name fields
+fn tar_header_name(header: &[u8]) -> String {
+    let field_text = |field: &[u8]| -> String {
+        let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..len]).to_string()
+    };
+
+    let name = field_text(&header[0..100]);
+    let prefix = if header[TAR_MAGIC_POS..].starts_with(b"ustar\0") { field_text(&header[345..500]) } else { String::new() };
+
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+// PAX records are formatted as '<length> <key>=<value>\n'
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, key_value)| key_value))
+        .find_map(|key_value| key_value.strip_prefix("path=").map(|v| v.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+
+    use crate::limits::ResourceLimitError;
+
+    use super::*;
+
+    // Names and contents of the extracted entries
+    type ExtractedEntries = Vec<(String, Vec<u8>)>;
+
+    fn test_l10n() -> l10n::Translations {
+        let mut locale_data = HashMap::new();
+        locale_data.insert("en", include_bytes!("../translations/en/LC_MESSAGES/messages.mo").as_slice());
+        l10n::load_translations(locale_data);
+
+        l10n::new_translations("en".to_string())
+    }
+
+    fn test_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("entrusted-archives-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tar_header(name: &str, size_field: &[u8], type_flag: u8) -> Vec<u8> {
+        let mut header = vec![0u8; TAR_BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..124 + size_field.len()].copy_from_slice(size_field);
+        header[156] = type_flag;
+        header[TAR_MAGIC_POS..TAR_MAGIC_POS + 8].copy_from_slice(b"ustar\x0000");
+
+        let checksum: u64 = header.iter().map(|b| *b as u64).sum::<u64>() + 8 * b' ' as u64;
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        header
+    }
+
+    fn tar_entry(name: &str, type_flag: u8, data: &[u8]) -> Vec<u8> {
+        let mut ret = tar_header(name, format!("{:011o}\0", data.len()).as_bytes(), type_flag);
+        ret.extend_from_slice(data);
+        ret.resize(ret.len() + (TAR_BLOCK_SIZE - data.len() % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE, 0);
+        ret
+    }
+
+    fn extract_tar_data(data: &[u8]) -> Result<ExtractedEntries, Box<dyn Error>> {
+        let dir = test_dir();
+        let mut expanded_size = 0;
+        let ret = extract_tar(data, &dir, &ResourceLimits::default(), &mut expanded_size, test_l10n()).map(|entries| {
+            entries.into_iter().map(|entry| (entry.name, fs::read(entry.extracted.unwrap()).unwrap())).collect()
+        });
+
+        fs::remove_dir_all(dir).unwrap();
+        ret
+    }
+
+    #[test]
+    fn test_tar_number() {
+        assert_eq!(tar_number(b"0000644\0"), Some(0o644));
+        assert_eq!(tar_number(b"  17 \0"), Some(0o17));
+        assert_eq!(tar_number(b"       \0"), Some(0));
+        assert_eq!(tar_number(b"\0\0\0\0"), Some(0));
+        assert_eq!(tar_number(b"1777777777777777777777"), Some(u64::MAX));
+
+        assert_eq!(tar_number(b"2000000000000000000000"), None);
+        assert_eq!(tar_number(b"0000008\0"), None);
+        assert_eq!(tar_number(b"-0000001"), None);
+        assert_eq!(tar_number(b"00 0644\0"), None);
+        assert_eq!(tar_number(b"\x80\0\0\0\0\0\0\0\0\0\0\x01"), None);
+        assert_eq!(tar_number(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff"), None);
+    }
+
+    #[test]
+    fn test_tar_checksum_matches() {
+        let mut header = tar_header("file.txt", b"00000000005\0", b'0');
+        assert!(tar_checksum_matches(&header));
+        assert!(is_tar(&header));
+
+        header[0] = b'F';
+        assert!(!tar_checksum_matches(&header));
+
+        let mut header = tar_header("file.txt", b"00000000005\0", b'0');
+        header[148..156].copy_from_slice(b"garbage\0");
+        assert!(!tar_checksum_matches(&header));
+        assert!(!is_tar(&header[..TAR_BLOCK_SIZE - 1]));
+    }
+
+    #[test]
+    fn test_pax_path() {
+        assert_eq!(pax_path(b"30 path=dir/long-name.txt\n"), Some("dir/long-name.txt".to_string()));
+        assert_eq!(pax_path(b"20 mtime=1680000000\n18 path=file.txt\n"), Some("file.txt".to_string()));
+        assert_eq!(pax_path(b"20 mtime=1680000000\n"), None);
+        assert_eq!(pax_path(b"path=file.txt\n"), None);
+        assert_eq!(pax_path(b""), None);
+    }
+
+    #[test]
+    fn test_extract_tar() {
+        let mut data = Vec::new();
+        data.extend(tar_entry("dir/", b'5', b""));
+        data.extend(tar_entry("first.txt", b'0', b"first"));
+        data.extend(tar_entry("././@LongLink", b'L', b"long/name.txt\0"));
+        data.extend(tar_entry("long/na", b'0', b"second"));
+        data.extend(tar_entry("PaxHeaders/third", b'x', b"22 path=pax/third.txt\n"));
+        data.extend(tar_entry("third", b'0', &[b'3'; 600]));
+        data.extend([0u8; 2 * TAR_BLOCK_SIZE]);
+
+        let entries = extract_tar_data(&data).unwrap();
+        assert_eq!(entries, vec![
+            ("first.txt".to_string(), b"first".to_vec()),
+            ("long/name.txt".to_string(), b"second".to_vec()),
+            ("pax/third.txt".to_string(), vec![b'3'; 600]),
+        ]);
+
+        assert!(extract_tar_data(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_extract_tar_invalid_headers() {
+        // Truncated header and truncated entry data
+        let data = tar_entry("file.txt", b'0', b"content");
+        assert!(extract_tar_data(&data[..300]).is_err());
+        assert!(extract_tar_data(&data[..TAR_BLOCK_SIZE + 3]).is_err());
+
+        // Negative or binary sizes, and invalid checksums
+        let samples = [
+            tar_header("file.txt", b"-0000000001\0", b'0'),
+            tar_header("file.txt", b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff", b'0'),
+            tar_header("file.txt", b"\x80\0\0\0\0\0\0\0\0\0\0\x01", b'0'),
+            tar_header("file.txt", b"0000000008\0", b'0'),
+        ];
+
+        for sample in samples {
+            assert!(extract_tar_data(&sample).is_err());
+        }
+
+        let mut data = tar_entry("file.txt", b'0', b"content");
+        data[0] = b'F';
+        assert!(extract_tar_data(&data).is_err());
+
+        // Declared sizes are checked before reading the entry data
+        let data = tar_header("file.txt", b"77777777777\0", b'0');
+        let ex = extract_tar_data(&data).unwrap_err();
+        assert!(ex.is::<ResourceLimitError>(), "{}", ex);
+
+        let data = tar_header("././@LongLink", b"00000200001\0", b'L');
+        assert!(extract_tar_data(&data).is_err());
+    }
+}
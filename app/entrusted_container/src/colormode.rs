@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::path::Path;
+
+use cairo::ImageSurface;
+use image::{GrayImage, Luma};
+
+use crate::imgencoding;
+
+pub const COLOR_MODE_COLOR: &str     = "color";
+pub const COLOR_MODE_GRAYSCALE: &str = "grayscale";
+pub const COLOR_MODE_BW: &str        = "bw";
+pub const COLOR_MODE_CHOICES: [&str; 3] = [COLOR_MODE_COLOR, COLOR_MODE_GRAYSCALE, COLOR_MODE_BW];
+
+// Colors are dropped right after rendering, so that the OCR and PDF conversion steps only deal with gray pixels
+#[derive(Clone, Copy, Debug)]
+pub enum ColorMode {
+    Color,
+    Grayscale,
+    Bw,
+}
+
+impl ColorMode {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            COLOR_MODE_GRAYSCALE => ColorMode::Grayscale,
+            COLOR_MODE_BW        => ColorMode::Bw,
+            _                    => ColorMode::Color
+        }
+    }
+}
+
+// Saves a rendered page as an 8 bit grayscale PNG image, with only black and white pixels in bw mode
+pub fn write_gray_png(surface: &mut ImageSurface, color_mode: ColorMode, dest_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (width, height, stride) = (surface.width() as u32, surface.height() as u32, surface.stride() as usize);
+    let data = surface.data()?;
+    let mut gray_img = GrayImage::new(width, height);
+
+    // Rgb24 pixels are native endian 32 bit values, with the upper 8 bits unused
+    for (x, y, pixel) in gray_img.enumerate_pixels_mut() {
+        let offset = y as usize * stride + x as usize * 4;
+        let rgb = u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        let (r, g, b) = ((rgb >> 16) & 0xff, (rgb >> 8) & 0xff, rgb & 0xff);
+        *pixel = Luma([((r * 2126 + g * 7152 + b * 722) / 10000) as u8]);
+    }
+
+    if let ColorMode::Bw = color_mode {
+        let threshold = imgencoding::otsu_threshold(&gray_img);
+
+        for pixel in gray_img.pixels_mut() {
+            pixel.0[0] = if pixel.0[0] <= threshold { 0 } else { 255 };
+        }
+    }
+
+    gray_img.save_with_format(dest_path, image::ImageFormat::Png)?;
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use entrusted_l10n as l10n;
+use crate::textdocs;
+
+// Hostile e-books can have a very long reading order or highly compressed entries
+const MAX_EPUB_SPINE_ITEMS: usize   = 10000;
+const MAX_EPUB_ENTRY_SIZE: u64      = 64 * 1024 * 1024;
+const MAX_EPUB_TOTAL_SIZE: u64      = 512 * 1024 * 1024;
+
+const EPUB_CONTAINER_PATH: &str = "META-INF/container.xml";
+
+// Each chapter of the reading order starts on a new page
+const HTML_CHAPTER_START: &str = "<div style=\"page-break-before: always\">\n";
+const HTML_CHAPTER_END: &str   = "\n</div>\n";
+
+// LibreOffice can't import EPUB files, their chapters are merged into a single sanitized HTML document instead
+// Chapters are read in the order of the package spine, embedded images and stylesheets are left out
+pub fn write_epub_as_html(src_path: &Path, dest_path: &Path, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(src_path)?)?;
+    let mut total_size: u64 = 0;
+
+    let container = read_epub_entry(&mut zip, EPUB_CONTAINER_PATH, &mut total_size, l10n.clone())?;
+    let package_path = match xml_elements(&container, "rootfile").iter().find_map(|attrs| attrs.get("full-path").cloned()) {
+        Some(v) => v,
+        None => return Err(l10n.gettext("Could not find the EPUB package document").into())
+    };
+
+    let package = read_epub_entry(&mut zip, &package_path, &mut total_size, l10n.clone())?;
+    let manifest: HashMap<String, (String, String)> = xml_elements(&package, "item")
+        .into_iter()
+        .filter_map(|attrs| Some((attrs.get("id")?.clone(), (attrs.get("href")?.clone(), attrs.get("media-type").cloned().unwrap_or_default()))))
+        .collect();
+
+    let mut html = String::from("<html><body>\n");
+
+    for itemref in xml_elements(&package, "itemref").iter().take(MAX_EPUB_SPINE_ITEMS) {
+        let opt_item = itemref.get("idref").and_then(|idref| manifest.get(idref));
+
+        if let Some((href, media_type)) = opt_item {
+            if media_type != "application/xhtml+xml" && media_type != "text/html" {
+                continue;
+            }
+
+            let chapter_path = resolve_href(&package_path, href);
+            let chapter = read_epub_entry(&mut zip, &chapter_path, &mut total_size, l10n.clone())?;
+
+            html.push_str(HTML_CHAPTER_START);
+            html.push_str(&textdocs::sanitize_html(textdocs::html_body(&chapter)));
+            html.push_str(HTML_CHAPTER_END);
+        }
+    }
+
+    html.push_str("</body></html>\n");
+
+    let mut data = vec![0xEF, 0xBB, 0xBF];
+    data.extend(html.into_bytes());
+    fs::write(dest_path, data)?;
+
+    Ok(())
+}
+
+// Uncompressed sizes in the zip headers can't be trusted, entries are read up to the size limits
+fn read_epub_entry(zip: &mut zip::ZipArchive<fs::File>, name: &str, total_size: &mut u64, l10n: l10n::Translations) -> Result<String, Box<dyn Error>> {
+    let entry = match zip.by_name(name) {
+        Ok(v) => v,
+        Err(_) => return Err(l10n.gettext_fmt("Could not find the EPUB entry {0}", vec![name]).into())
+    };
+
+    let max_size = MAX_EPUB_ENTRY_SIZE.min(MAX_EPUB_TOTAL_SIZE.saturating_sub(*total_size));
+    let mut data = Vec::new();
+    entry.take(max_size + 1).read_to_end(&mut data)?;
+
+    if data.len() as u64 > max_size {
+        return Err(l10n.gettext_fmt("The EPUB entry {0} is too large", vec![name]).into());
+    }
+
+    *total_size += data.len() as u64;
+
+    match textdocs::decode_text(&data) {
+        Some(v) => Ok(v),
+        None => Err(l10n.gettext_fmt("Could not decode the text of the EPUB entry {0}", vec![name]).into())
+    }
+}
+
+// Returns the attributes of all the elements with a given local name, regardless of their namespace prefix
+fn xml_elements(xml: &str, local_name: &str) -> Vec<HashMap<String, String>> {
+    let mut ret = Vec::new();
+    let mut rest = xml;
+
+    while let Some(tag_start) = rest.find('<') {
+        rest = &rest[tag_start..];
+
+        let tag_end = match textdocs::html_tag_end(rest) {
+            Some(v) => v,
+            None => break
+        };
+
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        let name_len = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        let name = &tag[..name_len];
+
+        if name.rsplit(':').next() == Some(local_name) {
+            ret.push(textdocs::html_attributes(&tag[name_len..]).into_iter().collect());
+        }
+    }
+
+    ret
+}
+
+// Chapter references are relative to the package document and can be percent-encoded
+fn resolve_href(package_path: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or(href));
+    let mut segments: Vec<&str> = package_path.split('/').collect();
+    segments.pop();
+
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {},
+            ".."     => { segments.pop(); },
+            _        => segments.push(segment),
+        }
+    }
+
+    segments.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        let opt_decoded = if bytes[idx] == b'%' {
+            text.get(idx + 1..idx + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match opt_decoded {
+            Some(v) => {
+                ret.push(v);
+                idx += 3;
+            },
+            None => {
+                ret.push(bytes[idx]);
+                idx += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&ret).into_owned()
+}
@@ -0,0 +1,704 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use encoding_rs::Encoding;
+
+use entrusted_l10n as l10n;
+use crate::metadata;
+use crate::textdocs;
+
+pub const MIME_TYPE_EML: &str = "message/rfc822";
+pub const MIME_TYPE_MSG: &str = "application/vnd.ms-outlook";
+
+// Hostile messages can have deeply nested parts or a very large number of attachments
+const MAX_MIME_DEPTH: usize  = 16;
+const MAX_ATTACHMENTS: usize = 256;
+
+// Recipient lists can be long, they're still displayed on a single line
+const MAX_HEADER_LEN: usize = 4096;
+
+// Only the beginning of the file is inspected to recognize message headers
+const EML_PROBE_SIZE: usize = 16 * 1024;
+
+// Headers that are expected in any message, besides the sender
+const EML_EXPECTED_HEADERS: [&str; 6] = ["date", "subject", "message-id", "mime-version", "received", "to"];
+
+// Outlook message properties, see [MS-OXPROPS]
+const MSG_PROP_SUBJECT: u16              = 0x0037;
+const MSG_PROP_CLIENT_SUBMIT_TIME: u16   = 0x0039;
+const MSG_PROP_TRANSPORT_HEADERS: u16    = 0x007D;
+const MSG_PROP_SENDER_NAME: u16          = 0x0C1A;
+const MSG_PROP_SENDER_EMAIL: u16         = 0x0C1F;
+const MSG_PROP_DISPLAY_CC: u16           = 0x0E03;
+const MSG_PROP_DISPLAY_TO: u16           = 0x0E04;
+const MSG_PROP_BODY: u16                 = 0x1000;
+const MSG_PROP_BODY_HTML: u16            = 0x1013;
+const MSG_PROP_DISPLAY_NAME: u16         = 0x3001;
+const MSG_PROP_ATTACH_DATA: u16          = 0x3701;
+const MSG_PROP_ATTACH_FILENAME: u16      = 0x3704;
+const MSG_PROP_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const MSG_PROP_SENDER_SMTP_ADDRESS: u16  = 0x5D01;
+
+// Outlook property types
+const MSG_TYPE_STRING8: u16 = 0x001E;
+const MSG_TYPE_UNICODE: u16 = 0x001F;
+const MSG_TYPE_SYSTIME: u16 = 0x0040;
+const MSG_TYPE_BINARY: u16  = 0x0102;
+const MSG_TYPE_OBJECT: u16  = 0x000D;
+
+// The fixed-size properties stream starts with a header, that is shorter for embedded messages
+const MSG_PROPERTIES_HEADER_SIZE: usize          = 32;
+const MSG_EMBEDDED_PROPERTIES_HEADER_SIZE: usize = 24;
+const MSG_PROPERTY_ENTRY_SIZE: usize             = 16;
+
+const MSG_ATTACHMENT_STORAGE_PREFIX: &str = "__attach_version1.0_#";
+
+// Seconds between the Windows epoch (1601-01-01) and the Unix epoch
+const WINDOWS_EPOCH_OFFSET_SECS: i64 = 11644473600;
+
+pub enum AttachmentContent {
+    File(Vec<u8>),
+    Message(Box<EmailMessage>), // Outlook messages embed attached messages as storages, not as files
+}
+
+pub struct EmailAttachment {
+    pub name: String,
+    pub content: AttachmentContent,
+}
+
+// Displayed headers, bodies and attachments of a message, other headers are left behind
+#[derive(Default)]
+pub struct EmailMessage {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    pub date: Option<String>,
+    pub subject: Option<String>,
+    text_body: Option<String>,
+    html_body: Option<String>,
+    pub attachments: Vec<EmailAttachment>,
+}
+
+impl EmailMessage {
+    // RFC 822 messages with MIME parts, inline text parts are the body and other parts are attachments
+    pub fn parse_eml(data: &[u8]) -> Self {
+        let (header_data, body) = split_mime_part(data);
+        let headers = parse_headers(header_data);
+
+        let mut ret = Self {
+            from: header_text(&headers, "from"),
+            to: header_text(&headers, "to"),
+            cc: header_text(&headers, "cc"),
+            date: header_text(&headers, "date"),
+            subject: header_text(&headers, "subject"),
+            ..Default::default()
+        };
+
+        ret.read_mime_part(&headers, body, 0);
+        ret
+    }
+
+    fn read_mime_part(&mut self, headers: &[(String, String)], body: &[u8], depth: usize) {
+        let (content_type, content_type_params) = header_with_params(headers, "content-type").unwrap_or_else(|| ("text/plain".to_string(), HashMap::new()));
+        let (disposition, disposition_params) = header_with_params(headers, "content-disposition").unwrap_or_default();
+
+        if content_type.starts_with("multipart/") {
+            if let Some(boundary) = content_type_params.get("boundary") {
+                if depth < MAX_MIME_DEPTH {
+                    for part in split_multipart(body, boundary) {
+                        let (part_header_data, part_body) = split_mime_part(part);
+                        self.read_mime_part(&parse_headers(part_header_data), part_body, depth + 1);
+                    }
+                }
+
+                return;
+            }
+        }
+
+        let opt_name = param_text(&disposition_params, "filename").or_else(|| param_text(&content_type_params, "name"));
+        let data = decode_transfer_encoding(body, header(headers, "content-transfer-encoding"));
+        let is_attachment = disposition == "attachment" || opt_name.is_some();
+
+        if !is_attachment && (content_type == "text/plain" || content_type == "text/html") {
+            let text = decode_charset(&data, content_type_params.get("charset").map(|v| v.as_str()));
+            let body = if content_type == "text/html" { &mut self.html_body } else { &mut self.text_body };
+            body.get_or_insert_with(String::new).push_str(&text);
+        } else if self.attachments.len() < MAX_ATTACHMENTS {
+            let name = attachment_name(opt_name, &content_type, self.attachments.len() + 1);
+            self.attachments.push(EmailAttachment { name, content: AttachmentContent::File(data) });
+        }
+    }
+
+    // Outlook messages are compound files, with a stream per property and a storage per attachment
+    pub fn parse_msg(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut comp = cfb::CompoundFile::open(fs::File::open(path)?)?;
+        Ok(read_msg_storage(&mut comp, "/", MSG_PROPERTIES_HEADER_SIZE, 0))
+    }
+
+    // The headers table is followed by the list of attachments and by the sanitized body
+    pub fn to_html(&self, attachment_statuses: &[String], l10n: l10n::Translations) -> String {
+        let mut html = String::from("<html><body>\n<table>\n");
+
+        let header_rows = [
+            (l10n.gettext("From"), &self.from),
+            (l10n.gettext("To"), &self.to),
+            (l10n.gettext("Cc"), &self.cc),
+            (l10n.gettext("Date"), &self.date),
+            (l10n.gettext("Subject"), &self.subject),
+        ];
+
+        for (label, opt_value) in header_rows {
+            if let Some(value) = opt_value {
+                html.push_str(&format!("<tr><th align=\"left\" valign=\"top\">{}</th><td>{}</td></tr>\n", escape_html(&label), escape_html(value)));
+            }
+        }
+
+        if !self.attachments.is_empty() {
+            let attachment_lines: Vec<String> = self.attachments
+                .iter()
+                .zip(attachment_statuses)
+                .map(|(attachment, status)| format!("{} ({})", escape_html(&attachment.name), escape_html(status)))
+                .collect();
+
+            html.push_str(&format!("<tr><th align=\"left\" valign=\"top\">{}</th><td>{}</td></tr>\n", escape_html(&l10n.gettext("Attachments")), attachment_lines.join("<br>\n")));
+        }
+
+        html.push_str("</table>\n<hr>\n");
+
+        if let Some(html_body) = self.html_body.as_deref() {
+            html.push_str(&textdocs::sanitize_html(textdocs::html_body(html_body)));
+        } else if let Some(text_body) = self.text_body.as_deref() {
+            html.push_str(&escape_html(text_body).replace("\r\n", "\n").replace('\n', "<br>\n"));
+        }
+
+        html.push_str("\n</body></html>\n");
+        html
+    }
+}
+
+// Messages start with a block of headers, optionally after the 'From ' separator line of mailbox files
+pub fn looks_like_eml(data: &[u8]) -> bool {
+    let probe = &data[..data.len().min(EML_PROBE_SIZE)];
+    let mut lines: Vec<&[u8]> = probe.split(|b| *b == b'\n').map(trim_line_end).collect();
+
+    // The last line can be cut in the middle
+    if data.len() > EML_PROBE_SIZE {
+        lines.pop();
+    }
+
+    if lines.first().map(|line| line.starts_with(b"From ")).unwrap_or(false) {
+        lines.remove(0);
+    }
+
+    let mut header_names = Vec::new();
+
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+
+        if line[0] == b' ' || line[0] == b'\t' {
+            continue;
+        }
+
+        match line.iter().position(|b| *b == b':') {
+            Some(idx) if idx > 0 && line[..idx].iter().all(|b| b.is_ascii_graphic()) => {
+                header_names.push(String::from_utf8_lossy(&line[..idx]).to_ascii_lowercase());
+            },
+            _ => return false
+        }
+    }
+
+    header_names.iter().any(|name| name == "from") && header_names.iter().any(|name| EML_EXPECTED_HEADERS.contains(&name.as_str()))
+}
+
+// Message bodies are written as UTF-8 HTML documents for LibreOffice
+pub fn write_message_html(message: &EmailMessage, attachment_statuses: &[String], dest_path: &Path, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    let mut data = vec![0xEF, 0xBB, 0xBF];
+    data.extend(message.to_html(attachment_statuses, l10n).into_bytes());
+    fs::write(dest_path, data)?;
+
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// Splits the headers from the body, at the first empty line
+fn split_mime_part(data: &[u8]) -> (&[u8], &[u8]) {
+    let mut pos = 0;
+
+    for line in data.split_inclusive(|b| *b == b'\n') {
+        pos += line.len();
+
+        if line == b"\n" || line == b"\r\n" {
+            return (&data[..pos - line.len()], &data[pos..]);
+        }
+    }
+
+    (data, &[])
+}
+
+// Header names are lowercased and folded values are unfolded
+fn parse_headers(data: &[u8]) -> Vec<(String, String)> {
+    let text = decode_charset(data, None);
+    let mut ret: Vec<(String, String)> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        if idx == 0 && line.starts_with("From ") {
+            continue;
+        }
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = ret.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            ret.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    ret
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(header_name, _)| header_name == name).map(|(_, value)| value.as_str())
+}
+
+fn header_text(headers: &[(String, String)], name: &str) -> Option<String> {
+    clean_text(&decode_encoded_words(header(headers, name)?))
+}
+
+// Control characters are removed and whitespaces are collapsed, like document properties
+fn clean_text(text: &str) -> Option<String> {
+    let ret: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+        .take(MAX_HEADER_LEN)
+        .collect();
+
+    if ret.is_empty() {
+        None
+    } else {
+        Some(ret)
+    }
+}
+
+// Returns the lowercased value of a structured header and its parameters (i.e. 'text/plain; charset=utf-8')
+fn header_with_params(headers: &[(String, String)], name: &str) -> Option<(String, HashMap<String, String>)> {
+    let value = header(headers, name)?;
+    let mut items: Vec<String> = Vec::new();
+    let mut item = String::new();
+    let mut in_quotes = false;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\\' if in_quotes => item.extend(chars.next()),
+            ';' if !in_quotes => items.push(std::mem::take(&mut item)),
+            _ => item.push(c),
+        }
+    }
+
+    items.push(item);
+
+    let main_value = items[0].trim().to_ascii_lowercase();
+    let params = items[1..]
+        .iter()
+        .filter_map(|param| param.split_once('='))
+        .map(|(param_name, param_value)| (param_name.trim().to_ascii_lowercase(), param_value.trim().to_string()))
+        .collect();
+
+    Some((main_value, params))
+}
+
+// Parameters can be encoded words, RFC 2231 extended values or RFC 2231 continuations
+fn param_text(params: &HashMap<String, String>, name: &str) -> Option<String> {
+    if let Some(value) = params.get(name) {
+        return clean_text(&decode_encoded_words(value));
+    }
+
+    let mut opt_charset: Option<String> = None;
+    let mut data = Vec::new();
+
+    // Either a single extended value, or numbered sections that are each either extended or not
+    let section_names = std::iter::once(format!("{}*", name)).chain((0..).map(|idx| format!("{}*{}", name, idx)));
+
+    for (section_idx, section_name) in section_names.enumerate() {
+        let (value, is_extended) = match (params.get(&format!("{}*", section_name)), params.get(&section_name)) {
+            (Some(v), _) if section_idx > 0 => (v, true),
+            (_, Some(v))                    => (v, section_idx == 0),
+            _ if section_idx == 0           => continue,
+            _                               => break,
+        };
+
+        if !is_extended {
+            data.extend(value.as_bytes());
+        } else if opt_charset.is_none() && value.matches('\'').count() >= 2 {
+            let mut fields = value.splitn(3, '\'');
+            opt_charset = fields.next().map(|v| v.to_string());
+            data.extend(percent_decode(fields.nth(1).unwrap_or("")));
+        } else {
+            data.extend(percent_decode(value));
+        }
+    }
+
+    clean_text(&decode_charset(&data, opt_charset.as_deref()))
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes.get(idx + 1..idx + 3).and_then(hex_byte) {
+            Some(v) if bytes[idx] == b'%' => {
+                ret.push(v);
+                idx += 3;
+            },
+            _ => {
+                ret.push(bytes[idx]);
+                idx += 1;
+            }
+        }
+    }
+
+    ret
+}
+
+fn hex_byte(hex: &[u8]) -> Option<u8> {
+    if hex.iter().all(u8::is_ascii_hexdigit) {
+        u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+    } else {
+        None
+    }
+}
+
+// RFC 2047 encoded words (i.e. '=?utf-8?B?...?='), whitespace between adjacent encoded words is dropped
+fn decode_encoded_words(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut after_encoded_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let prefix = &rest[..start];
+
+        match decode_encoded_word(&rest[start..]) {
+            Some((decoded, len)) => {
+                if !(after_encoded_word && prefix.trim().is_empty()) {
+                    ret.push_str(prefix);
+                }
+
+                ret.push_str(&decoded);
+                rest = &rest[start + len..];
+                after_encoded_word = true;
+            },
+            None => {
+                ret.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_encoded_word = false;
+            }
+        }
+    }
+
+    ret.push_str(rest);
+    ret
+}
+
+// Returns the decoded text and the length of the encoded word
+fn decode_encoded_word(text: &str) -> Option<(String, usize)> {
+    let inner = text.strip_prefix("=?")?;
+    let (charset, after_charset) = inner.split_once('?')?;
+    let (encoding, after_encoding) = after_charset.split_once('?')?;
+    let data_len = after_encoding.find("?=")?;
+    let data = &after_encoding[..data_len];
+
+    if data.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "B" | "b" => base64_decode(data.as_bytes()),
+        "Q" | "q" => quoted_printable_decode(data.replace('_', " ").as_bytes()),
+        _ => return None
+    };
+
+    let len = "=?".len() + charset.len() + 1 + encoding.len() + 1 + data_len + "?=".len();
+
+    // The charset can be followed by a RFC 2231 language tag
+    let charset = charset.split('*').next().unwrap_or(charset);
+
+    Some((decode_charset(&bytes, Some(charset)), len))
+}
+
+// Unknown charsets fall back to UTF-8, and invalid UTF-8 text to Windows-1252 (i.e. unlabelled Latin-1 text)
+fn decode_charset(data: &[u8], opt_charset: Option<&str>) -> String {
+    let encoding = opt_charset
+        .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+
+    if encoding == encoding_rs::UTF_8 {
+        if let Ok(text) = std::str::from_utf8(data) {
+            return text.to_string();
+        }
+
+        return encoding_rs::WINDOWS_1252.decode(data).0.into_owned();
+    }
+
+    encoding.decode(data).0.into_owned()
+}
+
+fn decode_transfer_encoding(body: &[u8], opt_encoding: Option<&str>) -> Vec<u8> {
+    match opt_encoding.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64")           => base64_decode(body),
+        Some("quoted-printable") => quoted_printable_decode(body),
+        _                        => body.to_vec(),
+    }
+}
+
+// Line breaks and other invalid characters are skipped, decoding stops at the padding
+fn base64_decode(data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc: u32 = 0;
+    let mut bit_count = 0;
+
+    for b in data {
+        let value = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+'        => 62,
+            b'/'        => 63,
+            b'='        => break,
+            _           => continue,
+        };
+
+        acc = (acc << 6) | value as u32;
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            ret.push((acc >> bit_count) as u8);
+            acc &= (1 << bit_count) - 1;
+        }
+    }
+
+    ret
+}
+
+// Soft line breaks are removed, invalid escape sequences are kept as-is
+fn quoted_printable_decode(data: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(data.len());
+    let mut idx = 0;
+
+    while idx < data.len() {
+        if data[idx] == b'=' {
+            if data[idx + 1..].starts_with(b"\r\n") {
+                idx += 3;
+                continue;
+            } else if data[idx + 1..].starts_with(b"\n") {
+                idx += 2;
+                continue;
+            } else if let Some(v) = data.get(idx + 1..idx + 3).and_then(hex_byte) {
+                ret.push(v);
+                idx += 3;
+                continue;
+            }
+        }
+
+        ret.push(data[idx]);
+        idx += 1;
+    }
+
+    ret
+}
+
+// Parts between the boundary delimiter lines, until the closing delimiter
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut ret = Vec::new();
+    let mut opt_part_start: Option<usize> = None;
+    let mut pos = 0;
+
+    for line in body.split_inclusive(|b| *b == b'\n') {
+        let line_start = pos;
+        pos += line.len();
+
+        let rest = match line.strip_prefix(delimiter.as_bytes()) {
+            Some(v) => v.strip_suffix(b"\n").unwrap_or(v),
+            None => continue
+        };
+
+        let rest = trim_line_end(rest);
+
+        if !rest.is_empty() && rest != b"--" {
+            continue;
+        }
+
+        // The line break before the delimiter belongs to the delimiter
+        if let Some(part_start) = opt_part_start {
+            let part = &body[part_start..line_start.max(part_start)];
+            let part = part.strip_suffix(b"\n").unwrap_or(part);
+            ret.push(part.strip_suffix(b"\r").unwrap_or(part));
+        }
+
+        if rest == b"--" {
+            return ret;
+        }
+
+        opt_part_start = Some(pos);
+    }
+
+    // Unterminated multipart bodies are common in truncated messages
+    if let Some(part_start) = opt_part_start {
+        ret.push(&body[part_start..]);
+    }
+
+    ret
+}
+
+// Only the last path component of the declared name is kept
+fn attachment_name(opt_name: Option<String>, content_type: &str, attachment_num: usize) -> String {
+    let opt_name = opt_name
+        .and_then(|name| name.rsplit(['/', '\\']).next().and_then(metadata::sanitize_text));
+
+    match opt_name {
+        Some(name) => name,
+        None => {
+            let ext = match content_type {
+                "message/rfc822"  => "eml",
+                "application/pdf" => "pdf",
+                "text/plain"      => "txt",
+                "text/html"       => "html",
+                "text/csv"        => "csv",
+                "image/png"       => "png",
+                "image/jpeg"      => "jpg",
+                "image/gif"       => "gif",
+                _                 => "bin",
+            };
+
+            format!("attachment-{}.{}", attachment_num, ext)
+        }
+    }
+}
+
+fn read_msg_storage<F: Read + Seek>(comp: &mut cfb::CompoundFile<F>, storage: &str, properties_header_size: usize, depth: usize) -> EmailMessage {
+    let transport_headers = msg_string(comp, storage, MSG_PROP_TRANSPORT_HEADERS)
+        .map(|v| parse_headers(v.as_bytes()))
+        .unwrap_or_default();
+
+    let sender_name = msg_string(comp, storage, MSG_PROP_SENDER_NAME).and_then(|v| clean_text(&v));
+    let sender_address = msg_string(comp, storage, MSG_PROP_SENDER_SMTP_ADDRESS)
+        .or_else(|| msg_string(comp, storage, MSG_PROP_SENDER_EMAIL))
+        .and_then(|v| clean_text(&v));
+
+    let from = match (sender_name, sender_address) {
+        (Some(name), Some(address)) if name != address => Some(format!("{} <{}>", name, address)),
+        (Some(name), _) => Some(name),
+        (None, Some(address)) => Some(address),
+        (None, None) => header_text(&transport_headers, "from"),
+    };
+
+    let date = header_text(&transport_headers, "date").or_else(|| {
+        msg_systime(comp, storage, properties_header_size, MSG_PROP_CLIENT_SUBMIT_TIME)
+            .and_then(metadata::MetadataDate::from_unix_timestamp)
+            .map(|v| v.to_xmp_date())
+    });
+
+    let mut ret = EmailMessage {
+        from,
+        to: msg_string(comp, storage, MSG_PROP_DISPLAY_TO).and_then(|v| clean_text(&v)),
+        cc: msg_string(comp, storage, MSG_PROP_DISPLAY_CC).and_then(|v| clean_text(&v)),
+        date,
+        subject: msg_string(comp, storage, MSG_PROP_SUBJECT).and_then(|v| clean_text(&v)),
+        text_body: msg_string(comp, storage, MSG_PROP_BODY),
+        html_body: msg_string(comp, storage, MSG_PROP_BODY_HTML)
+            .or_else(|| msg_stream(comp, &msg_property_path(storage, MSG_PROP_BODY_HTML, MSG_TYPE_BINARY)).map(|v| decode_charset(&v, None))),
+        attachments: Vec::new(),
+    };
+
+    let attachment_storages: Vec<String> = match comp.read_storage(storage) {
+        Ok(entries) => entries
+            .filter(|entry| entry.is_storage() && entry.name().starts_with(MSG_ATTACHMENT_STORAGE_PREFIX))
+            .map(|entry| format!("{}{}/", storage, entry.name()))
+            .take(MAX_ATTACHMENTS)
+            .collect(),
+        Err(_) => Vec::new()
+    };
+
+    for attachment_storage in attachment_storages {
+        let opt_name = msg_string(comp, &attachment_storage, MSG_PROP_ATTACH_LONG_FILENAME)
+            .or_else(|| msg_string(comp, &attachment_storage, MSG_PROP_ATTACH_FILENAME))
+            .or_else(|| msg_string(comp, &attachment_storage, MSG_PROP_DISPLAY_NAME));
+        let embedded_message_storage = format!("{}/", msg_property_path(&attachment_storage, MSG_PROP_ATTACH_DATA, MSG_TYPE_OBJECT));
+        let attachment_num = ret.attachments.len() + 1;
+
+        if comp.is_storage(&embedded_message_storage) {
+            if depth < MAX_MIME_DEPTH {
+                let message = read_msg_storage(comp, &embedded_message_storage, MSG_EMBEDDED_PROPERTIES_HEADER_SIZE, depth + 1);
+                let name = attachment_name(opt_name.or_else(|| message.subject.clone()), "", attachment_num);
+
+                ret.attachments.push(EmailAttachment { name, content: AttachmentContent::Message(Box::new(message)) });
+            }
+        } else if let Some(data) = msg_stream(comp, &msg_property_path(&attachment_storage, MSG_PROP_ATTACH_DATA, MSG_TYPE_BINARY)) {
+            let name = attachment_name(opt_name, "", attachment_num);
+            ret.attachments.push(EmailAttachment { name, content: AttachmentContent::File(data) });
+        }
+    }
+
+    ret
+}
+
+fn msg_property_path(storage: &str, property_id: u16, property_type: u16) -> String {
+    format!("{}__substg1.0_{:04X}{:04X}", storage, property_id, property_type)
+}
+
+fn msg_stream<F: Read + Seek>(comp: &mut cfb::CompoundFile<F>, path: &str) -> Option<Vec<u8>> {
+    let mut stream = comp.open_stream(path).ok()?;
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data).ok()?;
+
+    Some(data)
+}
+
+// String properties are stored either in UTF-16LE or in the message code page
+fn msg_string<F: Read + Seek>(comp: &mut cfb::CompoundFile<F>, storage: &str, property_id: u16) -> Option<String> {
+    if let Some(data) = msg_stream(comp, &msg_property_path(storage, property_id, MSG_TYPE_UNICODE)) {
+        let units: Vec<u16> = data.chunks_exact(2).map(|v| u16::from_le_bytes([v[0], v[1]])).collect();
+        return Some(String::from_utf16_lossy(&units).trim_end_matches('\0').to_string());
+    }
+
+    msg_stream(comp, &msg_property_path(storage, property_id, MSG_TYPE_STRING8))
+        .map(|data| decode_charset(&data, None).trim_end_matches('\0').to_string())
+}
+
+// Fixed-size properties are in a single stream, dates are Windows file times (100 nanoseconds intervals)
+fn msg_systime<F: Read + Seek>(comp: &mut cfb::CompoundFile<F>, storage: &str, header_size: usize, property_id: u16) -> Option<i64> {
+    let data = msg_stream(comp, &format!("{}__properties_version1.0", storage))?;
+    let expected_tag = ((property_id as u32) << 16) | MSG_TYPE_SYSTIME as u32;
+
+    data.get(header_size..)?
+        .chunks_exact(MSG_PROPERTY_ENTRY_SIZE)
+        .find(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) == expected_tag)
+        .map(|entry| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(&entry[8..16]);
+            (u64::from_le_bytes(value) / 10_000_000) as i64 - WINDOWS_EPOCH_OFFSET_SECS
+        })
+}
@@ -0,0 +1,381 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use lopdf::{Object, ObjectId};
+use uuid::Uuid;
+
+use entrusted_l10n as l10n;
+use crate::archives;
+use crate::limits::{ResourceLimitError, ResourceLimits};
+use crate::metadata;
+
+// Hostile documents can have very deep name trees
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+// OLE objects wrap files (packages) or documents of other programs (i.e. a spreadsheet in a text document)
+const OLE_NATIVE_STREAM: &str   = "\u{1}Ole10Native";
+const OLE_PACKAGE_STREAM: &str  = "Package";
+const OLE_CONTENTS_STREAM: &str = "CONTENTS";
+const OLE_COMPOBJ_STREAM: &str  = "\u{1}CompObj";
+
+const OOXML_EMBEDDINGS_DIRS: [&str; 3] = ["word/embeddings/", "xl/embeddings/", "ppt/embeddings/"];
+
+// An embedded file, either extracted to its own file or skipped with a reason (i.e. an unsupported object)
+pub struct EmbeddedFile {
+    pub name: String,
+    pub extracted: Result<PathBuf, String>,
+}
+
+// PDF attachments and objects embedded in Office documents, other formats don't have any embedded files
+// The expanded size is shared with archive entries, so that nested files can't multiply the size limit
+pub fn extract_embedded_files(mime_type: &str, src_path: &Path, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<EmbeddedFile>, Box<dyn Error>> {
+    match mime_type {
+        "application/pdf" => extract_pdf_files(src_path, dest_dir, limits, expanded_size, l10n),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            | "application/vnd.openxmlformats-officedocument.presentationml.presentation" => {
+                extract_zip_files(src_path, is_ooxml_embedding, dest_dir, limits, expanded_size, l10n)
+            },
+        "application/vnd.oasis.opendocument.text"
+            | "application/vnd.oasis.opendocument.spreadsheet"
+            | "application/vnd.oasis.opendocument.presentation"
+            | "application/vnd.oasis.opendocument.graphics" => {
+                extract_zip_files(src_path, is_opendocument_embedding, dest_dir, limits, expanded_size, l10n)
+            },
+        "application/msword" | "application/vnd.ms-excel" => extract_cfb_objects(src_path, dest_dir, limits, expanded_size, l10n),
+        _ => Ok(Vec::new())
+    }
+}
+
+// Files are attached to the whole document (name tree) or to a page (file attachment annotations)
+fn extract_pdf_files(src_path: &Path, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<EmbeddedFile>, Box<dyn Error>> {
+    let doc = lopdf::Document::load(src_path)?;
+
+    if doc.trailer.has(b"Encrypt") {
+        return Err(l10n.gettext("Embedded files of password-protected PDF files are not supported").into());
+    }
+
+    let mut filespecs = Vec::new();
+
+    if let Some(tree) = doc.catalog().ok()
+        .and_then(|catalog| catalog.get(b"Names").ok())
+        .and_then(|names| doc.dereference(names).ok())
+        .and_then(|(_, names)| names.as_dict().ok())
+        .and_then(|names| names.get(b"EmbeddedFiles").ok()) {
+            collect_name_tree_values(&doc, tree, 0, &mut BTreeSet::new(), &mut filespecs);
+        }
+
+    for page_id in doc.get_pages().into_values() {
+        let annots = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Annots")).and_then(|v| doc.dereference(v)) {
+            Ok((_, v)) => v.as_array().cloned().unwrap_or_default(),
+            Err(_) => continue
+        };
+
+        for annot in annots.iter() {
+            if let Ok((_, Object::Dictionary(annot))) = doc.dereference(annot) {
+                if annot.get(b"Subtype").and_then(Object::as_name_str).ok() == Some("FileAttachment") {
+                    if let Ok(filespec) = annot.get(b"FS") {
+                        filespecs.push(filespec.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut ret = Vec::new();
+    let mut stream_ids = BTreeSet::new();
+
+    for filespec in filespecs.iter() {
+        let filespec = match doc.dereference(filespec).and_then(|(_, v)| v.as_dict()) {
+            Ok(v) => v,
+            Err(_) => continue
+        };
+
+        // The same file can be referenced by the name tree and by annotations
+        let stream_id: ObjectId = match filespec.get(b"EF").and_then(|v| doc.dereference(v)).and_then(|(_, v)| v.as_dict()) {
+            Ok(ef) => match ef.get(b"UF").or_else(|_| ef.get(b"F")).and_then(Object::as_reference) {
+                Ok(v) => v,
+                Err(_) => continue
+            },
+            Err(_) => continue
+        };
+
+        if !stream_ids.insert(stream_id) {
+            continue;
+        }
+
+        limits.check_embedded_file_count(ret.len() + 1, l10n.clone())?;
+
+        let original_name = filespec.get(b"UF").or_else(|_| filespec.get(b"F"))
+            .and_then(Object::as_str)
+            .map(pdf_text_string)
+            .unwrap_or_default();
+        let name = archives::entry_display_name(&original_name, ret.len() + 1);
+
+        let extracted = match doc.get_object(stream_id).and_then(Object::as_stream) {
+            Ok(stream) => write_pdf_stream(stream, &name, dest_dir, limits, expanded_size, l10n.clone())?,
+            Err(ex) => Err(ex.to_string())
+        };
+
+        ret.push(EmbeddedFile { name, extracted });
+    }
+
+    Ok(ret)
+}
+
+fn collect_name_tree_values(doc: &lopdf::Document, node: &Object, depth: usize, visited_ids: &mut BTreeSet<ObjectId>, values: &mut Vec<Object>) {
+    if depth >= MAX_NAME_TREE_DEPTH {
+        return;
+    }
+
+    let node = match doc.dereference(node) {
+        Ok((Some(node_id), _)) if !visited_ids.insert(node_id) => return,
+        Ok((_, Object::Dictionary(node))) => node,
+        _ => return
+    };
+
+    // Leaf nodes list keys and values in the same array
+    if let Ok((_, Object::Array(names))) = node.get(b"Names").and_then(|v| doc.dereference(v)) {
+        values.extend(names.iter().skip(1).step_by(2).cloned());
+    }
+
+    if let Ok((_, Object::Array(kids))) = node.get(b"Kids").and_then(|v| doc.dereference(v)) {
+        for kid in kids.iter() {
+            collect_name_tree_values(doc, kid, depth + 1, visited_ids, values);
+        }
+    }
+}
+
+// Compressed streams are decoded up to the compression ratio limit, instead of loading them fully in memory
+fn write_pdf_stream(stream: &lopdf::Stream, name: &str, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Result<PathBuf, String>, Box<dyn Error>> {
+    let dest_path = archives::entry_dest_path(dest_dir, name);
+    let compressed_size = stream.content.len() as u64;
+    let filters = stream.filters().unwrap_or_default();
+
+    let res_written = if filters.is_empty() {
+        archives::write_entry(&mut stream.content.as_slice(), &dest_path, limits.max_expanded_size(), limits, expanded_size, l10n.clone())
+            .map(|_| ())
+    } else if filters == ["FlateDecode"] && !stream.dict.has(b"DecodeParms") {
+        let mut decoder = flate2::read::ZlibDecoder::new(stream.content.as_slice());
+        archives::write_entry(&mut decoder, &dest_path, limits.max_expanded_entry_size(compressed_size), limits, expanded_size, l10n.clone())
+            .and_then(|entry_size| limits.check_compression_ratio(name, compressed_size, entry_size, l10n.clone()))
+    } else {
+        return Ok(Err(l10n.gettext_fmt("Unsupported PDF stream filter: {0}", vec![&filters.join(", ")])));
+    };
+
+    soft_failure(res_written.map(|_| dest_path))
+}
+
+// Only resource limit errors stop the extraction, other embedded files can still be valid
+fn soft_failure<T>(res: Result<T, Box<dyn Error>>) -> Result<Result<T, String>, Box<dyn Error>> {
+    match res {
+        Ok(v) => Ok(Ok(v)),
+        Err(ex) if ex.is::<ResourceLimitError>() => Err(ex),
+        Err(ex) => Ok(Err(ex.to_string()))
+    }
+}
+
+// PDF text strings are either in PDFDocEncoding (close to Latin-1) or in UTF-16BE with a byte order mark
+pub fn pdf_text_string(data: &[u8]) -> String {
+    match data.strip_prefix(&[0xFE, 0xFF]) {
+        Some(v) => {
+            let units: Vec<u16> = v.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        },
+        None => data.iter().map(|b| *b as char).collect()
+    }
+}
+
+pub fn is_ooxml_embedding(name: &str) -> bool {
+    OOXML_EMBEDDINGS_DIRS
+        .iter()
+        .any(|dir| name.strip_prefix(dir).map(|v| !v.is_empty() && !v.contains('/')).unwrap_or(false))
+}
+
+// Embedded OpenDocument objects are folders rendered with the document, OLE objects are binary files
+pub fn is_opendocument_embedding(name: &str) -> bool {
+    name.starts_with("Object ") && !name.contains('/')
+}
+
+fn extract_zip_files(src_path: &Path, is_embedding: fn(&str) -> bool, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<EmbeddedFile>, Box<dyn Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(src_path)?)?;
+    let mut ret = Vec::new();
+
+    for idx in 0..zip.len() {
+        let mut entry = zip.by_index(idx)?;
+
+        if entry.is_dir() || !is_embedding(entry.name()) {
+            continue;
+        }
+
+        limits.check_embedded_file_count(ret.len() + 1, l10n.clone())?;
+
+        let entry_name = entry.name().rsplit('/').next().unwrap_or_default().to_string();
+        let name = archives::entry_display_name(&entry_name, ret.len() + 1);
+        let dest_path = archives::entry_dest_path(dest_dir, &name);
+        let compressed_size = entry.compressed_size();
+        let entry_size = archives::write_entry(&mut entry, &dest_path, limits.max_expanded_entry_size(compressed_size), limits, expanded_size, l10n.clone())?;
+        limits.check_compression_ratio(&name, compressed_size, entry_size, l10n.clone())?;
+
+        ret.push(unwrap_ole_file(name, dest_path, dest_dir, limits, expanded_size, l10n.clone())?);
+    }
+
+    Ok(ret)
+}
+
+// OLE objects of legacy Office documents are storages of the compound file
+// Word documents keep them in the object pool, Excel workbooks at the root of the file
+fn extract_cfb_objects(src_path: &Path, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<Vec<EmbeddedFile>, Box<dyn Error>> {
+    let mut comp = cfb::open(src_path)?;
+
+    let mut storage_paths: Vec<PathBuf> = comp.read_root_storage()
+        .filter(|entry| entry.is_storage() && entry.name().starts_with("MBD"))
+        .map(|entry| entry.path().to_path_buf())
+        .collect();
+
+    if let Ok(entries) = comp.read_storage("/ObjectPool") {
+        storage_paths.extend(entries.filter(|entry| entry.is_storage()).map(|entry| entry.path().to_path_buf()));
+    }
+
+    let mut ret = Vec::new();
+
+    for storage_path in storage_paths {
+        limits.check_embedded_file_count(ret.len() + 1, l10n.clone())?;
+        ret.push(ole_storage_file(&mut comp, &storage_path, None, dest_dir, limits, expanded_size, l10n.clone())?);
+    }
+
+    Ok(ret)
+}
+
+// Files embedded in Office Open XML documents are either OLE objects or plain documents
+fn unwrap_ole_file(name: String, file_path: PathBuf, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<EmbeddedFile, Box<dyn Error>> {
+    let mut comp = match cfb::open(&file_path) {
+        Ok(v) => v,
+        Err(_) => return Ok(EmbeddedFile { name, extracted: Ok(file_path) })
+    };
+
+    ole_storage_file(&mut comp, Path::new("/"), Some((name, file_path.clone())), dest_dir, limits, expanded_size, l10n)
+}
+
+// Packages and raw contents are extracted as-is, other objects are documents in their own compound file
+fn ole_storage_file<F: Read + Seek>(comp: &mut cfb::CompoundFile<F>, storage_path: &Path, opt_file: Option<(String, PathBuf)>, dest_dir: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<EmbeddedFile, Box<dyn Error>> {
+    let storage_name = storage_path.file_name().and_then(|v| v.to_str()).unwrap_or_default().to_string();
+    let name = ole_user_type(comp, storage_path)
+        .or_else(|| opt_file.as_ref().map(|(name, _)| name.clone()))
+        .unwrap_or(storage_name);
+    let native_path = storage_path.join(OLE_NATIVE_STREAM);
+
+    if comp.is_stream(&native_path) {
+        // The stream is held in memory before its content is written, it can't be larger than the remaining extraction budget
+        let max_size = limits.max_expanded_size().saturating_sub(*expanded_size);
+        let mut data = Vec::new();
+        comp.open_stream(&native_path)?.take(max_size.saturating_add(1)).read_to_end(&mut data)?;
+
+        if data.len() as u64 > max_size {
+            *expanded_size = expanded_size.saturating_add(data.len() as u64);
+            limits.check_expanded_size(*expanded_size, l10n.clone())?;
+        }
+
+        return match parse_ole_package(&data) {
+            Some((label, content)) => {
+                let name = archives::entry_display_name(&label, 1);
+                let dest_path = archives::entry_dest_path(dest_dir, &name);
+                let extracted = soft_failure(archives::write_entry(&mut &content[..], &dest_path, limits.max_expanded_size(), limits, expanded_size, l10n.clone()))?;
+
+                Ok(EmbeddedFile { name, extracted: extracted.map(|_| dest_path) })
+            },
+            None => Ok(EmbeddedFile { name, extracted: Err(l10n.gettext("Invalid OLE package")) })
+        };
+    }
+
+    for stream_name in [OLE_PACKAGE_STREAM, OLE_CONTENTS_STREAM] {
+        let stream_path = storage_path.join(stream_name);
+
+        if comp.is_stream(&stream_path) {
+            let dest_path = archives::entry_dest_path(dest_dir, &name);
+            let mut stream = comp.open_stream(&stream_path)?;
+            let extracted = soft_failure(archives::write_entry(&mut stream, &dest_path, limits.max_expanded_size(), limits, expanded_size, l10n.clone()))?;
+
+            return Ok(EmbeddedFile { name, extracted: extracted.map(|_| dest_path) });
+        }
+    }
+
+    // The file is already a compound document, objects from the storage of another document are copied to a new file
+    if let Some((_, file_path)) = opt_file {
+        return Ok(EmbeddedFile { name, extracted: Ok(file_path) });
+    }
+
+    let dest_path = dest_dir.join(format!("object-{}", Uuid::new_v4()));
+    let mut dest = cfb::create(&dest_path)?;
+    dest.set_storage_clsid("/", *comp.entry(storage_path)?.clsid())?;
+    copy_storage(comp, storage_path, &mut dest, Path::new("/"), limits, expanded_size, l10n)?;
+    dest.flush()?;
+
+    Ok(EmbeddedFile { name, extracted: Ok(dest_path) })
+}
+
+fn copy_storage<F: Read + Seek>(src: &mut cfb::CompoundFile<F>, src_path: &Path, dest: &mut cfb::CompoundFile<fs::File>, dest_path: &Path, limits: &ResourceLimits, expanded_size: &mut u64, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    let entries: Vec<cfb::Entry> = src.read_storage(src_path)?.collect();
+
+    for entry in entries {
+        let entry_dest_path = dest_path.join(entry.name());
+
+        if entry.is_storage() {
+            dest.create_storage(&entry_dest_path)?;
+            dest.set_storage_clsid(&entry_dest_path, *entry.clsid())?;
+            copy_storage(src, entry.path(), dest, &entry_dest_path, limits, expanded_size, l10n.clone())?;
+        } else {
+            *expanded_size = expanded_size.saturating_add(entry.len());
+            limits.check_expanded_size(*expanded_size, l10n.clone())?;
+
+            let mut reader = src.open_stream(entry.path())?;
+            let mut writer = dest.create_stream(&entry_dest_path)?;
+            io::copy(&mut reader, &mut writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+// The user type is a display name (i.e. 'Microsoft Excel Worksheet'), after a fixed size header
+pub fn ole_user_type<F: Read + Seek>(comp: &mut cfb::CompoundFile<F>, storage_path: &Path) -> Option<String> {
+    let mut data = Vec::new();
+    comp.open_stream(storage_path.join(OLE_COMPOBJ_STREAM)).ok()?.take(4096).read_to_end(&mut data).ok()?;
+
+    let len = u32::from_le_bytes(data.get(28..32)?.try_into().ok()?) as usize;
+    let text = data.get(32..32usize.checked_add(len)?)?;
+
+    metadata::sanitize_text(String::from_utf8_lossy(text).trim_end_matches('\0'))
+}
+
+// OLE packages start with the file label and its original path, followed by the file content
+fn parse_ole_package(data: &[u8]) -> Option<(String, &[u8])> {
+    let mut pos = 6; // total size and format identifier
+
+    let read_cstring = |pos: &mut usize| -> Option<String> {
+        let len = data.get(*pos..)?.iter().position(|b| *b == 0)?;
+        let ret = String::from_utf8_lossy(&data[*pos..*pos + len]).to_string();
+        *pos += len + 1;
+        Some(ret)
+    };
+
+    let label = read_cstring(&mut pos)?;
+    let _src_path = read_cstring(&mut pos)?;
+    pos += 4; // reserved and object type
+
+    let read_u32 = |pos: &mut usize| -> Option<usize> {
+        let ret = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+        *pos += 4;
+        Some(ret)
+    };
+
+    let temp_path_len = read_u32(&mut pos)?;
+    pos = pos.checked_add(temp_path_len)?;
+    let data_len = read_u32(&mut pos)?;
+    let content = data.get(pos..pos.checked_add(data_len)?)?;
+
+    Some((label, content))
+}
@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::limits::ResourceLimitError;
+
+pub use entrusted_common::errors::ErrorCode;
+
+#[derive(Debug)]
+pub struct ConversionError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl ConversionError {
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        Self { code, message }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ConversionError {}
+
+// Errors without a specific code are generic conversion failures
+pub fn error_code(ex: &(dyn Error + 'static)) -> ErrorCode {
+    if let Some(conversion_error) = ex.downcast_ref::<ConversionError>() {
+        conversion_error.code
+    } else if ex.is::<ResourceLimitError>() {
+        ErrorCode::ResourceLimit
+    } else if ex.downcast_ref::<glib::Error>().and_then(|glib_error| glib_error.kind::<poppler::Error>()) == Some(poppler::Error::Encrypted) {
+        ErrorCode::WrongPassword
+    } else {
+        ErrorCode::ConversionFailed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code() {
+        let ex: Box<dyn Error> = ConversionError::new(ErrorCode::UnsupportedMime, "unsupported".to_string()).into();
+        assert_eq!(error_code(ex.as_ref()), ErrorCode::UnsupportedMime);
+        assert_eq!(ex.to_string(), "unsupported");
+
+        let ex: Box<dyn Error> = "failure".into();
+        assert_eq!(error_code(ex.as_ref()), ErrorCode::ConversionFailed);
+    }
+}
@@ -0,0 +1,118 @@
+use serde::Serialize;
+
+// JSON progress events are read by other programs (i.e. entrusted-client and entrusted-webserver)
+// The version changes whenever fields are renamed or removed, new fields can be added without any version change
+pub const EVENT_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    PhaseStarted,
+    Progress,
+    PageDone,
+    Warning,
+    Error,
+    Result,
+}
+
+// Pages are rendered and converted one phase at a time, unless they're processed as a pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Analysis,
+    InputConversion,
+    PageRendering,
+    PageConversion,
+    Ocr,
+    PageProcessing,
+    Combining,
+    Output,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConversionResult {
+    pub page_count: usize,
+    pub output_size: u64, // bytes
+}
+
+// The data field is the human-readable message, the other fields are for programs
+#[derive(Clone, Debug, Serialize)]
+pub struct ProgressEvent {
+    pub version: u32,
+    pub event: EventType,
+    pub percent_complete: usize,
+    pub data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase: Option<Phase>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_num: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<usize>,
+    pub elapsed_ms: u64, // since the start of the conversion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ConversionResult>,
+}
+
+impl ProgressEvent {
+    fn new(event: EventType, percent_complete: usize, data: String) -> Self {
+        Self {
+            version: EVENT_PROTOCOL_VERSION,
+            event,
+            percent_complete,
+            data,
+            phase: None,
+            page_num: None,
+            page_index: None,
+            page_count: None,
+            elapsed_ms: 0,
+            error_code: None,
+            result: None,
+        }
+    }
+
+    pub fn progress(percent_complete: usize, data: String) -> Self {
+        Self::new(EventType::Progress, percent_complete, data)
+    }
+
+    // The page count is only known for phases processing pages
+    pub fn phase_started(percent_complete: usize, data: String, phase: Phase, opt_page_count: Option<usize>) -> Self {
+        Self {
+            phase: Some(phase),
+            page_count: opt_page_count,
+            ..Self::new(EventType::PhaseStarted, percent_complete, data)
+        }
+    }
+
+    // The page number is the document page, the page index is its 1-based position among the pages of the phase
+    pub fn page_done(percent_complete: usize, data: String, phase: Phase, page_num: usize, page_index: usize, page_count: usize) -> Self {
+        Self {
+            phase: Some(phase),
+            page_num: Some(page_num),
+            page_index: Some(page_index),
+            page_count: Some(page_count),
+            ..Self::new(EventType::PageDone, percent_complete, data)
+        }
+    }
+
+    pub fn warning(percent_complete: usize, data: String) -> Self {
+        Self::new(EventType::Warning, percent_complete, data)
+    }
+
+    pub fn error(percent_complete: usize, data: String, error_code: &str) -> Self {
+        Self {
+            error_code: Some(error_code.to_string()),
+            ..Self::new(EventType::Error, percent_complete, data)
+        }
+    }
+
+    pub fn result(percent_complete: usize, data: String, result: ConversionResult) -> Self {
+        Self {
+            result: Some(result),
+            ..Self::new(EventType::Result, percent_complete, data)
+        }
+    }
+}
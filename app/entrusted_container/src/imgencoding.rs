@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::fs;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, GrayImage};
+use lopdf::{dictionary, Object, Stream};
+
+use entrusted_l10n as l10n;
+use crate::errors;
+use crate::POINTS_PER_INCH;
+
+pub const IMAGE_ENCODING_LOSSLESS: &str = "lossless";
+pub const IMAGE_ENCODING_JPEG: &str     = "jpeg";
+pub const IMAGE_ENCODING_BILEVEL: &str  = "bilevel";
+pub const IMAGE_ENCODING_CHOICES: [&str; 3] = [IMAGE_ENCODING_LOSSLESS, IMAGE_ENCODING_JPEG, IMAGE_ENCODING_BILEVEL];
+
+pub const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+// How page images are stored in the output PDF
+// Lossless keeps the cairo PNG rendering, JPEG is meant for photos and scans, bilevel for text documents
+#[derive(Clone, Copy, Debug)]
+pub enum ImageEncoding {
+    Lossless,
+    Jpeg(u8), // quality between 1 and 100
+    Bilevel,
+}
+
+impl ImageEncoding {
+    pub fn parse(name: &str, jpeg_quality: u8, l10n: l10n::Translations) -> Result<Self, Box<dyn Error>> {
+        match name {
+            IMAGE_ENCODING_LOSSLESS => Ok(ImageEncoding::Lossless),
+            IMAGE_ENCODING_JPEG     => Ok(ImageEncoding::Jpeg(jpeg_quality.clamp(1, 100))),
+            IMAGE_ENCODING_BILEVEL  => Ok(ImageEncoding::Bilevel),
+            _                       => Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("Unknown image encoding: {0}. Hint: Try 'lossless', 'jpeg' or 'bilevel'.", vec![name])).into())
+        }
+    }
+}
+
+// Otsu's method, picks the gray level that best separates the ink from the paper
+pub fn otsu_threshold(img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+
+    for pixel in img.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let sum_all: f64 = histogram.iter().enumerate().map(|(level, count)| level as f64 * *count as f64).sum();
+
+    let mut sum_background = 0.0;
+    let mut weight_background: u64 = 0;
+    let mut best_variance = 0.0;
+    let mut best_threshold: u8 = 127;
+
+    for (level, count) in histogram.iter().enumerate() {
+        weight_background += count;
+
+        if weight_background == 0 {
+            continue;
+        }
+
+        let weight_foreground = total - weight_background;
+
+        if weight_foreground == 0 {
+            break;
+        }
+
+        sum_background += level as f64 * *count as f64;
+
+        let mean_background = sum_background / weight_background as f64;
+        let mean_foreground = (sum_all - sum_background) / weight_foreground as f64;
+        let variance = weight_background as f64 * weight_foreground as f64 * (mean_background - mean_foreground).powi(2);
+
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = level as u8;
+        }
+    }
+
+    best_threshold
+}
+
+// Rows are padded to a byte boundary, a bit is set for dark pixels
+// usize::div_ceil requires Rust 1.73, the builder images use Rust 1.72
+#[allow(clippy::manual_div_ceil)]
+fn pack_bilevel_rows(img: &GrayImage) -> Vec<u8> {
+    let threshold = otsu_threshold(img);
+    let row_len = (img.width() as usize + 7) / 8;
+    let mut data = vec![0u8; row_len * img.height() as usize];
+
+    for (x, y, pixel) in img.enumerate_pixels() {
+        if pixel.0[0] <= threshold {
+            data[y as usize * row_len + x as usize / 8] |= 0x80 >> (x % 8);
+        }
+    }
+
+    data
+}
+
+pub fn write_jpeg(img: &DynamicImage, quality: u8, dest_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(fs::File::create(dest_path)?);
+    encode_jpeg(img, quality, &mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Binary PBM (P4) images are read by leptonica as 1 bit images, which tesseract stores with CCITT G4 compression
+pub fn write_pbm(img: &DynamicImage, dest_path: &Path) -> Result<(), Box<dyn Error>> {
+    let gray_img = img.to_luma8();
+    let mut writer = BufWriter::new(fs::File::create(dest_path)?);
+    write!(writer, "P4\n{} {}\n", gray_img.width(), gray_img.height())?;
+    writer.write_all(&pack_bilevel_rows(&gray_img))?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn encode_jpeg<W: Write>(img: &DynamicImage, quality: u8, writer: &mut W) -> Result<(), Box<dyn Error>> {
+    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(writer, quality);
+
+    // Grayscale renderings don't need the extra color channels
+    if img.color().has_color() {
+        encoder.encode_image(&img.to_rgb8())?;
+    } else {
+        encoder.encode_image(&img.to_luma8())?;
+    }
+
+    Ok(())
+}
+
+// Writes a single page PDF document holding only the page image, sized according to the image resolution
+pub fn img_to_encoded_pdf(img: &DynamicImage, encoding: ImageEncoding, dpi: f64, dest_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (img.width(), img.height());
+
+    let image_stream = match encoding {
+        ImageEncoding::Jpeg(quality) => {
+            let mut data = Vec::new();
+            encode_jpeg(img, quality, &mut Cursor::new(&mut data))?;
+            let color_space = if img.color().has_color() { "DeviceRGB" } else { "DeviceGray" };
+
+            Stream::new(dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width as i64,
+                "Height" => height as i64,
+                "ColorSpace" => color_space,
+                "BitsPerComponent" => 8,
+                "Filter" => "DCTDecode",
+            }, data).with_compression(false)
+        },
+        _ => {
+            // Set bits are painted in black
+            let mut stream = Stream::new(dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width as i64,
+                "Height" => height as i64,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 1,
+                "Decode" => vec![Object::Integer(1), Object::Integer(0)],
+            }, pack_bilevel_rows(&img.to_luma8()));
+            stream.compress()?;
+            stream
+        }
+    };
+
+    let scale = POINTS_PER_INCH / dpi;
+    let (page_width, page_height) = (width as f64 * scale, height as f64 * scale);
+
+    let mut doc = lopdf::Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let image_id = doc.add_object(image_stream);
+
+    let content = format!("q {:.4} 0 0 {:.4} 0 0 cm /Im0 Do Q", page_width, page_height);
+    let mut content_stream = Stream::new(dictionary! {}, content.into_bytes());
+    content_stream.compress()?;
+    let content_id = doc.add_object(content_stream);
+
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), Object::Real(page_width as f32), Object::Real(page_height as f32)],
+        "Resources" => dictionary! {
+            "XObject" => dictionary! {
+                "Im0" => image_id,
+            },
+        },
+        "Contents" => content_id,
+    });
+
+    doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => vec![page_id.into()],
+        "Count" => 1,
+    }));
+
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+
+    doc.trailer.set("Root", catalog_id);
+    doc.save(dest_path)?;
+
+    Ok(())
+}
+
+// Tesseract embeds JPEG input files as-is and 1 bit images with CCITT G4 compression
+// Returns the path of the transcoded image, if the page image needs to be transcoded for the selected encoding
+pub fn ocr_input_img(png_path: &Path, encoding: ImageEncoding) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    match encoding {
+        ImageEncoding::Lossless => Ok(None),
+        ImageEncoding::Jpeg(quality) => {
+            let dest_path = png_path.with_extension("jpg");
+            write_jpeg(&image::open(png_path)?, quality, &dest_path)?;
+            Ok(Some(dest_path))
+        },
+        ImageEncoding::Bilevel => {
+            let dest_path = png_path.with_extension("pbm");
+            write_pbm(&image::open(png_path)?, &dest_path)?;
+            Ok(Some(dest_path))
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use cairo::{Context, ImageSurface, PdfSurface};
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat};
+
+use entrusted_l10n as l10n;
+use crate::limits::ResourceLimits;
+use crate::ConversionLogger;
+
+// Image directories are chained, hostile files can have loops or a very long chain
+const MAX_TIFF_FRAMES: usize = 65536;
+
+// Converts every frame of an image to its own PDF page, one pixel per point
+// Multi-page TIFF images (i.e. faxes) and animated GIF images have several frames, other images have only one
+pub fn img_frames_to_pdf(logger: &dyn ConversionLogger, img_format: ImageFormat, src_path: &Path, dest_path: &Path, limits: &ResourceLimits, l10n: l10n::Translations) -> Result<usize, Box<dyn Error>> {
+    let mut frames_pdf = FramesPdf::new(dest_path);
+
+    match img_format {
+        ImageFormat::Tiff => {
+            let data = fs::read(src_path)?;
+
+            for ifd_offset in tiff_ifd_offsets(&data) {
+                let frame_reader = || image::io::Reader::with_format(BufReader::new(TiffFrameReader::new(&data, ifd_offset)), ImageFormat::Tiff);
+                frames_pdf.check_frame(frame_reader().into_dimensions()?, limits, l10n.clone())?;
+
+                logger.log(5, l10n.gettext_fmt("Converting image frame {0} to PDF", vec![&(frames_pdf.frame_count + 1).to_string()]));
+                frames_pdf.add_frame(&frame_reader().decode()?)?;
+            }
+        },
+        ImageFormat::Gif => {
+            let decoder = image::codecs::gif::GifDecoder::new(BufReader::new(fs::File::open(src_path)?))?;
+            let (canvas_width, canvas_height) = decoder.dimensions();
+
+            // Every frame is composed over the whole canvas, at most one frame is decoded at a time
+            limits.check_total_pixels(canvas_width as u64 * canvas_height as u64, l10n.clone())?;
+
+            for frame in decoder.into_frames() {
+                let img = DynamicImage::ImageRgba8(frame?.into_buffer());
+                frames_pdf.check_frame(img_dimensions(&img), limits, l10n.clone())?;
+
+                logger.log(5, l10n.gettext_fmt("Converting image frame {0} to PDF", vec![&(frames_pdf.frame_count + 1).to_string()]));
+                frames_pdf.add_frame(&img)?;
+            }
+        },
+        _ => {
+            let img_reader = || -> Result<image::io::Reader<BufReader<fs::File>>, io::Error> {
+                Ok(image::io::Reader::with_format(BufReader::new(fs::File::open(src_path)?), img_format))
+            };
+
+            // Read the image dimensions from its header before decoding anything
+            frames_pdf.check_frame(img_reader()?.into_dimensions()?, limits, l10n.clone())?;
+            frames_pdf.add_frame(&img_reader()?.decode()?)?;
+        }
+    }
+
+    frames_pdf.finish(l10n)
+}
+
+fn img_dimensions(img: &DynamicImage) -> (u32, u32) {
+    (img.width(), img.height())
+}
+
+// Multi-page PDF document, with a page size that follows each frame
+struct FramesPdf {
+    dest_path: PathBuf,
+    opt_surface: Option<PdfSurface>,
+    frame_count: usize,
+    pixel_count: u64,
+}
+
+impl FramesPdf {
+    fn new(dest_path: &Path) -> Self {
+        Self {
+            dest_path: dest_path.to_path_buf(),
+            opt_surface: None,
+            frame_count: 0,
+            pixel_count: 0,
+        }
+    }
+
+    // Frames count as pages towards the resource limits
+    fn check_frame(&self, dimensions: (u32, u32), limits: &ResourceLimits, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        limits.check_page_count(self.frame_count + 1, l10n.clone())?;
+        limits.check_total_pixels(self.pixel_count.saturating_add(dimensions.0 as u64 * dimensions.1 as u64), l10n)
+    }
+
+    fn add_frame(&mut self, img: &DynamicImage) -> Result<(), Box<dyn Error>> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let buffer_cursor = &mut Cursor::new(&mut buffer);
+
+        img.write_to(buffer_cursor, image::ImageOutputFormat::Png)?;
+        buffer_cursor.flush()?;
+        buffer_cursor.rewind()?;
+
+        let surface_png = ImageSurface::create_from_png(buffer_cursor)?;
+        let (w, h) = (surface_png.width() as f64, surface_png.height() as f64);
+
+        let surface_pdf = match self.opt_surface.take() {
+            Some(surface_pdf) => {
+                surface_pdf.set_size(w, h)?;
+                surface_pdf
+            },
+            None => PdfSurface::new(w, h, &self.dest_path)?
+        };
+
+        let ctx = Context::new(&surface_pdf)?;
+        ctx.set_source_surface(&surface_png, 0.0, 0.0)?;
+        ctx.paint()?;
+        ctx.show_page()?;
+
+        self.opt_surface = Some(surface_pdf);
+        self.frame_count += 1;
+        self.pixel_count = self.pixel_count.saturating_add(img.width() as u64 * img.height() as u64);
+
+        Ok(())
+    }
+
+    fn finish(self, l10n: l10n::Translations) -> Result<usize, Box<dyn Error>> {
+        match self.opt_surface {
+            Some(surface_pdf) => {
+                surface_pdf.finish();
+                surface_pdf.status()?;
+                Ok(self.frame_count)
+            },
+            None => Err(l10n.gettext("Could not find any image frame in the input file").into())
+        }
+    }
+}
+
+// Offsets of all the image file directories (IFD), one per frame, in classic TIFF or BigTIFF files
+// Invalid headers are left to the image decoder, so that it reports the actual error
+fn tiff_ifd_offsets(data: &[u8]) -> Vec<u64> {
+    let read_uint = |offset: u64, len: u64| -> Option<u64> {
+        let start = usize::try_from(offset).ok()?;
+        let bytes = data.get(start..start.checked_add(len as usize)?)?;
+
+        Some(match &data[0..2] {
+            b"II" => bytes.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64),
+            _     => bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u64),
+        })
+    };
+
+    let layout = match (data.get(0..2), read_uint(2, 2)) {
+        (Some(b"II" | b"MM"), Some(42)) => Some((4, 4, 2, 12)),
+        (Some(b"II" | b"MM"), Some(43)) => Some((8, 8, 8, 20)),
+        _ => None,
+    };
+
+    let mut ret = Vec::new();
+    let mut visited_offsets = BTreeSet::new();
+
+    if let Some((offset_len, first_offset_pos, count_len, entry_len)) = layout {
+        let mut opt_offset = read_uint(first_offset_pos, offset_len);
+
+        while let Some(offset) = opt_offset {
+            if offset == 0 || !visited_offsets.insert(offset) || ret.len() >= MAX_TIFF_FRAMES {
+                break;
+            }
+
+            ret.push(offset);
+
+            opt_offset = read_uint(offset, count_len)
+                .and_then(|entry_count| entry_count.checked_mul(entry_len))
+                .and_then(|entries_len| offset.checked_add(count_len + entries_len))
+                .and_then(|next_offset_pos| read_uint(next_offset_pos, offset_len));
+        }
+    }
+
+    if ret.is_empty() {
+        ret.push(0);
+    }
+
+    ret
+}
+
+// TIFF decoders only read the first frame, the header is patched to point to another image file directory instead
+struct TiffFrameReader<'a> {
+    data: &'a [u8],
+    header: Vec<u8>,
+    pos: u64,
+}
+
+impl<'a> TiffFrameReader<'a> {
+    fn new(data: &'a [u8], ifd_offset: u64) -> Self {
+        let is_little_endian = data.starts_with(b"II");
+        let is_bigtiff = data.get(2..4) == Some(if is_little_endian { &[43u8, 0][..] } else { &[0u8, 43][..] });
+
+        let mut header = data[0..data.len().min(if is_bigtiff { 16 } else { 8 })].to_vec();
+
+        if ifd_offset != 0 {
+            let offset_bytes = match (is_bigtiff, is_little_endian) {
+                (true, true)   => ifd_offset.to_le_bytes().to_vec(),
+                (true, false)  => ifd_offset.to_be_bytes().to_vec(),
+                (false, true)  => (ifd_offset as u32).to_le_bytes().to_vec(),
+                (false, false) => (ifd_offset as u32).to_be_bytes().to_vec(),
+            };
+
+            let offset_pos = if is_bigtiff { 8 } else { 4 };
+            header.truncate(offset_pos);
+            header.extend(offset_bytes);
+        }
+
+        Self { data, header, pos: 0 }
+    }
+}
+
+impl<'a> Read for TiffFrameReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = (self.pos as usize).min(self.data.len());
+        let len = buf.len().min(self.data.len() - start);
+
+        buf[..len].copy_from_slice(&self.data[start..start + len]);
+
+        for (i, b) in buf[..len].iter_mut().enumerate() {
+            match self.header.get(start + i) {
+                Some(header_byte) => *b = *header_byte,
+                None => break,
+            }
+        }
+
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<'a> Seek for TiffFrameReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset)   => Some(offset),
+            SeekFrom::End(offset)     => (self.data.len() as u64).checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        match new_pos {
+            Some(v) => {
+                self.pos = v;
+                Ok(v)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use poppler::Document;
+use serde::Serialize;
+
+use crate::metadata;
+use crate::mimetypes;
+use crate::textlayer;
+
+// Document properties are small, larger entries are ignored instead of being decompressed
+const MAX_PROPERTIES_SIZE: u64 = 1024 * 1024;
+
+// Page sizes are in points, as declared by the PDF document
+#[derive(Clone, Debug, Serialize)]
+pub struct PageSize {
+    pub page_num: usize,
+    pub width: f64,
+    pub height: f64,
+}
+
+// Facts about the input for routing documents, page facts are unknown for password-protected documents without their password
+// Other formats than PDF are not converted, their page count is the one declared by their document properties (if any)
+#[derive(Clone, Debug, Serialize)]
+pub struct InspectionReport {
+    pub mime_type: Option<String>,
+    pub mime_confidence: Option<u8>,
+    pub encrypted: bool,
+    pub page_count: Option<usize>,
+    pub page_sizes: Vec<PageSize>,
+    pub producer: Option<String>,
+    pub text_page_count: Option<usize>, // pages with extractable text, which don't need any OCR
+    pub has_text: Option<bool>,
+}
+
+impl InspectionReport {
+    pub fn new(opt_detection: Option<&mimetypes::MimeDetection>, encrypted: bool) -> Self {
+        Self {
+            mime_type: opt_detection.map(|detection| detection.mime_type.to_string()),
+            mime_confidence: opt_detection.map(|detection| detection.confidence),
+            encrypted,
+            page_count: None,
+            page_sizes: Vec::new(),
+            producer: None,
+            text_page_count: None,
+            has_text: None,
+        }
+    }
+
+    pub fn read_pages(&mut self, doc: &Document) {
+        let page_count = doc.n_pages() as usize;
+        let page_numbers: Vec<usize> = (1..=page_count).collect();
+        let text_page_count = textlayer::extract_pages_text(doc, &page_numbers).len();
+
+        self.page_sizes = page_numbers
+            .iter()
+            .filter_map(|page_num| {
+                doc.page((*page_num - 1) as i32).map(|page| {
+                    let (width, height) = page.size();
+                    PageSize { page_num: *page_num, width, height }
+                })
+            })
+            .collect();
+
+        self.producer = doc.producer().and_then(|v| metadata::sanitize_text(&v));
+        self.page_count = Some(page_count);
+        self.text_page_count = Some(text_page_count);
+        self.has_text = Some(text_page_count > 0);
+    }
+}
+
+// PDF files have an encryption dictionary, password-protected Office documents are compound files with an encrypted package
+pub fn is_encrypted(path: &Path, opt_mime_type: Option<&str>) -> bool {
+    if opt_mime_type == Some("application/pdf") {
+        return lopdf::Document::load(path).map(|doc| doc.trailer.has(b"Encrypt")).unwrap_or(false);
+    }
+
+    cfb::open(path).map(|comp| comp.is_stream("/EncryptedPackage")).unwrap_or(false)
+}
+
+// Page counts are read from the document properties, which are written by Office applications and can be wrong or missing
+// Images are converted into a single page
+pub fn declared_page_count(path: &Path, mime_type: &str) -> Option<usize> {
+    match mime_type {
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" |
+        "application/vnd.ms-word.document.macroEnabled.12" => zip_entry_number(path, "docProps/app.xml", "<Pages>"),
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => zip_entry_number(path, "docProps/app.xml", "<Slides>"),
+        "application/vnd.oasis.opendocument.text" => zip_entry_number(path, "meta.xml", "meta:page-count=\""),
+        _ if mime_type.starts_with("image/") => Some(1),
+        _ => None
+    }
+}
+
+// The number right after the first occurrence of the prefix in an archive entry
+fn zip_entry_number(path: &Path, entry_name: &str, prefix: &str) -> Option<usize> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(path).ok()?).ok()?;
+    let mut entry = zip.by_name(entry_name).ok()?;
+
+    if entry.size() > MAX_PROPERTIES_SIZE {
+        return None;
+    }
+
+    let mut data = Vec::new();
+    entry.by_ref().take(MAX_PROPERTIES_SIZE).read_to_end(&mut data).ok()?;
+
+    let text = String::from_utf8_lossy(&data);
+    let (_, value_text) = text.split_once(prefix)?;
+    let digit_count = value_text.chars().take_while(|c| c.is_ascii_digit()).count();
+
+    value_text[..digit_count].parse().ok()
+}
@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use entrusted_l10n as l10n;
+use crate::POINTS_PER_INCH;
+
+// Guards against decompression and page bombs, so that the conversion fails early instead of being OOM-killed
+// The default page area is the largest page size allowed by the PDF specification without scaling (200 x 200 inches)
+// The default total is about 1800 pages in medium visual quality, or 16 GB of uncompressed RGB images
+pub const DEFAULT_MAX_INPUT_SIZE_MB: u64  = 512;
+pub const DEFAULT_MAX_PAGE_COUNT: u64     = 5000;
+pub const DEFAULT_MAX_PAGE_AREA_SQIN: u64 = 40000;
+pub const DEFAULT_MAX_TOTAL_PIXELS: u64   = 4_000_000_000;
+
+// Each rendered page is an image surface of 4 bytes per pixel, i.e. at most 800 MB for a single page
+// Pages rendered in parallel share a budget of 1.6 GB, fewer pages are rendered at once for large surfaces
+pub const MAX_PAGE_PIXELS: u64            = 200_000_000;
+pub const MAX_PARALLEL_PAGE_PIXELS: u64   = 400_000_000;
+
+// Archives are containers of documents, entries are extracted to disk before their conversion
+// Small entries are exempt from the compression ratio limit, short repetitive text compresses very well
+pub const MAX_ARCHIVE_ENTRIES: usize      = 10000;
+pub const MAX_EMBEDDED_FILES: usize       = 1000;
+pub const MAX_COMPRESSION_RATIO: u64      = 100;
+const MIN_COMPRESSION_RATIO_CHECK_SIZE: u64 = 1024 * 1024;
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct ResourceLimitError {
+    message: String,
+}
+
+impl ResourceLimitError {
+    fn new(reason: String, l10n: l10n::Translations) -> Self {
+        Self {
+            message: l10n.gettext_fmt("Resource limit exceeded! {0}", vec![&reason])
+        }
+    }
+}
+
+impl fmt::Display for ResourceLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ResourceLimitError {}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceLimits {
+    pub max_input_size_mb: u64,
+    pub max_page_count: u64,
+    pub max_page_area_sqin: u64,
+    pub max_total_pixels: u64,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            max_input_size_mb: DEFAULT_MAX_INPUT_SIZE_MB,
+            max_page_count: DEFAULT_MAX_PAGE_COUNT,
+            max_page_area_sqin: DEFAULT_MAX_PAGE_AREA_SQIN,
+            max_total_pixels: DEFAULT_MAX_TOTAL_PIXELS,
+        }
+    }
+}
+
+impl ResourceLimits {
+    pub fn check_input_size(&self, input_path: &Path, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        let input_size = fs::metadata(input_path)?.len();
+
+        if input_size > self.max_input_size_mb.saturating_mul(BYTES_PER_MB) {
+            let reason = l10n.gettext_fmt("The input file size ({0} bytes) is above the maximum of {1} MB.", vec![&input_size.to_string(), &self.max_input_size_mb.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_page_count(&self, page_count: usize, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if page_count as u64 > self.max_page_count {
+            let reason = l10n.gettext_fmt("The number of pages to process ({0}) is above the maximum of {1}.", vec![&page_count.to_string(), &self.max_page_count.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    // The page size is expressed in points, as reported by poppler
+    pub fn check_page_area(&self, page_num: usize, page_size: (f64, f64), l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        let page_area_sqin = (page_size.0 / POINTS_PER_INCH) * (page_size.1 / POINTS_PER_INCH);
+
+        if !page_area_sqin.is_finite() || page_area_sqin > self.max_page_area_sqin as f64 {
+            let reason = l10n.gettext_fmt("The area of page {0} ({1} square inches) is above the maximum of {2} square inches.", vec![&page_num.to_string(), &format!("{:.0}", page_area_sqin), &self.max_page_area_sqin.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_total_pixels(&self, pixel_count: u64, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if pixel_count > self.max_total_pixels {
+            let reason = l10n.gettext_fmt("The rendered images would require {0} pixels, which is above the maximum of {1} pixels.", vec![&pixel_count.to_string(), &self.max_total_pixels.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_page_pixels(&self, page_num: usize, pixel_count: u64, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if pixel_count > MAX_PAGE_PIXELS {
+            let reason = l10n.gettext_fmt("The rendered image of page {0} would require {1} pixels, which is above the maximum of {2} pixels.", vec![&page_num.to_string(), &pixel_count.to_string(), &MAX_PAGE_PIXELS.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    // The number of pages rendered in parallel, given the pixel count of the largest page
+    pub fn max_render_jobs(&self, max_page_pixel_count: u64) -> usize {
+        (MAX_PARALLEL_PAGE_PIXELS / max_page_pixel_count.max(1)).max(1) as usize
+    }
+
+    // Extracted archive entries count towards the input size limit, including the entries of nested archives
+    pub fn max_expanded_size(&self) -> u64 {
+        self.max_input_size_mb.saturating_mul(BYTES_PER_MB)
+    }
+
+    pub fn check_expanded_size(&self, expanded_size: u64, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if expanded_size > self.max_expanded_size() {
+            let reason = l10n.gettext_fmt("The extracted archive entries ({0} bytes) are above the maximum of {1} MB.", vec![&expanded_size.to_string(), &self.max_input_size_mb.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_archive_entry_count(&self, entry_count: usize, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if entry_count > MAX_ARCHIVE_ENTRIES {
+            let reason = l10n.gettext_fmt("The number of archive entries ({0}) is above the maximum of {1}.", vec![&entry_count.to_string(), &MAX_ARCHIVE_ENTRIES.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_embedded_file_count(&self, file_count: usize, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if file_count > MAX_EMBEDDED_FILES {
+            let reason = l10n.gettext_fmt("The number of embedded files ({0}) is above the maximum of {1}.", vec![&file_count.to_string(), &MAX_EMBEDDED_FILES.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+
+    // Entries are read up to the compression ratio limit, archive bombs are detected before being fully extracted
+    pub fn max_expanded_entry_size(&self, compressed_size: u64) -> u64 {
+        compressed_size.saturating_mul(MAX_COMPRESSION_RATIO).max(MIN_COMPRESSION_RATIO_CHECK_SIZE)
+    }
+
+    pub fn check_compression_ratio(&self, entry_name: &str, compressed_size: u64, expanded_size: u64, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        if expanded_size > MIN_COMPRESSION_RATIO_CHECK_SIZE && expanded_size > compressed_size.saturating_mul(MAX_COMPRESSION_RATIO) {
+            let reason = l10n.gettext_fmt("The compression ratio of the archive entry {0} is above the maximum of {1}.", vec![entry_name, &MAX_COMPRESSION_RATIO.to_string()]);
+            return Err(ResourceLimitError::new(reason, l10n).into());
+        }
+
+        Ok(())
+    }
+}
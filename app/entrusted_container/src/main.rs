@@ -1,24 +1,42 @@
-use clap::{Command, Arg, builder::PossibleValue};
+use clap::{Command, Arg, ArgAction, builder::PossibleValue};
 use cairo::{Context, Format, ImageSurface, PdfSurface};
 use std::env;
 use uuid::Uuid;
 use poppler::Document;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::ffi::CString;
 use std::fs;
-use serde::{Deserialize, Serialize};
 use std::io::{BufReader, Cursor, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 use libreoffice_rs::{Office, LibreOfficeKitOptionalFeatures, urls};
 use once_cell::sync::OnceCell;
 
 use entrusted_l10n as l10n;
 
+mod analysis;
+mod archives;
+mod colormode;
+mod ebooks;
+mod emails;
+mod embedded;
+mod errors;
+mod events;
+mod imgframes;
+mod imgencoding;
+mod inspection;
+mod limits;
+mod metadata;
 mod mimetypes;
+mod ocrsidecars;
+mod outline;
+mod pdfa;
+mod textdocs;
+mod textlayer;
 
 const LOG_FORMAT_PLAIN: &str = "plain";
 const LOG_FORMAT_JSON: &str = "json";
@@ -33,12 +51,24 @@ const ENV_VAR_ENTRUSTED_TESSERACT_TESSDATA_DIR: &str  = "ENTRUSTED_TESSERACT_TES
 const ENV_VAR_ENTRUSTED_LIBREOFFICE_PROGRAM_DIR: &str = "ENTRUSTED_LIBREOFFICE_PROGRAM_DIR";
 const ENV_VAR_ENTRUSTED_DOC_PASSWD: &str              = "ENTRUSTED_DOC_PASSWD";
 
+// E-mail messages and archives can contain other messages or archives, hostile inputs can nest them very deeply
+const MAX_NESTING_DEPTH: usize = 3;
+
 // A4 150PPI/DPI
 // See https://www.a4-size.com/a4-size-in-pixels/?size=a4&unit=px&ppi=150
 const IMAGE_SIZE_QUALITY_LOW: (f64, f64)    = (794.0  , 1123.0);
 const IMAGE_SIZE_QUALITY_MEDIUM: (f64, f64) = (1240.0 , 1754.0);
 const IMAGE_SIZE_QUALITY_HIGH: (f64, f64)   = (4961.0 , 7016.0);
 
+// Same resolutions as the A4 sizes above, used when the original page size is preserved
+const IMAGE_DPI_QUALITY_LOW: f64    = 96.0;
+const IMAGE_DPI_QUALITY_MEDIUM: f64 = 150.0;
+const IMAGE_DPI_QUALITY_HIGH: f64   = 600.0;
+
+const POINTS_PER_INCH: f64 = 72.0;
+const MIN_DPI: u64 = 70;
+const MAX_DPI: u64 = 2400;
+
 static INSTANCE_DEFAULT_VISUAL_QUALITY: OnceCell<String> = OnceCell::new();
 
 macro_rules! incl_gettext_files {
@@ -60,6 +90,30 @@ enum ConversionType {
     None,
     LibreOffice(&'static str), // file_extension
     Convert,
+    Email,
+    Archive,
+}
+
+// Pages are either scaled to fit a pixel box, or rendered at a fixed DPI
+// Either way the output PDF pages keep the physical size of the original pages
+#[derive(Clone, Copy, Debug)]
+enum RenderSize {
+    Fit(f64, f64), // width and height in pixels
+    Dpi(f64),
+}
+
+impl RenderSize {
+    // Returns the scaling ratio (pixels per point) and the image size in pixels
+    fn scaling_data(&self, page_size: (f64, f64)) -> (f64, (f64, f64)) {
+        match *self {
+            RenderSize::Fit(width, height) => scaling_data(page_size, (width, height)),
+            RenderSize::Dpi(dpi) => {
+                let ratio = dpi / POINTS_PER_INCH;
+                (ratio, ((page_size.0 * ratio).max(1.0), (page_size.1 * ratio).max(1.0)))
+            }
+        }
+    }
+
 }
 
 struct TessSettings<'a> {
@@ -76,10 +130,25 @@ struct ExecCtx {
     doc_uuid: String,
     root_tmp_dir: PathBuf,
     input_path: PathBuf,
+    input_extension: Option<String>,
     output_path: PathBuf,
     visual_quality: String,
     ocr_lang: Option<String>,
     doc_passwd: Option<String>,
+    jobs: usize,
+    pipeline: bool,
+    preserve_page_size: bool,
+    dpi: Option<u64>,
+    image_size: Option<String>,
+    page_ranges: Option<String>,
+    image_encoding: imgencoding::ImageEncoding,
+    color_mode: colormode::ColorMode,
+    pdfa: bool,
+    strip_metadata: bool,
+    analyze: bool,
+    ocr_sidecars: Vec<String>,
+    text_layer: textlayer::TextLayer,
+    limits: limits::ResourceLimits,
     l10n: l10n::Translations,
     logger: Box<dyn ConversionLogger>,
 }
@@ -98,10 +167,30 @@ fn main() -> Result<(), Box<dyn Error>> {
     let l10n = l10n::new_translations(locale);
 
     let help_input_filename = l10n.gettext("Input filename");
+    let help_input_extension = l10n.gettext("Optional original file extension of the input (i.e. 'csv'), when the input filename doesn't have it");
     let help_output_filename = l10n.gettext("Optional output filename defaulting to <filename>-entrusted.pdf.");
     let help_visual_quality = l10n.gettext("PDF result visual quality");
     let help_ocr_lang = l10n.gettext("Optional language for OCR (i.e. 'eng' for English)");
     let help_log_format = l10n.gettext("Log format (json or plain)");
+    let help_jobs = l10n.gettext("Number of pages to render in parallel (defaults to the number of available CPUs)");
+    let help_pages = l10n.gettext("Optional selection of pages to sanitize (i.e. '1-5,9,12-')");
+    let help_pipeline = l10n.gettext("Process pages one at a time to limit temporary disk usage (ignores the jobs setting, the result is still combined in memory)");
+    let help_preserve_page_size = l10n.gettext("Keep the original size and orientation of each page, rendered at the DPI of the visual quality");
+    let help_dpi = l10n.gettext_fmt("Custom rendering resolution in DPI, between {0} and {1} (keeps the original page size and overrides the visual quality)", vec![&MIN_DPI.to_string(), &MAX_DPI.to_string()]);
+    let help_image_size = l10n.gettext("Custom pixel box to fit pages into, as width x height (i.e. '2480x3508', overrides the visual quality)");
+    let help_image_encoding = l10n.gettext("Encoding of the page images in the PDF result (lossless, jpeg or bilevel for text documents)");
+    let help_jpeg_quality = l10n.gettext_fmt("JPEG quality between 1 and 100 for the jpeg image encoding (defaults to {0})", vec![&imgencoding::DEFAULT_JPEG_QUALITY.to_string()]);
+    let help_color_mode = l10n.gettext("Color mode of the rendered pages (color, grayscale or bw for black and white)");
+    let help_pdfa = l10n.gettext("Produce a PDF/A-2b document for long-term archiving");
+    let help_strip_metadata = l10n.gettext("Do not carry over the document title, author, subject and creation date");
+    let help_inspect = l10n.gettext("Print facts about the input as JSON (type, pages, encryption, text) without converting it");
+    let help_analyze = l10n.gettext("Save a JSON risk report of the original document next to the PDF result (i.e. scripts, macros, embedded files)");
+    let help_text_layer = l10n.gettext("Optional source of the searchable text (ocr or original, which falls back to OCR for pages without text)");
+    let help_ocr_sidecars = l10n.gettext("Optional comma-separated list of OCR text files to save next to the PDF result (txt, hocr, alto)");
+    let help_max_input_size = l10n.gettext_fmt("Maximum input file size in megabytes (defaults to {0})", vec![&limits::DEFAULT_MAX_INPUT_SIZE_MB.to_string()]);
+    let help_max_pages = l10n.gettext_fmt("Maximum number of pages to process (defaults to {0})", vec![&limits::DEFAULT_MAX_PAGE_COUNT.to_string()]);
+    let help_max_page_area = l10n.gettext_fmt("Maximum page area in square inches (defaults to {0})", vec![&limits::DEFAULT_MAX_PAGE_AREA_SQIN.to_string()]);
+    let help_max_total_pixels = l10n.gettext_fmt("Maximum number of pixels to render for all pages (defaults to {0})", vec![&limits::DEFAULT_MAX_TOTAL_PIXELS.to_string()]);
 
     let cmd_help_template = l10n.gettext(&format!("{}\n{}\n{}\n\n{}\n\n{}\n{}",
                                                   "{bin} {version}",
@@ -124,6 +213,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .help(help_input_filename)
                 .required(false)
                 .default_value("/tmp/input_file")
+        ).arg(
+            Arg::new("input-extension")
+                .long("input-extension")
+                .help(help_input_extension)
+                .required(false)
         ).arg(
             Arg::new("output-filename")
                 .long("output-filename")
@@ -156,6 +250,141 @@ fn main() -> Result<(), Box<dyn Error>> {
                 ])
                 .default_value(default_visual_quality_to_str())
                 .required(false)
+        ).arg(
+            Arg::new("jobs")
+                .long("jobs")
+                .help(help_jobs)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("pages")
+                .long("pages")
+                .help(help_pages)
+                .required(false)
+        ).arg(
+            Arg::new("pipeline")
+                .long("pipeline")
+                .help(help_pipeline)
+                .action(ArgAction::SetTrue)
+                .required(false)
+        ).arg(
+            Arg::new("preserve-page-size")
+                .long("preserve-page-size")
+                .help(help_preserve_page_size)
+                .action(ArgAction::SetTrue)
+                .required(false)
+        ).arg(
+            Arg::new("dpi")
+                .long("dpi")
+                .help(help_dpi)
+                .value_parser(clap::value_parser!(u64).range(MIN_DPI..=MAX_DPI))
+                .conflicts_with("image-size")
+                .required(false)
+        ).arg(
+            Arg::new("image-size")
+                .long("image-size")
+                .help(help_image_size)
+                .conflicts_with("preserve-page-size")
+                .required(false)
+        ).arg(
+            Arg::new("image-encoding")
+                .long("image-encoding")
+                .help(help_image_encoding)
+                .value_parser([
+                    PossibleValue::new(imgencoding::IMAGE_ENCODING_CHOICES[0]),
+                    PossibleValue::new(imgencoding::IMAGE_ENCODING_CHOICES[1]),
+                    PossibleValue::new(imgencoding::IMAGE_ENCODING_CHOICES[2]),
+                ])
+                .default_value(imgencoding::IMAGE_ENCODING_LOSSLESS)
+                .required(false)
+        ).arg(
+            Arg::new("jpeg-quality")
+                .long("jpeg-quality")
+                .help(help_jpeg_quality)
+                .value_parser(clap::value_parser!(u8).range(1..=100))
+                .required(false)
+        ).arg(
+            Arg::new("color-mode")
+                .long("color-mode")
+                .help(help_color_mode)
+                .value_parser([
+                    PossibleValue::new(colormode::COLOR_MODE_CHOICES[0]),
+                    PossibleValue::new(colormode::COLOR_MODE_CHOICES[1]),
+                    PossibleValue::new(colormode::COLOR_MODE_CHOICES[2]),
+                ])
+                .default_value(colormode::COLOR_MODE_COLOR)
+                .required(false)
+        ).arg(
+            Arg::new("pdfa")
+                .long("pdfa")
+                .help(help_pdfa)
+                .action(ArgAction::SetTrue)
+                .required(false)
+        ).arg(
+            Arg::new("strip-metadata")
+                .long("strip-metadata")
+                .help(help_strip_metadata)
+                .action(ArgAction::SetTrue)
+                .required(false)
+        ).arg(
+            Arg::new("ocr-sidecars")
+                .long("ocr-sidecars")
+                .help(help_ocr_sidecars)
+                .value_parser([
+                    PossibleValue::new(ocrsidecars::OCR_SIDECAR_CHOICES[0]),
+                    PossibleValue::new(ocrsidecars::OCR_SIDECAR_CHOICES[1]),
+                    PossibleValue::new(ocrsidecars::OCR_SIDECAR_CHOICES[2]),
+                ])
+                .value_delimiter(',')
+                .action(ArgAction::Append)
+                .requires("ocr-lang")
+                .required(false)
+        ).arg(
+            Arg::new("text-layer")
+                .long("text-layer")
+                .help(help_text_layer)
+                .value_parser([
+                    PossibleValue::new(textlayer::TEXT_LAYER_CHOICES[0]),
+                    PossibleValue::new(textlayer::TEXT_LAYER_CHOICES[1]),
+                ])
+                .default_value(textlayer::TEXT_LAYER_OCR)
+                .required(false)
+        ).arg(
+            Arg::new("inspect")
+                .long("inspect")
+                .help(help_inspect)
+                .action(ArgAction::SetTrue)
+                .required(false)
+        ).arg(
+            Arg::new("analyze")
+                .long("analyze")
+                .help(help_analyze)
+                .action(ArgAction::SetTrue)
+                .required(false)
+        ).arg(
+            Arg::new("max-input-size")
+                .long("max-input-size")
+                .help(help_max_input_size)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("max-pages")
+                .long("max-pages")
+                .help(help_max_pages)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("max-page-area")
+                .long("max-page-area")
+                .help(help_max_page_area)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        ).arg(
+            Arg::new("max-total-pixels")
+                .long("max-total-pixels")
+                .help(help_max_total_pixels)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
         );
 
     let run_matches = app.get_matches();
@@ -166,6 +395,8 @@ fn main() -> Result<(), Box<dyn Error>> {
         PathBuf::from("/tmp/input_file")
     };
 
+    let input_extension = run_matches.get_one::<String>("input-extension").and_then(|v| mimetypes::sanitize_file_extension(v));
+
     let output_path = if let Some(v) = run_matches.get_one::<String>("output-filename") {
         PathBuf::from(v)
     } else {
@@ -186,6 +417,61 @@ fn main() -> Result<(), Box<dyn Error>> {
         LOG_FORMAT_JSON.to_string()
     };
 
+    let jobs = if let Some(v) = run_matches.get_one::<u64>("jobs") {
+        *v as usize
+    } else {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    };
+
+    let pipeline = run_matches.get_flag("pipeline");
+    let pdfa = run_matches.get_flag("pdfa");
+    let strip_metadata = run_matches.get_flag("strip-metadata");
+    let analyze = run_matches.get_flag("analyze");
+    let inspect = run_matches.get_flag("inspect");
+    let text_layer = if let Some(v) = run_matches.get_one::<String>("text-layer") {
+        textlayer::TextLayer::from_name(v)
+    } else {
+        textlayer::TextLayer::Ocr
+    };
+    let ocr_sidecars: Vec<String> = if let Some(v) = run_matches.get_many::<String>("ocr-sidecars") {
+        v.cloned().collect()
+    } else {
+        vec![]
+    };
+    let preserve_page_size = run_matches.get_flag("preserve-page-size");
+    let dpi = run_matches.get_one::<u64>("dpi").copied();
+    let image_size = run_matches.get_one::<String>("image-size").cloned();
+    let page_ranges = run_matches.get_one::<String>("pages").cloned();
+    let jpeg_quality = run_matches.get_one::<u8>("jpeg-quality").copied().unwrap_or(imgencoding::DEFAULT_JPEG_QUALITY);
+    let image_encoding = if let Some(v) = run_matches.get_one::<String>("image-encoding") {
+        imgencoding::ImageEncoding::parse(v, jpeg_quality, l10n.clone())?
+    } else {
+        imgencoding::ImageEncoding::Lossless
+    };
+    let color_mode = if let Some(v) = run_matches.get_one::<String>("color-mode") {
+        colormode::ColorMode::from_name(v)
+    } else {
+        colormode::ColorMode::Color
+    };
+
+    let mut limits = limits::ResourceLimits::default();
+
+    if let Some(v) = run_matches.get_one::<u64>("max-input-size") {
+        limits.max_input_size_mb = *v;
+    }
+
+    if let Some(v) = run_matches.get_one::<u64>("max-pages") {
+        limits.max_page_count = *v;
+    }
+
+    if let Some(v) = run_matches.get_one::<u64>("max-page-area") {
+        limits.max_page_area_sqin = *v;
+    }
+
+    if let Some(v) = run_matches.get_one::<u64>("max-total-pixels") {
+        limits.max_total_pixels = *v;
+    }
+
     let doc_passwd = if let Ok(v) = env::var(ENV_VAR_ENTRUSTED_DOC_PASSWD) {
         if !v.is_empty() {
             Some(v)
@@ -202,7 +488,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let logger: Box<dyn ConversionLogger> = match log_format.as_str() {
         "json" => {
-            Box::new(JsonConversionLogger)
+            Box::new(JsonConversionLogger { started: timer })
         },
         _ => Box::new(PlainConversionLogger)
     };
@@ -211,23 +497,52 @@ fn main() -> Result<(), Box<dyn Error>> {
         root_tmp_dir,
         doc_uuid,
         input_path,
+        input_extension,
         output_path,
         visual_quality,
         ocr_lang,
         doc_passwd,
+        jobs,
+        pipeline,
+        preserve_page_size,
+        dpi,
+        image_size,
+        page_ranges,
+        image_encoding,
+        color_mode,
+        pdfa,
+        strip_metadata,
+        analyze,
+        ocr_sidecars,
+        text_layer,
+        limits,
         l10n: l10n.clone(),
         logger: logger.clone_box()
     };
     
-    let mut exit_code = 0;
-    let msg: String = if let Err(ex) = execute(ctx) {
-        exit_code = 1;
-        l10n.gettext_fmt("Conversion failed with reason: {0}", vec![&ex.to_string()])
-    } else {
-        l10n.gettext("Conversion succeeded!")
-    };
+    // The inspection report is the only output on success, so that it can be read as-is
+    if inspect {
+        if let Err(ex) = inspect_input(ctx) {
+            let error_code = errors::error_code(ex.as_ref());
+            logger.log_event(events::ProgressEvent::error(100, l10n.gettext_fmt("Inspection failed with reason: {0}", vec![&ex.to_string()]), error_code.name()));
+            std::process::exit(error_code.exit_code());
+        }
 
-    logger.log(99, msg);
+        return Ok(());
+    }
+
+    // The error code is logged with the failure message, and it determines the exit code
+    let exit_code = match execute(ctx) {
+        Ok(conversion_result) => {
+            logger.log_event(events::ProgressEvent::result(99, l10n.gettext("Conversion succeeded!"), conversion_result));
+            0
+        },
+        Err(ex) => {
+            let error_code = errors::error_code(ex.as_ref());
+            logger.log_event(events::ProgressEvent::error(99, l10n.gettext_fmt("Conversion failed with reason: {0}", vec![&ex.to_string()]), error_code.name()));
+            error_code.exit_code()
+        }
+    };
 
     let millis = timer.elapsed().as_millis();
     logger.log(100, format!("{}: {}", l10n.gettext("Elapsed time"), elapsed_time_string(millis, l10n)));
@@ -235,13 +550,23 @@ fn main() -> Result<(), Box<dyn Error>> {
     std::process::exit(exit_code);
 }
 
-fn execute(ctx: ExecCtx) -> Result<(), Box<dyn Error>> {
+fn execute(ctx: ExecCtx) -> Result<events::ConversionResult, Box<dyn Error>> {
     let document_password = ctx.doc_passwd;
-    let image_quality = match ctx.visual_quality.as_str() {
-        "low"    => IMAGE_SIZE_QUALITY_LOW,
-        "medium" => IMAGE_SIZE_QUALITY_MEDIUM,
-        "high"   => IMAGE_SIZE_QUALITY_HIGH,
-        _        => IMAGE_SIZE_QUALITY_MEDIUM
+    let (image_quality, image_dpi) = match ctx.visual_quality.as_str() {
+        "low"    => (IMAGE_SIZE_QUALITY_LOW, IMAGE_DPI_QUALITY_LOW),
+        "medium" => (IMAGE_SIZE_QUALITY_MEDIUM, IMAGE_DPI_QUALITY_MEDIUM),
+        "high"   => (IMAGE_SIZE_QUALITY_HIGH, IMAGE_DPI_QUALITY_HIGH),
+        _        => (IMAGE_SIZE_QUALITY_MEDIUM, IMAGE_DPI_QUALITY_MEDIUM)
+    };
+    let render_size = if let Some(dpi) = ctx.dpi {
+        RenderSize::Dpi(dpi as f64)
+    } else if let Some(image_size) = ctx.image_size.as_deref() {
+        let (width, height) = parse_image_size(image_size, ctx.l10n.clone())?;
+        RenderSize::Fit(width, height)
+    } else if ctx.preserve_page_size {
+        RenderSize::Dpi(image_dpi)
+    } else {
+        RenderSize::Fit(image_quality.0, image_quality.1)
     };
     let doc_uuid = ctx.doc_uuid;
     let l10n = ctx.l10n;
@@ -258,81 +583,211 @@ fn execute(ctx: ExecCtx) -> Result<(), Box<dyn Error>> {
         return Err(l10n.gettext_fmt("Cannot temporary folder: {0}! Error: {1}", vec![&root_tmp_dir.display().to_string(), &ex.to_string()]).into());
     }    
 
+    if raw_input_path.exists() {
+        ctx.limits.check_input_size(&raw_input_path, l10n.clone())?;
+    }
+
+    // The report describes the original document, conversion failures don't prevent it
+    if ctx.analyze {
+        analyze_input(&*logger, &raw_input_path, ctx.input_extension.as_deref(), &safe_dir_path, l10n.clone());
+    }
+
     // step 1 (0%-20%)
     let mut progress_range = ProgressRange::new(0, 20);
-    let input_file_path = input_as_pdf_to_pathbuf_uri(&*logger, &progress_range, raw_input_path, document_password.clone(), l10n.clone())?;
+    logger.log_event(events::ProgressEvent::phase_started(progress_range.min, l10n.gettext("Converting the input document to PDF"), events::Phase::InputConversion, None));
+    let input_file_path = input_as_pdf_to_pathbuf_uri(&*logger, &progress_range, raw_input_path, ctx.input_extension.as_deref(), document_password.clone(), &ctx.limits, l10n.clone())?;
 
-    let doc = if let Some(passwd) = document_password {
-        // We only care about originally encrypted PDF files
-        // If the document was in another format, then it's already decrypted at this stage
-        // Providing a password for a non-encrypted document doesn't fail, and that removes the need for additional logic
-        Document::from_file(&input_file_path.display().to_string(), Some(&passwd))?
+    // We only care about originally encrypted PDF files
+    // If the document was in another format, then it's already decrypted at this stage
+    // Providing a password for a non-encrypted document doesn't fail, and that removes the need for additional logic
+    let doc = Document::from_file(&input_file_path.display().to_string(), document_password.as_deref())?;
+    let page_count = doc.n_pages() as usize;
+    let page_numbers: Vec<usize> = if let Some(page_ranges) = ctx.page_ranges.as_deref() {
+        parse_page_ranges(page_ranges, page_count, l10n.clone())?
     } else {
-        Document::from_file(&input_file_path.display().to_string(), None)?
+        (1..=page_count).collect()
     };
 
-    let page_count = doc.n_pages() as usize;
+    let max_page_pixel_count = check_page_limits(&page_numbers, &doc, render_size, &ctx.limits, l10n.clone())?;
 
-    // step 2 (20%-45%)
-    progress_range.update(20, 45);
-    split_pdf_pages_into_images(&*logger, &progress_range, page_count, doc, image_quality, output_dir_path.clone(), l10n.clone())?;
+    // Only a few document properties are kept, after removing any control characters
+    let doc_metadata = if ctx.strip_metadata {
+        metadata::DocumentMetadata::default()
+    } else {
+        metadata::DocumentMetadata::read(&doc)
+    };
+    let doc_outline = outline::read_outline(&doc);
 
-    // step 3 (45%-90%)
-    progress_range.update(45, 90);
+    let provided_tessdata_dir = if let Ok(tessdata_dir) = env::var(ENV_VAR_ENTRUSTED_TESSERACT_TESSDATA_DIR) {
+        tessdata_dir
+    } else {
+        DEFAULT_DIR_TESSERACT_TESSDATA.to_string()
+    };
 
-    if let Some(v) = ctx.ocr_lang {
-        let ocr_lang_text = v.as_str();
+    let opt_tess_settings = if let Some(ocr_lang_text) = ctx.ocr_lang.as_deref() {
         let selected_langcodes: Vec<&str> = ocr_lang_text.split('+').collect();
 
         for selected_langcode in selected_langcodes {
             if !l10n::ocr_lang_key_by_name(&l10n).contains_key(&selected_langcode) {
-                return Err(l10n.gettext_fmt("Unknown language code for the ocr-lang parameter: {0}. Hint: Try 'eng' for English.", vec![selected_langcode]).into());
+                return Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("Unknown language code for the ocr-lang parameter: {0}. Hint: Try 'eng' for English.", vec![selected_langcode])).into());
             }
         }
 
-        let provided_tessdata_dir = if let Ok(tessdata_dir) = env::var(ENV_VAR_ENTRUSTED_TESSERACT_TESSDATA_DIR) {
-            tessdata_dir
-        } else {
-            DEFAULT_DIR_TESSERACT_TESSDATA.to_string()
-        };
-
-        let tess_settings = TessSettings {
+        Some(TessSettings {
             lang: ocr_lang_text,
             data_dir: &provided_tessdata_dir
-        };
+        })
+    } else {
+        None
+    };
 
-        ocr_imgs_to_pdf(&*logger, &progress_range, page_count, tess_settings, output_dir_path.clone(), output_dir_path.clone(), l10n.clone())?;
+    let mut ocr_sidecars = ocrsidecars::OcrSidecars::new(&ctx.ocr_sidecars);
+
+    if ctx.pipeline {
+        // step 2 (20%-90%)
+        progress_range.update(20, 90);
+        let pdf_combiner = pipeline_pages_to_pdf(&*logger, &progress_range, &page_numbers, &doc, render_size, ctx.color_mode, &ctx.limits, ctx.image_encoding, ctx.text_layer, opt_tess_settings, &mut ocr_sidecars, output_dir_path, l10n.clone())?;
+
+        // step 3 (90%-98%)
+        progress_range.update(90, 98);
+        logger.log_event(events::ProgressEvent::phase_started(progress_range.min,
+                                                              l10n.ngettext("Combining one PDF document",
+                                                                            "Combining few PDF documents",
+                                                                            page_numbers.len() as u64),
+                                                              events::Phase::Combining,
+                                                              Some(page_numbers.len())));
+        pdf_combiner.save(&*logger, &progress_range, output_file_path.clone(), ctx.pdfa, &doc_metadata, &doc_outline, l10n.clone())?;
     } else {
-        imgs_to_pdf(&*logger, &progress_range, page_count, output_dir_path.clone(), output_dir_path.clone(), l10n.clone())?;
-    }
+        // step 2 (20%-45%)
+        progress_range.update(20, 45);
+        // Large pages allocate large image surfaces, fewer of them are rendered in parallel
+        let jobs = ctx.jobs.min(ctx.limits.max_render_jobs(max_page_pixel_count));
+        let page_dpis = split_pdf_pages_into_images(&*logger, &progress_range, &page_numbers, &input_file_path, document_password.as_deref(), render_size, ctx.color_mode, jobs, &ctx.limits, output_dir_path.clone(), l10n.clone())?;
+
+        // step 3 (45%-90%)
+        progress_range.update(45, 90);
+
+        // Pages with extractable text keep their original text, the other pages go through OCR when it's enabled
+        let page_texts = if let textlayer::TextLayer::Original = ctx.text_layer {
+            textlayer::extract_pages_text(&doc, &page_numbers)
+        } else {
+            BTreeMap::new()
+        };
+        let (text_page_numbers, other_page_numbers): (Vec<usize>, Vec<usize>) = page_numbers.iter().copied().partition(|page_num| page_texts.contains_key(page_num));
+
+        if !text_page_numbers.is_empty() {
+            imgs_to_pdf(&*logger, &progress_range, &text_page_numbers, &page_dpis, ctx.image_encoding, output_dir_path.clone(), output_dir_path.clone(), l10n.clone())?;
 
-    // step 4 (90%-98%)
-    progress_range.update(90, 98);
-    pdf_combine_pdfs(&*logger, &progress_range, page_count, output_dir_path, output_file_path.clone(), l10n.clone())?;
+            for (page_num, page_text) in page_texts.iter() {
+                textlayer::add_text_layer_to_file(&output_dir_path.join(format!("page-{}.pdf", page_num)), page_text)?;
+            }
+        }
+
+        if !other_page_numbers.is_empty() {
+            if let Some(tess_settings) = opt_tess_settings {
+                ocr_imgs_to_pdf(&*logger, &progress_range, &other_page_numbers, tess_settings, &page_dpis, ctx.image_encoding, &mut ocr_sidecars, output_dir_path.clone(), output_dir_path.clone(), l10n.clone())?;
+            } else {
+                imgs_to_pdf(&*logger, &progress_range, &other_page_numbers, &page_dpis, ctx.image_encoding, output_dir_path.clone(), output_dir_path.clone(), l10n.clone())?;
+            }
+        }
+
+        // step 4 (90%-98%)
+        progress_range.update(90, 98);
+        pdf_combine_pdfs(&*logger, &progress_range, &page_numbers, output_dir_path, output_file_path.clone(), ctx.pdfa, &doc_metadata, &doc_outline, l10n.clone())?;
+    }
 
     // step 5 (98%-98%)
     progress_range.update(98, 98);
-    move_file_to_dir(&*logger, &progress_range, output_file_path, safe_dir_path, l10n)
+    move_file_to_dir(&*logger, &progress_range, output_file_path, safe_dir_path.clone(), l10n.clone())?;
+
+    for sidecar_path in ocr_sidecars.save(&safe_dir_path)? {
+        logger.log(progress_range.min, l10n.gettext_fmt("Saved OCR text file {0}", vec![&sidecar_path.display().to_string()]));
+    }
+
+    Ok(events::ConversionResult {
+        page_count: page_numbers.len(),
+        output_size: fs::metadata(&safe_dir_path)?.len()
+    })
+}
+
+// Only PDF files are opened for their page facts, other formats are neither converted nor rendered
+fn inspect_input(ctx: ExecCtx) -> Result<(), Box<dyn Error>> {
+    let l10n = ctx.l10n;
+    let input_path = ctx.input_path;
+
+    if !input_path.exists() {
+        return Err(errors::ConversionError::new(errors::ErrorCode::InputNotFound, l10n.gettext_fmt("Cannot find file at {0}", vec![&input_path.display().to_string()])).into());
+    }
+
+    ctx.limits.check_input_size(&input_path, l10n.clone())?;
+
+    let opt_detection = mimetypes::detect(&input_path, ctx.input_extension.as_deref(), l10n.clone())?;
+    let opt_mime_type = opt_detection.as_ref().map(|detection| detection.mime_type);
+    let mut report = inspection::InspectionReport::new(opt_detection.as_ref(), inspection::is_encrypted(&input_path, opt_mime_type));
+
+    if opt_mime_type == Some("application/pdf") {
+        let pdf_uri = format!("file://{}", fs::canonicalize(&input_path)?.display());
+
+        match Document::from_file(&pdf_uri, ctx.doc_passwd.as_deref()) {
+            Ok(doc) => report.read_pages(&doc),
+            Err(_) if report.encrypted => {},
+            Err(ex) => return Err(ex.into())
+        }
+    } else if let Some(mime_type) = opt_mime_type {
+        report.page_count = inspection::declared_page_count(&input_path, mime_type);
+    }
+
+    println!("{}", serde_json::to_string(&report)?);
+
+    Ok(())
+}
+
+// Analysis failures are only reported, the document is still converted
+fn analyze_input(logger: &dyn ConversionLogger, input_path: &Path, opt_input_ext: Option<&str>, pdf_path: &Path, l10n: l10n::Translations) {
+    logger.log_event(events::ProgressEvent::phase_started(0, l10n.gettext("Analyzing the original document"), events::Phase::Analysis, None));
+
+    let report = match analysis::analyze(input_path, opt_input_ext, l10n.clone()) {
+        Ok(v) => v,
+        Err(ex) => {
+            logger.log_event(events::ProgressEvent::warning(0, l10n.gettext_fmt("Could not analyze the document: {0}", vec![&ex.to_string()])));
+            return;
+        }
+    };
+
+    logger.log(0, l10n.gettext_fmt("Risk level of the original document: {0}", vec![&report.risk_level.display_name(l10n.clone())]));
+
+    for finding in report.findings.iter() {
+        logger.log(0, l10n.gettext_fmt("Found {0} ({1} risk, {2} occurrences)", vec![&finding.description, &finding.risk_level.display_name(l10n.clone()), &finding.count.to_string()]));
+    }
+
+    if let Ok(report_json) = serde_json::to_string(&report) {
+        logger.log(0, report_json);
+    }
+
+    match report.save(pdf_path) {
+        Ok(report_path) => logger.log(0, l10n.gettext_fmt("Saved analysis report {0}", vec![&report_path.display().to_string()])),
+        Err(ex) => logger.log_event(events::ProgressEvent::warning(0, l10n.gettext_fmt("Could not save the analysis report: {0}", vec![&ex.to_string()]))),
+    }
 }
 
 fn move_file_to_dir(logger: &dyn ConversionLogger, progress_range: &ProgressRange, src_file_path: PathBuf, dest_dir_path: PathBuf, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    logger.log_event(events::ProgressEvent::phase_started(progress_range.min, l10n.gettext("Moving output files to their final destination"), events::Phase::Output, None));
+
     if let Err(ex) = fs::copy(&src_file_path, &dest_dir_path) {
-        logger.log(progress_range.min, l10n.gettext_fmt("Failed to copy file from {0} to {1}", vec![&src_file_path.display().to_string(), &dest_dir_path.display().to_string()]));
+        logger.log_event(events::ProgressEvent::warning(progress_range.min, l10n.gettext_fmt("Failed to copy file from {0} to {1}", vec![&src_file_path.display().to_string(), &dest_dir_path.display().to_string()])));
         return Err(ex.into());
     }
 
     if let Err(ex) = fs::remove_file(&src_file_path) {
-        logger.log(progress_range.min, l10n.gettext_fmt("Failed to remove file from {0}.", vec![&src_file_path.display().to_string()]));
+        logger.log_event(events::ProgressEvent::warning(progress_range.min, l10n.gettext_fmt("Failed to remove file from {0}.", vec![&src_file_path.display().to_string()])));
         return Err(ex.into());
     }
 
-    logger.log(progress_range.min, l10n.gettext("Moving output files to their final destination"));
-
     Ok(())
 }
 
-fn input_as_pdf_to_pathbuf_uri(logger: &dyn ConversionLogger, _: &ProgressRange, raw_input_path: PathBuf, opt_passwd: Option<String>, l10n: l10n::Translations) -> Result<PathBuf, Box<dyn Error>> {
-    let conversion_by_mimetype: HashMap<&str, ConversionType> = [
+fn conversion_by_mimetype() -> HashMap<&'static str, ConversionType> {
+    [
         ("application/pdf", ConversionType::None),
         (
             "application/rtf",
@@ -380,22 +835,50 @@ fn input_as_pdf_to_pathbuf_uri(logger: &dyn ConversionLogger, _: &ProgressRange,
             "application/vnd.oasis.opendocument.spreadsheet",
             ConversionType::LibreOffice("ods"),
         ),
+        ("application/x-fictionbook+xml", ConversionType::LibreOffice("fb2")),
+        // LibreOffice doesn't import EPUB, chapters are merged into a single HTML document
+        ("application/epub+zip", ConversionType::LibreOffice("html")),
+        ("text/plain",    ConversionType::LibreOffice("txt")),
+        // LibreOffice doesn't import Markdown, the source is rendered as-is
+        ("text/markdown", ConversionType::LibreOffice("txt")),
+        ("text/csv",      ConversionType::LibreOffice("csv")),
+        ("text/html",     ConversionType::LibreOffice("html")),
+        ("message/rfc822",             ConversionType::Email),
+        ("application/vnd.ms-outlook", ConversionType::Email),
+        ("application/zip",    ConversionType::Archive),
+        ("application/x-tar",  ConversionType::Archive),
+        ("application/gzip",   ConversionType::Archive),
         ("image/jpeg",   ConversionType::Convert),
         ("image/gif",    ConversionType::Convert),
         ("image/png",    ConversionType::Convert),
         ("image/tiff",   ConversionType::Convert),
         ("image/x-tiff", ConversionType::Convert),
+        ("image/webp",   ConversionType::Convert),
+        ("image/bmp",    ConversionType::Convert),
+        ("image/vnd.microsoft.icon", ConversionType::Convert),
+        ("image/x-portable-bitmap",  ConversionType::Convert),
+        ("image/x-portable-graymap", ConversionType::Convert),
+        ("image/x-portable-pixmap",  ConversionType::Convert),
     ]
         .iter()
         .cloned()
-        .collect();
+        .collect()
+}
 
+fn input_as_pdf_to_pathbuf_uri(logger: &dyn ConversionLogger, _: &ProgressRange, raw_input_path: PathBuf, opt_input_ext: Option<&str>, opt_passwd: Option<String>, limits: &limits::ResourceLimits, l10n: l10n::Translations) -> Result<PathBuf, Box<dyn Error>> {
     if !raw_input_path.exists() {
-        return Err(l10n.gettext_fmt("Cannot find file at {0}", vec![&raw_input_path.display().to_string()]).into());
+        return Err(errors::ConversionError::new(errors::ErrorCode::InputNotFound, l10n.gettext_fmt("Cannot find file at {0}", vec![&raw_input_path.display().to_string()])).into());
     }
 
-    if let Some(mime_type) = mimetypes::detect_from_path(raw_input_path.clone())? {
-        if let Some(conversion_type) = conversion_by_mimetype.get(mime_type) {
+    if let Some(detection) = mimetypes::detect(&raw_input_path, opt_input_ext, l10n.clone())? {
+        let mime_type = detection.mime_type;
+        logger.log(5, l10n.gettext_fmt("Detected input type: {0} ({1}% confidence)", vec![mime_type, &detection.confidence.to_string()]));
+
+        for warning in detection.warnings(l10n.clone()) {
+            logger.log_event(events::ProgressEvent::warning(5, warning));
+        }
+
+        if let Some(conversion_type) = conversion_by_mimetype().get(mime_type).cloned() {
             if let Some(parent_dir) = raw_input_path.parent() {
                 let filename_pdf: String = {
                     if let Some(basename) = raw_input_path.file_stem().and_then(|i| i.to_str()) {
@@ -406,200 +889,663 @@ fn input_as_pdf_to_pathbuf_uri(logger: &dyn ConversionLogger, _: &ProgressRange,
                     }
                 };
 
-                match conversion_type {
-                    ConversionType::None => {
-                        logger.log(5, l10n.gettext_fmt("Copying PDF input to {0}", vec![&filename_pdf]));
-                        fs::copy(raw_input_path, &filename_pdf)?;
-                    }
-                    ConversionType::Convert => {
-                        logger.log(5, l10n.gettext("Converting input image to PDF"));
-
-                        let img_format = match mime_type {
-                            "image/png"    => Ok(image::ImageFormat::Png),
-                            "image/jpeg"   => Ok(image::ImageFormat::Jpeg),
-                            "image/gif"    => Ok(image::ImageFormat::Gif),
-                            "image/tiff"   => Ok(image::ImageFormat::Tiff),
-                            "image/x-tiff" => Ok(image::ImageFormat::Tiff),
-                            unknown_img_t  => Err(l10n.gettext_fmt("Unsupported image type {0}", vec![unknown_img_t])),
-                        }?;
-
-                        img_to_pdf(img_format, raw_input_path, PathBuf::from(&filename_pdf))?;
-                    }
-                    ConversionType::LibreOffice(fileext) => {
-                        logger.log(5, l10n.gettext("Converting to PDF using LibreOffice"));
-                        let new_input_loc = format!("/tmp/input.{}", fileext);
-                        let new_input_path = Path::new(&new_input_loc);
-                        fs::copy(raw_input_path, new_input_path)?;
-
-                        let libreoffice_program_dir = if let Ok(env_libreoffice_program_dir) = env::var(ENV_VAR_ENTRUSTED_LIBREOFFICE_PROGRAM_DIR) {
-                            env_libreoffice_program_dir
-                        } else {
-                            DEFAULT_DIR_LIBREOFFICE_PROGRAM.to_string()
-                        };
-
-                        let mut office = Office::new(&libreoffice_program_dir)?;
-                        let input_uri = urls::local_into_abs(new_input_path.display().to_string())?;
-                        let password_was_set = AtomicBool::new(false);
-                        let failed_password_input = Arc::new(AtomicBool::new(false));
-
-                        if let Some(passwd) = opt_passwd {
-                            if let Err(ex) = office.set_optional_features([LibreOfficeKitOptionalFeatures::LOK_FEATURE_DOCUMENT_PASSWORD]) {
-                                return Err(l10n.gettext_fmt("Failed to enable password-protected Office document features! {0}", vec![&ex.to_string()]).into());
-                            }
-
-                            if let Err(ex) = office.register_callback({
-                                let mut office = office.clone();
-                                let failed_password_input = failed_password_input.clone();
-                                let input_uri = input_uri.clone();
-
-                                move |_, _| {
-                                    if !password_was_set.load(Ordering::Acquire) {
-                                        let _ = office.set_document_password(input_uri.clone(), &passwd);
-                                        password_was_set.store(true, Ordering::Release);
-                                    } else if !failed_password_input.load(Ordering::Acquire) {
-                                        failed_password_input.store(true, Ordering::Release);
-                                        let _ = office.unset_document_password(input_uri.clone());
-                                    }
-                                }
-                            }) {
-                                return Err(l10n.gettext_fmt("Failed to handle password-protected Office document features! {0}", vec![&ex.to_string()]).into());
-                            }
-                        }
-
-                        let res_document_saved: Result<(), Box<dyn Error>> = match office.document_load(input_uri) {
-                            Ok(mut doc) => {
-                                if doc.save_as(&filename_pdf, "pdf", None) {
-                                    Ok(())
-                                } else {
-                                    Err(l10n.gettext_fmt("Could not save document as PDF: {0}", vec![&office.get_error()]).into())
-                                }
-                            },
-                            Err(ex) =>  {
-                                let err_reason = if failed_password_input.load(Ordering::Relaxed) {
-                                    l10n.gettext("Password input failed!")
-                                } else {
-                                    ex.to_string()
-                                };
-
-                                Err(err_reason.into())
-                            }
-                        };
-
-                        if let Err(ex) = res_document_saved {
-                            return Err(l10n.gettext_fmt("Could not export input document as PDF! {0}", vec![&ex.to_string()]).into());
-                        }
-                    }
-                }
+                let mut input_converter = InputConverter::new(logger, limits, l10n.clone())?;
+                let res_converted = input_converter.convert(&raw_input_path, mime_type, conversion_type, Path::new(&filename_pdf), opt_passwd, 0);
+                input_converter.cleanup();
+                res_converted?;
 
                 Ok(PathBuf::from(format!("file://{}", filename_pdf)))
             } else {
                 Err(l10n.gettext("Cannot find input parent directory!").into())
             }
         } else {
-            Err(l10n.gettext_fmt("Unsupported mime type: {0}", vec![mime_type]).into())
+            Err(errors::ConversionError::new(errors::ErrorCode::UnsupportedMime, l10n.gettext_fmt("Unsupported mime type: {0}", vec![mime_type])).into())
         }
     } else {
-        Err(l10n.gettext("Mime type error! Does the input have a 'known' file extension?").into())
+        Err(errors::ConversionError::new(errors::ErrorCode::UnsupportedMime, l10n.gettext("Mime type error! Does the input have a 'known' file extension?")).into())
     }
 }
 
-#[inline]
-fn elapsed_time_string(millis: u128, l10n: l10n::Translations) -> String {
-    let mut diff = millis;
-    let secs_in_millis = 1000;
-    let mins_in_millis = secs_in_millis * 60;
-    let hrs_in_millis = mins_in_millis * 60;
-    let hours = diff / hrs_in_millis;
-    diff %= hrs_in_millis;
-    let minutes = diff / mins_in_millis;
-    diff %= mins_in_millis;
-    let seconds = diff / secs_in_millis;
-
-    format!("{} {} {}",
-            l10n.ngettext("hour",   "hours",   hours   as u64),
-            l10n.ngettext("minute", "minutes", minutes as u64),
-            l10n.ngettext("second", "seconds", seconds as u64))
+// Converts inputs to PDF, e-mail attachments are converted recursively
+// LibreOffice can only be initialized once per process, the same instance is used for all documents
+struct InputConverter<'a> {
+    logger: &'a dyn ConversionLogger,
+    limits: &'a limits::ResourceLimits,
+    work_dir: PathBuf,
+    opt_office: Option<Office>,
+    expanded_size: u64, // total size of the extracted archive entries
+    l10n: l10n::Translations,
 }
 
-fn ocr_imgs_to_pdf(
-    logger: &dyn ConversionLogger,
-    progress_range: &ProgressRange,
-    page_count: usize,
-    tess_settings: TessSettings,
-    input_path: PathBuf,
-    output_path: PathBuf,
-    l10n: l10n::Translations
-) -> Result<(), Box<dyn Error>> {
-    let progress_delta = progress_range.delta();
-    let mut progress_value: usize = progress_range.min;
-    logger.log(progress_value, l10n.ngettext("Performing OCR to PDF on one image", "Performing OCR to PDF on few images", page_count as u64));
+impl<'a> InputConverter<'a> {
+    fn new(logger: &'a dyn ConversionLogger, limits: &'a limits::ResourceLimits, l10n: l10n::Translations) -> Result<Self, Box<dyn Error>> {
+        let work_dir = env::temp_dir().join(format!("entrusted-input-{}", Uuid::new_v4()));
+        fs::create_dir_all(&work_dir)?;
 
-    let api = tesseract_init(tess_settings.lang, tess_settings.data_dir);
+        Ok(Self { logger, limits, work_dir, opt_office: None, expanded_size: 0, l10n })
+    }
 
-    for i in 0..page_count {
-        let page_num = i + 1;
-        progress_value = progress_range.min + (page_num * progress_delta / page_count);
-        let page_num_text = page_num.to_string();
-        logger.log(progress_value, l10n.gettext_fmt("Performing OCR on page {0}", vec![&page_num_text]));
-        let src = input_path.join(format!("page-{}.png", page_num));
-        let dest = output_path.join(format!("page-{}", page_num));
-        ocr_img_to_pdf(api, src, dest)?;
+    fn cleanup(&self) {
+        let _ = fs::remove_dir_all(&self.work_dir);
     }
 
-    tesseract_delete(api);
+    #[allow(clippy::too_many_arguments)]
+    fn convert(&mut self, input_path: &Path, mime_type: &str, conversion_type: ConversionType, dest_path: &Path, opt_passwd: Option<String>, depth: usize) -> Result<(), Box<dyn Error>> {
+        let l10n = self.l10n.clone();
 
-    Ok(())
-}
+        match conversion_type {
+            ConversionType::None => {
+                self.logger.log(5, l10n.gettext_fmt("Copying PDF input to {0}", vec![&dest_path.display().to_string()]));
+                fs::copy(input_path, dest_path)?;
+                self.append_embedded_files(input_path, mime_type, dest_path, depth)?;
+            }
+            ConversionType::Convert => {
+                self.logger.log(5, l10n.gettext("Converting input image to PDF"));
+
+                let img_format = match mime_type {
+                    "image/png"    => Ok(image::ImageFormat::Png),
+                    "image/jpeg"   => Ok(image::ImageFormat::Jpeg),
+                    "image/gif"    => Ok(image::ImageFormat::Gif),
+                    "image/tiff"   => Ok(image::ImageFormat::Tiff),
+                    "image/x-tiff" => Ok(image::ImageFormat::Tiff),
+                    "image/webp"   => Ok(image::ImageFormat::WebP),
+                    "image/bmp"    => Ok(image::ImageFormat::Bmp),
+                    "image/vnd.microsoft.icon" => Ok(image::ImageFormat::Ico),
+                    "image/x-portable-bitmap"  => Ok(image::ImageFormat::Pnm),
+                    "image/x-portable-graymap" => Ok(image::ImageFormat::Pnm),
+                    "image/x-portable-pixmap"  => Ok(image::ImageFormat::Pnm),
+                    unknown_img_t  => Err(l10n.gettext_fmt("Unsupported image type {0}", vec![unknown_img_t])),
+                }?;
+
+                imgframes::img_frames_to_pdf(self.logger, img_format, input_path, dest_path, self.limits, l10n.clone())?;
+            }
+            ConversionType::LibreOffice(fileext) => {
+                self.logger.log(5, l10n.gettext("Converting to PDF using LibreOffice"));
+                let new_input_path = self.work_dir.join(format!("input-{}.{}", Uuid::new_v4(), fileext));
+
+                if mime_type.starts_with("text/") {
+                    textdocs::write_office_input(mime_type, input_path, &new_input_path, l10n.clone())?;
+                } else if mime_type == "application/epub+zip" {
+                    ebooks::write_epub_as_html(input_path, &new_input_path, l10n.clone())?;
+                } else {
+                    fs::copy(input_path, &new_input_path)?;
+                }
 
-// TODO similar concept of preset for paper size, etc., but for the user defined DPI hard-coded to 72
-fn tesseract_init(ocr_lang: &str, tessdata_dir: &str) -> *mut tesseract_plumbing::tesseract_sys::TessBaseAPI {
-    let c_lang = CString::new(ocr_lang).unwrap();
-    let lang = c_lang.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+                self.office_to_pdf(&new_input_path, dest_path, opt_passwd)?;
+                self.append_embedded_files(input_path, mime_type, dest_path, depth)?;
+            }
+            ConversionType::Email => {
+                self.logger.log(5, l10n.gettext("Converting e-mail message to PDF"));
 
-    let c_datapath = CString::new(tessdata_dir).unwrap();
-    let datapath = c_datapath.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+                let message = if mime_type == emails::MIME_TYPE_MSG {
+                    emails::EmailMessage::parse_msg(input_path)?
+                } else {
+                    emails::EmailMessage::parse_eml(&fs::read(input_path)?)
+                };
 
-    let c_user_defined_dpi_var_name = CString::new("user_defined_dpi").unwrap();
-    let user_defined_dpi_var_name = c_user_defined_dpi_var_name.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+                self.email_to_pdf(&message, dest_path, depth)?;
+            }
+            ConversionType::Archive => {
+                self.logger.log(5, l10n.gettext("Extracting archive entries"));
+                self.archive_to_pdf(input_path, mime_type, dest_path, depth)?;
+            }
+        }
 
-    let c_user_defined_dpi_var_value = CString::new("72").unwrap();
-    let user_defined_dpi_var_value = c_user_defined_dpi_var_value.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+        Ok(())
+    }
 
-    unsafe {
-        let api = tesseract_plumbing::tesseract_sys::TessBaseAPICreate();
-        tesseract_plumbing::tesseract_sys::TessBaseAPIInit3(api, datapath, lang);
-        tesseract_plumbing::tesseract_sys::TessBaseAPISetVariable(api, user_defined_dpi_var_name, user_defined_dpi_var_value);
+    fn office_to_pdf(&mut self, input_path: &Path, dest_path: &Path, opt_passwd: Option<String>) -> Result<(), Box<dyn Error>> {
+        let l10n = self.l10n.clone();
+        let filename_pdf = dest_path.display().to_string();
+
+        let mut office = match self.opt_office.as_ref() {
+            Some(office) => office.clone(),
+            None => {
+                let libreoffice_program_dir = if let Ok(env_libreoffice_program_dir) = env::var(ENV_VAR_ENTRUSTED_LIBREOFFICE_PROGRAM_DIR) {
+                    env_libreoffice_program_dir
+                } else {
+                    DEFAULT_DIR_LIBREOFFICE_PROGRAM.to_string()
+                };
 
-        api
-    }
-}
+                let office = match Office::new(&libreoffice_program_dir) {
+                    Ok(v) => v,
+                    Err(ex) => return Err(errors::ConversionError::new(errors::ErrorCode::LibreofficeExportFailed, l10n.gettext_fmt("Could not export input document as PDF! {0}", vec![&ex.to_string()])).into())
+                };
+                self.opt_office = Some(office.clone());
+                office
+            }
+        };
 
-fn tesseract_delete(api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI) {
-    unsafe {
-        tesseract_plumbing::tesseract_sys::TessBaseAPIEnd(api);
-        tesseract_plumbing::tesseract_sys::TessBaseAPIDelete(api);
-    }
-}
+        let input_uri = urls::local_into_abs(input_path.display().to_string())?;
+        let password_was_set = AtomicBool::new(false);
+        let failed_password_input = Arc::new(AtomicBool::new(false));
 
-fn ocr_img_to_pdf(
-    api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI,
-    input_path: PathBuf,
-    output_path: PathBuf,
-) -> Result<(), Box<dyn Error>> {
-    let c_inputname = CString::new(input_path.display().to_string().as_str())?;
-    let inputname = c_inputname.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+        if let Some(passwd) = opt_passwd {
+            if let Err(ex) = office.set_optional_features([LibreOfficeKitOptionalFeatures::LOK_FEATURE_DOCUMENT_PASSWORD]) {
+                return Err(l10n.gettext_fmt("Failed to enable password-protected Office document features! {0}", vec![&ex.to_string()]).into());
+            }
 
-    let c_outputbase = CString::new(output_path.display().to_string().as_str())?;
-    let outputbase = c_outputbase.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+            if let Err(ex) = office.register_callback({
+                let mut office = office.clone();
+                let failed_password_input = failed_password_input.clone();
+                let input_uri = input_uri.clone();
+
+                move |_, _| {
+                    if !password_was_set.load(Ordering::Acquire) {
+                        let _ = office.set_document_password(input_uri.clone(), &passwd);
+                        password_was_set.store(true, Ordering::Release);
+                    } else if !failed_password_input.load(Ordering::Acquire) {
+                        failed_password_input.store(true, Ordering::Release);
+                        let _ = office.unset_document_password(input_uri.clone());
+                    }
+                }
+            }) {
+                return Err(l10n.gettext_fmt("Failed to handle password-protected Office document features! {0}", vec![&ex.to_string()]).into());
+            }
+        }
 
-    let c_input_name = CString::new(input_path.file_name().unwrap().to_str().unwrap()).unwrap();
-    let input_name = c_input_name.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+        let res_document_saved: Result<(), Box<dyn Error>> = match office.document_load(input_uri) {
+            Ok(mut doc) => {
+                if doc.save_as(&filename_pdf, "pdf", None) {
+                    Ok(())
+                } else {
+                    Err(l10n.gettext_fmt("Could not save document as PDF: {0}", vec![&office.get_error()]).into())
+                }
+            },
+            Err(ex) =>  {
+                let err_reason = if failed_password_input.load(Ordering::Relaxed) {
+                    l10n.gettext("Password input failed!")
+                } else {
+                    ex.to_string()
+                };
 
-    let do_not_care = CString::new("").unwrap().as_bytes().as_ptr() as *mut std::os::raw::c_char;
+                Err(err_reason.into())
+            }
+        };
 
-    unsafe {
-        tesseract_plumbing::tesseract_sys::TessBaseAPISetInputName(api, input_name);
-        tesseract_plumbing::tesseract_sys::TessBaseAPISetOutputName(api, outputbase);
+        if let Err(ex) = res_document_saved {
+            let error_code = if failed_password_input.load(Ordering::Relaxed) {
+                errors::ErrorCode::WrongPassword
+            } else {
+                errors::ErrorCode::LibreofficeExportFailed
+            };
+
+            return Err(errors::ConversionError::new(error_code, l10n.gettext_fmt("Could not export input document as PDF! {0}", vec![&ex.to_string()])).into());
+        }
+
+        Ok(())
+    }
+
+    // The message headers and body are the first pages, followed by the pages of each converted attachment
+    // Attachments that can't be converted are skipped, their status is listed with the message headers
+    fn email_to_pdf(&mut self, message: &emails::EmailMessage, dest_path: &Path, depth: usize) -> Result<(), Box<dyn Error>> {
+        let l10n = self.l10n.clone();
+        let mut attachment_docs = Vec::new();
+        let mut attachment_statuses = Vec::new();
+
+        for attachment in message.attachments.iter() {
+            self.logger.log(5, l10n.gettext_fmt("Converting e-mail attachment {0}", vec![&attachment.name]));
+
+            match self.attachment_to_pdf(attachment, depth) {
+                Ok(doc) => {
+                    attachment_docs.push((attachment.name.clone(), doc));
+                    attachment_statuses.push(l10n.gettext("converted"));
+                },
+                Err(ex) => {
+                    self.logger.log_event(events::ProgressEvent::warning(5, l10n.gettext_fmt("Skipping e-mail attachment {0}: {1}", vec![&attachment.name, &ex.to_string()])));
+                    attachment_statuses.push(l10n.gettext_fmt("not converted: {0}", vec![&ex.to_string()]));
+                }
+            }
+        }
+
+        let message_html_path = self.work_dir.join(format!("message-{}.html", Uuid::new_v4()));
+        let message_pdf_path = self.work_dir.join(format!("message-{}.pdf", Uuid::new_v4()));
+        emails::write_message_html(message, &attachment_statuses, &message_html_path, l10n.clone())?;
+        self.office_to_pdf(&message_html_path, &message_pdf_path, None)?;
+
+        // Bookmark keys are the positions of the documents, not page numbers
+        let mut pdf_combiner = PdfCombiner::new();
+        pdf_combiner.append(lopdf::Document::load(&message_pdf_path)?, 1);
+
+        let mut doc_outline = vec![outline::OutlineItem {
+            title: message.subject.clone().unwrap_or_else(|| l10n.gettext("E-mail message")),
+            page_num: Some(1),
+            children: Vec::new(),
+        }];
+
+        for (idx, (name, doc)) in attachment_docs.into_iter().enumerate() {
+            pdf_combiner.append(doc, idx + 2);
+            doc_outline.push(outline::OutlineItem { title: name, page_num: Some(idx + 2), children: Vec::new() });
+        }
+
+        let doc_metadata = metadata::DocumentMetadata {
+            title: message.subject.as_deref().and_then(metadata::sanitize_text),
+            author: message.from.as_deref().and_then(metadata::sanitize_text),
+            ..Default::default()
+        };
+
+        pdf_combiner.save(self.logger, &ProgressRange::new(5, 5), dest_path.to_path_buf(), false, &doc_metadata, &doc_outline, l10n)
+    }
+
+    fn attachment_to_pdf(&mut self, attachment: &emails::EmailAttachment, depth: usize) -> Result<lopdf::Document, Box<dyn Error>> {
+        let pdf_path = self.work_dir.join(format!("attachment-{}.pdf", Uuid::new_v4()));
+
+        match &attachment.content {
+            emails::AttachmentContent::File(data) => {
+                // The original file extension is kept, text formats are recognized from it
+                let file_name = match mimetypes::safe_file_extension(&attachment.name) {
+                    Some(ext) => format!("attachment-{}.{}", Uuid::new_v4(), ext),
+                    None => format!("attachment-{}", Uuid::new_v4()),
+                };
+                let file_path = self.work_dir.join(file_name);
+                fs::write(&file_path, data)?;
+
+                self.embedded_file_to_pdf(&file_path, &pdf_path, depth)
+            },
+            emails::AttachmentContent::Message(message) => {
+                self.check_nesting_depth(depth)?;
+                self.email_to_pdf(message, &pdf_path, depth + 1)?;
+                self.load_embedded_pdf(&pdf_path)
+            }
+        }
+    }
+
+    // Every extracted entry is converted and appended, with a bookmark named after its path in the archive
+    // Entries that can't be converted are skipped, the conversion only fails if none of them is supported
+    fn archive_to_pdf(&mut self, input_path: &Path, mime_type: &str, dest_path: &Path, depth: usize) -> Result<(), Box<dyn Error>> {
+        let l10n = self.l10n.clone();
+        let entries = archives::extract_entries(mime_type, input_path, &self.work_dir, self.limits, &mut self.expanded_size, l10n.clone())?;
+
+        let mut pdf_combiner = PdfCombiner::new();
+        let mut doc_outline = Vec::new();
+
+        for entry in entries {
+            self.logger.log(5, l10n.gettext_fmt("Converting archive entry {0}", vec![&entry.name]));
+
+            let res_doc = match entry.extracted {
+                Ok(file_path) => {
+                    let pdf_path = self.work_dir.join(format!("entry-{}.pdf", Uuid::new_v4()));
+                    self.embedded_file_to_pdf(&file_path, &pdf_path, depth)
+                },
+                Err(reason) => Err(reason.into())
+            };
+
+            match res_doc {
+                Ok(doc) => {
+                    // Bookmark keys are the positions of the documents, not page numbers
+                    let doc_num = doc_outline.len() + 1;
+                    pdf_combiner.append(doc, doc_num);
+                    doc_outline.push(outline::OutlineItem { title: entry.name, page_num: Some(doc_num), children: Vec::new() });
+                },
+                Err(ex) => {
+                    self.logger.log_event(events::ProgressEvent::warning(5, l10n.gettext_fmt("Skipping archive entry {0}: {1}", vec![&entry.name, &ex.to_string()])));
+                }
+            }
+        }
+
+        if doc_outline.is_empty() {
+            return Err(l10n.gettext("Could not find any supported document in the archive").into());
+        }
+
+        pdf_combiner.save(self.logger, &ProgressRange::new(5, 5), dest_path.to_path_buf(), false, &metadata::DocumentMetadata::default(), &doc_outline, l10n)
+    }
+
+    // Files embedded in PDF and Office documents are appended after the pages of the document, with a bookmark for each of them
+    // Embedded files that can't be extracted or converted are only reported, the document itself is still converted
+    fn append_embedded_files(&mut self, input_path: &Path, mime_type: &str, dest_path: &Path, depth: usize) -> Result<(), Box<dyn Error>> {
+        let l10n = self.l10n.clone();
+
+        let embedded_files = match embedded::extract_embedded_files(mime_type, input_path, &self.work_dir, self.limits, &mut self.expanded_size, l10n.clone()) {
+            Ok(v) => v,
+            Err(ex) if ex.is::<limits::ResourceLimitError>() => return Err(ex),
+            Err(ex) => {
+                self.logger.log_event(events::ProgressEvent::warning(5, l10n.gettext_fmt("Could not extract embedded files: {0}", vec![&ex.to_string()])));
+                return Ok(());
+            }
+        };
+
+        if embedded_files.is_empty() {
+            return Ok(());
+        }
+
+        let file_count = embedded_files.len();
+        self.logger.log(5, l10n.gettext_fmt("Number of embedded files: {0}", vec![&file_count.to_string()]));
+
+        let mut embedded_docs = Vec::new();
+
+        for embedded_file in embedded_files {
+            self.logger.log(5, l10n.gettext_fmt("Converting embedded file {0}", vec![&embedded_file.name]));
+
+            let res_doc = match embedded_file.extracted {
+                Ok(file_path) => {
+                    let pdf_path = self.work_dir.join(format!("embedded-{}.pdf", Uuid::new_v4()));
+                    self.embedded_file_to_pdf(&file_path, &pdf_path, depth)
+                },
+                Err(reason) => Err(reason.into())
+            };
+
+            match res_doc {
+                Ok(doc) => embedded_docs.push((embedded_file.name, doc)),
+                Err(ex) => {
+                    self.logger.log_event(events::ProgressEvent::warning(5, l10n.gettext_fmt("Skipping embedded file {0}: {1}", vec![&embedded_file.name, &ex.to_string()])));
+                }
+            }
+        }
+
+        if embedded_docs.is_empty() {
+            return Ok(());
+        }
+
+        // The outline and the metadata of the document are kept, every page can be the destination of a bookmark
+        let dest_uri = format!("file://{}", dest_path.display());
+        let doc = Document::from_file(&dest_uri, None)?;
+        let page_count = doc.n_pages() as usize;
+        let doc_metadata = metadata::DocumentMetadata::read(&doc);
+        let mut doc_outline = outline::read_outline(&doc);
+
+        let mut pdf_combiner = PdfCombiner::new();
+        pdf_combiner.append_pages(lopdf::Document::load(dest_path)?, 1);
+
+        for (idx, (name, embedded_doc)) in embedded_docs.into_iter().enumerate() {
+            let doc_num = page_count + idx + 1;
+            pdf_combiner.append(embedded_doc, doc_num);
+            doc_outline.push(outline::OutlineItem { title: name, page_num: Some(doc_num), children: Vec::new() });
+        }
+
+        let combined_path = self.work_dir.join(format!("combined-{}.pdf", Uuid::new_v4()));
+        pdf_combiner.save(self.logger, &ProgressRange::new(5, 5), combined_path.clone(), false, &doc_metadata, &doc_outline, l10n)?;
+        fs::copy(combined_path, dest_path)?;
+
+        Ok(())
+    }
+
+    // Embedded files (i.e. e-mail attachments or archive entries) are detected and converted like any input
+    fn embedded_file_to_pdf(&mut self, file_path: &Path, pdf_path: &Path, depth: usize) -> Result<lopdf::Document, Box<dyn Error>> {
+        let l10n = self.l10n.clone();
+        self.check_nesting_depth(depth)?;
+
+        let detection = match mimetypes::detect(file_path, None, l10n.clone())? {
+            Some(v) => v,
+            None => return Err(l10n.gettext("Unknown file type").into())
+        };
+        let mime_type = detection.mime_type;
+
+        for warning in detection.warnings(l10n.clone()) {
+            self.logger.log_event(events::ProgressEvent::warning(5, warning));
+        }
+
+        let conversion_type = match conversion_by_mimetype().get(mime_type).cloned() {
+            Some(v) => v,
+            None => return Err(errors::ConversionError::new(errors::ErrorCode::UnsupportedMime, l10n.gettext_fmt("Unsupported mime type: {0}", vec![mime_type])).into())
+        };
+
+        self.convert(file_path, mime_type, conversion_type, pdf_path, None, depth + 1)?;
+        self.load_embedded_pdf(pdf_path)
+    }
+
+    fn check_nesting_depth(&self, depth: usize) -> Result<(), Box<dyn Error>> {
+        if depth >= MAX_NESTING_DEPTH {
+            return Err(self.l10n.gettext("Too many nested e-mail messages, archives or embedded files").into());
+        }
+
+        Ok(())
+    }
+
+    // Password-protected PDF files can't be merged, their objects are encrypted
+    fn load_embedded_pdf(&self, pdf_path: &Path) -> Result<lopdf::Document, Box<dyn Error>> {
+        let doc = lopdf::Document::load(pdf_path)?;
+
+        if doc.trailer.has(b"Encrypt") {
+            return Err(self.l10n.gettext("Password-protected embedded files are not supported").into());
+        }
+
+        Ok(doc)
+    }
+}
+
+// The page count, page sizes and rendered image sizes are checked before allocating any image surface
+// Returns the pixel count of the largest rendered page
+fn check_page_limits(page_numbers: &[usize], doc: &Document, render_size: RenderSize, limits: &limits::ResourceLimits, l10n: l10n::Translations) -> Result<u64, Box<dyn Error>> {
+    limits.check_page_count(page_numbers.len(), l10n.clone())?;
+
+    let mut pixel_count: u64 = 0;
+    let mut max_page_pixel_count: u64 = 0;
+
+    // Pages that can't be opened can't be measured either, they would bypass the limits
+    for idx in page_numbers.iter().copied() {
+        let page = match doc.page((idx - 1) as i32) {
+            Some(v) => v,
+            None => return Err(errors::ConversionError::new(errors::ErrorCode::ConversionFailed, l10n.gettext_fmt("Could not open page {0}", vec![&idx.to_string()])).into())
+        };
+        let page_size = page.size();
+        limits.check_page_area(idx, page_size, l10n.clone())?;
+
+        let page_pixel_count = page_pixel_count(render_size, page_size);
+        limits.check_page_pixels(idx, page_pixel_count, l10n.clone())?;
+        pixel_count = pixel_count.saturating_add(page_pixel_count);
+        max_page_pixel_count = max_page_pixel_count.max(page_pixel_count);
+    }
+
+    limits.check_total_pixels(pixel_count, l10n)?;
+
+    Ok(max_page_pixel_count)
+}
+
+fn page_pixel_count(render_size: RenderSize, page_size: (f64, f64)) -> u64 {
+    let (_, (new_width, new_height)) = render_size.scaling_data(page_size);
+    (new_width as u64).saturating_mul(new_height as u64)
+}
+
+// The image size is a pixel box such as '2480x3508', pages are scaled to fit into it
+fn parse_image_size(image_size: &str, l10n: l10n::Translations) -> Result<(f64, f64), Box<dyn Error>> {
+    let image_size_text = image_size.trim().to_lowercase();
+
+    if let Some((width_text, height_text)) = image_size_text.split_once('x') {
+        if let (Ok(width), Ok(height)) = (width_text.trim().parse::<u32>(), height_text.trim().parse::<u32>()) {
+            if width != 0 && height != 0 {
+                return Ok((width as f64, height as f64));
+            }
+        }
+    }
+
+    Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("Invalid image size: {0}. Hint: Try '2480x3508' for A4 at 300 DPI.", vec![image_size])).into())
+}
+
+// Page ranges are 1-based and inclusive, i.e. '1-5,9,12-' or '-3'
+// Open-ended ranges stop at the last page, pages past it are rejected and the selected pages are returned in document order
+fn parse_page_ranges(page_ranges: &str, page_count: usize, l10n: l10n::Translations) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut selected_pages = BTreeSet::new();
+
+    for page_range in page_ranges.split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+        let parse_page_num = |value: &str, default_value: usize| -> Result<usize, Box<dyn Error>> {
+            let value = value.trim();
+
+            if value.is_empty() {
+                Ok(default_value)
+            } else if let Ok(page_num) = value.parse::<usize>() {
+                Ok(page_num)
+            } else {
+                Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("Invalid page selection: {0}", vec![page_range])).into())
+            }
+        };
+
+        let (first_page, last_page) = if let Some((lo, hi)) = page_range.split_once('-') {
+            (parse_page_num(lo, 1)?, parse_page_num(hi, page_count)?)
+        } else {
+            let page_num = parse_page_num(page_range, 0)?;
+            (page_num, page_num)
+        };
+
+        if first_page == 0 {
+            return Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("Invalid page selection: {0}", vec![page_range])).into());
+        }
+
+        // Only open-ended ranges (i.e. '3-') stop at the last page, other selections must be within the document
+        if first_page.max(last_page) > page_count {
+            return Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("The page selection {0} is outside of the document pages (1-{1})", vec![page_range, &page_count.to_string()])).into());
+        }
+
+        if first_page > last_page {
+            return Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext_fmt("Invalid page selection: {0}", vec![page_range])).into());
+        }
+
+        selected_pages.extend(first_page..=last_page);
+    }
+
+    if selected_pages.is_empty() {
+        return Err(errors::ConversionError::new(errors::ErrorCode::InvalidOption, l10n.gettext("No pages were selected for processing!")).into());
+    }
+
+    Ok(selected_pages.into_iter().collect())
+}
+
+#[inline]
+fn elapsed_time_string(millis: u128, l10n: l10n::Translations) -> String {
+    let mut diff = millis;
+    let secs_in_millis = 1000;
+    let mins_in_millis = secs_in_millis * 60;
+    let hrs_in_millis = mins_in_millis * 60;
+    let hours = diff / hrs_in_millis;
+    diff %= hrs_in_millis;
+    let minutes = diff / mins_in_millis;
+    diff %= mins_in_millis;
+    let seconds = diff / secs_in_millis;
+
+    format!("{} {} {}",
+            l10n.ngettext("hour",   "hours",   hours   as u64),
+            l10n.ngettext("minute", "minutes", minutes as u64),
+            l10n.ngettext("second", "seconds", seconds as u64))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ocr_imgs_to_pdf(
+    logger: &dyn ConversionLogger,
+    progress_range: &ProgressRange,
+    page_numbers: &[usize],
+    tess_settings: TessSettings,
+    page_dpis: &BTreeMap<usize, f64>,
+    image_encoding: imgencoding::ImageEncoding,
+    ocr_sidecars: &mut ocrsidecars::OcrSidecars,
+    input_path: PathBuf,
+    output_path: PathBuf,
+    l10n: l10n::Translations
+) -> Result<(), Box<dyn Error>> {
+    let progress_delta = progress_range.delta();
+    let mut progress_value: usize = progress_range.min;
+    let page_count = page_numbers.len();
+    logger.log_event(events::ProgressEvent::phase_started(progress_value, l10n.ngettext("Performing OCR to PDF on one image", "Performing OCR to PDF on few images", page_count as u64), events::Phase::Ocr, Some(page_count)));
+
+    let tesseract_api = tesseract_init(tess_settings.lang, tess_settings.data_dir, l10n.clone())?;
+    let api = tesseract_api.as_ptr();
+
+    for (i, page_num) in page_numbers.iter().copied().enumerate() {
+        progress_value = progress_range.min + ((i + 1) * progress_delta / page_count);
+        let page_num_text = page_num.to_string();
+        let src = input_path.join(format!("page-{}.png", page_num));
+        let dest = output_path.join(format!("page-{}", page_num));
+        let dpi = page_dpis.get(&page_num).copied().unwrap_or(POINTS_PER_INCH);
+        ocr_encoded_img_to_pdf(api, dpi, image_encoding, src, dest)?;
+        ocr_sidecars.collect_page(api, page_num - 1);
+        logger.log_event(events::ProgressEvent::page_done(progress_value, l10n.gettext_fmt("Performing OCR on page {0}", vec![&page_num_text]), events::Phase::Ocr, page_num, i + 1, page_count));
+    }
+
+    Ok(())
+}
+
+// Tesseract is shut down once the API goes out of scope, including when a page fails
+struct TesseractApi(*mut tesseract_plumbing::tesseract_sys::TessBaseAPI);
+
+impl TesseractApi {
+    fn as_ptr(&self) -> *mut tesseract_plumbing::tesseract_sys::TessBaseAPI {
+        self.0
+    }
+}
+
+impl Drop for TesseractApi {
+    fn drop(&mut self) {
+        tesseract_delete(self.0);
+    }
+}
+
+// Tesseract fails to initialize when the language data files are missing
+fn tesseract_init(ocr_lang: &str, tessdata_dir: &str, l10n: l10n::Translations) -> Result<TesseractApi, Box<dyn Error>> {
+    let c_lang = CString::new(ocr_lang).unwrap();
+    let lang = c_lang.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    let c_datapath = CString::new(tessdata_dir).unwrap();
+    let datapath = c_datapath.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    unsafe {
+        let api = tesseract_plumbing::tesseract_sys::TessBaseAPICreate();
+
+        if tesseract_plumbing::tesseract_sys::TessBaseAPIInit3(api, datapath, lang) != 0 {
+            tesseract_plumbing::tesseract_sys::TessBaseAPIDelete(api);
+            return Err(errors::ConversionError::new(errors::ErrorCode::OcrInitFailed, l10n.gettext_fmt("Could not initialize OCR for the language {0}", vec![ocr_lang])).into());
+        }
+
+        Ok(TesseractApi(api))
+    }
+}
+
+// The user defined DPI must match the rendering resolution, it determines the size of the PDF pages generated by tesseract
+fn tesseract_set_dpi(api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI, dpi: f64) {
+    let c_user_defined_dpi_var_name = CString::new("user_defined_dpi").unwrap();
+    let user_defined_dpi_var_name = c_user_defined_dpi_var_name.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    let c_user_defined_dpi_var_value = CString::new(format!("{}", dpi.round() as i32)).unwrap();
+    let user_defined_dpi_var_value = c_user_defined_dpi_var_value.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    unsafe {
+        tesseract_plumbing::tesseract_sys::TessBaseAPISetVariable(api, user_defined_dpi_var_name, user_defined_dpi_var_value);
+    }
+}
+
+fn tesseract_delete(api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI) {
+    unsafe {
+        tesseract_plumbing::tesseract_sys::TessBaseAPIEnd(api);
+        tesseract_plumbing::tesseract_sys::TessBaseAPIDelete(api);
+    }
+}
+
+// The page image is transcoded beforehand when tesseract needs to embed it with a different encoding
+fn ocr_encoded_img_to_pdf(
+    api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI,
+    dpi: f64,
+    image_encoding: imgencoding::ImageEncoding,
+    input_path: PathBuf,
+    output_path: PathBuf,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(encoded_path) = imgencoding::ocr_input_img(&input_path, image_encoding)? {
+        let ocr_result = ocr_img_to_pdf(api, dpi, encoded_path.clone(), output_path);
+        fs::remove_file(&encoded_path)?;
+        ocr_result
+    } else {
+        ocr_img_to_pdf(api, dpi, input_path, output_path)
+    }
+}
+
+fn ocr_img_to_pdf(
+    api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI,
+    dpi: f64,
+    input_path: PathBuf,
+    output_path: PathBuf,
+) -> Result<(), Box<dyn Error>> {
+    tesseract_set_dpi(api, dpi);
+
+    let c_inputname = CString::new(input_path.display().to_string().as_str())?;
+    let inputname = c_inputname.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    let c_outputbase = CString::new(output_path.display().to_string().as_str())?;
+    let outputbase = c_outputbase.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    let c_input_name = CString::new(input_path.file_name().unwrap().to_str().unwrap()).unwrap();
+    let input_name = c_input_name.as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    let do_not_care = CString::new("").unwrap().as_bytes().as_ptr() as *mut std::os::raw::c_char;
+
+    unsafe {
+        tesseract_plumbing::tesseract_sys::TessBaseAPISetInputName(api, input_name);
+        tesseract_plumbing::tesseract_sys::TessBaseAPISetOutputName(api, outputbase);
 
         let renderer = tesseract_plumbing::tesseract_sys::TessPDFRendererCreate(
             outputbase,
@@ -625,19 +1571,44 @@ fn ocr_img_to_pdf(
     Ok(())
 }
 
+// Progress messages are events with a human-readable message, see the events module for the JSON format
 trait ConversionLogger {
-    fn log(&self, percent_complete: usize, data: String);
+    fn log(&self, percent_complete: usize, data: String) {
+        self.log_event(events::ProgressEvent::progress(percent_complete, data));
+    }
+
+    fn log_event(&self, event: events::ProgressEvent);
     fn clone_box(&self) -> Box<dyn ConversionLogger>;
 }
 
 #[derive(Clone)]
 struct PlainConversionLogger;
+
+// Event timings are relative to the start of the program
 #[derive(Clone)]
-struct JsonConversionLogger;
+struct JsonConversionLogger {
+    started: Instant
+}
+
+// Progress messages would be mixed with the inspection report
+#[derive(Clone)]
+struct SilentConversionLogger;
+
+impl ConversionLogger for SilentConversionLogger {
+    fn log_event(&self, _: events::ProgressEvent) {}
+
+    fn clone_box(&self) -> Box<dyn ConversionLogger> {
+        Box::new(self.clone())
+    }
+}
 
 impl ConversionLogger for PlainConversionLogger {
-    fn log(&self, percent_complete: usize, data: String) {
-        println!("{}% {}", percent_complete, data);
+    fn log_event(&self, event: events::ProgressEvent) {
+        if let Some(error_code) = event.error_code {
+            println!("{}% {} [{}]", event.percent_complete, event.data, error_code);
+        } else {
+            println!("{}% {}", event.percent_complete, event.data);
+        }
     }
 
     fn clone_box(&self) -> Box<dyn ConversionLogger> {
@@ -646,11 +1617,11 @@ impl ConversionLogger for PlainConversionLogger {
 }
 
 impl ConversionLogger for JsonConversionLogger {
-    fn log(&self, percent_complete: usize, data: String) {
-        let progress_msg = ProgressMessage { percent_complete, data};
+    fn log_event(&self, mut event: events::ProgressEvent) {
+        event.elapsed_ms = self.started.elapsed().as_millis() as u64;
 
-        if let Ok(progress_json) = serde_json::to_string(&progress_msg) {
-            println!("{}", progress_json);
+        if let Ok(event_json) = serde_json::to_string(&event) {
+            println!("{}", event_json);
         }
     }
 
@@ -659,12 +1630,6 @@ impl ConversionLogger for JsonConversionLogger {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
-struct ProgressMessage {
-    percent_complete: usize,
-    data: String
-}
-
 struct ProgressRange {
     min: usize,
     max: usize
@@ -685,49 +1650,247 @@ impl ProgressRange {
     }
 }
 
-fn split_pdf_pages_into_images(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_count: usize, doc: Document, target_size: (f64, f64), dest_folder: PathBuf, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
-    let mut progress_value: usize = progress_range.min;
+// Each page goes through rasterization, optional OCR and PDF conversion before the next one starts
+// Converted pages are saved in batches and their intermediate files are deleted right away, which bounds the temporary disk usage
+// Memory usage still grows with the page count: the batches are combined in memory before the result is saved (lopdf can't write a PDF incrementally)
+#[allow(clippy::too_many_arguments)]
+fn pipeline_pages_to_pdf(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_numbers: &[usize], doc: &Document, render_size: RenderSize, color_mode: colormode::ColorMode, limits: &limits::ResourceLimits, image_encoding: imgencoding::ImageEncoding, text_layer: textlayer::TextLayer, opt_tess_settings: Option<TessSettings>, ocr_sidecars: &mut ocrsidecars::OcrSidecars, work_dir: PathBuf, l10n: l10n::Translations) -> Result<PdfCombiner, Box<dyn Error>> {
+    let page_count = page_numbers.len();
+    logger.log_event(events::ProgressEvent::phase_started(progress_range.min,
+                                                          l10n.ngettext("Processing one page",
+                                                                        "Processing few pages, one at a time",
+                                                                        page_count as u64),
+                                                          events::Phase::PageProcessing,
+                                                          Some(page_count)));
+
+    let progress_delta = progress_range.delta();
+    let opt_tesseract_api = match opt_tess_settings {
+        Some(tess_settings) => Some(tesseract_init(tess_settings.lang, tess_settings.data_dir, l10n.clone())?),
+        None => None
+    };
+    let opt_api = opt_tesseract_api.as_ref().map(TesseractApi::as_ptr);
+    let mut batch_paths = Vec::new();
+    let mut batch_combiner = PdfCombiner::new();
+    let mut batch_page_numbers = Vec::new();
+
+    for (i, idx) in page_numbers.iter().copied().enumerate() {
+        let idx_text = idx.to_string();
+        let progress_value = progress_range.min + ((i + 1) * progress_delta / page_count);
+
+        // Pages that poppler can't open are failures, they must not be silently left out of the result
+        let page_result = match doc.page((idx - 1) as i32) {
+            Some(page) => pipeline_page_to_pdf(opt_api, &page, idx, render_size, color_mode, limits, image_encoding, text_layer, ocr_sidecars, &work_dir, l10n.clone()),
+            None => Err(l10n.gettext_fmt("Could not open page {0}", vec![&idx_text]).into())
+        }.and_then(|page_doc| {
+            batch_combiner.append_document(page_doc, &|_| None);
+            batch_page_numbers.push(idx);
+
+            if batch_page_numbers.len() == PdfCombiner::PIPELINE_BATCH_SIZE {
+                let batch_path = work_dir.join(format!("pages-{}.pdf", batch_paths.len() + 1));
+                std::mem::replace(&mut batch_combiner, PdfCombiner::new()).save_pages(batch_path.clone(), l10n.clone())?;
+                batch_paths.push((batch_path, std::mem::take(&mut batch_page_numbers)));
+            }
+
+            Ok(())
+        });
+
+        match page_result {
+            Ok(_) => {
+                logger.log_event(events::ProgressEvent::page_done(progress_value, l10n.gettext_fmt("Processing page {0}", vec![&idx_text]), events::Phase::PageProcessing, idx, i + 1, page_count));
+            },
+            Err(ex) => {
+                // The error code of the page failure is kept, i.e. for resource limits
+                return Err(errors::ConversionError::new(errors::error_code(ex.as_ref()), l10n.gettext_fmt("Could not process page {0}! {1}", vec![&idx_text, &ex.to_string()])).into());
+            }
+        }
+    }
 
-    logger.log(progress_value, l10n.ngettext("Extract PDF file into one image",
-                                             "Extract PDF file into few images",
-                                             page_count as u64));
+    drop(opt_tesseract_api);
+
+    if !batch_page_numbers.is_empty() {
+        let batch_path = work_dir.join(format!("pages-{}.pdf", batch_paths.len() + 1));
+        batch_combiner.save_pages(batch_path.clone(), l10n)?;
+        batch_paths.push((batch_path, batch_page_numbers));
+    }
+
+    let mut pdf_combiner = PdfCombiner::new();
+
+    for (batch_path, batch_page_numbers) in batch_paths {
+        pdf_combiner.append_batch(lopdf::Document::load(&batch_path)?, &batch_page_numbers);
+        fs::remove_file(&batch_path)?;
+    }
 
+    Ok(pdf_combiner)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pipeline_page_to_pdf(opt_api: Option<*mut tesseract_plumbing::tesseract_sys::TessBaseAPI>, page: &poppler::Page, idx: usize, render_size: RenderSize, color_mode: colormode::ColorMode, limits: &limits::ResourceLimits, image_encoding: imgencoding::ImageEncoding, text_layer: textlayer::TextLayer, ocr_sidecars: &mut ocrsidecars::OcrSidecars, work_dir: &Path, l10n: l10n::Translations) -> Result<lopdf::Document, Box<dyn Error>> {
+    let png_path = work_dir.join(format!("page-{}.png", idx));
+    let pdf_path = work_dir.join(format!("page-{}.pdf", idx));
+
+    let dpi = render_page_to_png(page, idx, render_size, color_mode, limits, png_path.clone(), l10n)?;
+    let opt_page_text = if let textlayer::TextLayer::Original = text_layer {
+        textlayer::extract_page_text(page)
+    } else {
+        None
+    };
+
+    match (opt_api, opt_page_text.as_ref()) {
+        (Some(api), None) => {
+            ocr_encoded_img_to_pdf(api, dpi, image_encoding, png_path.clone(), work_dir.join(format!("page-{}", idx)))?;
+            ocr_sidecars.collect_page(api, idx - 1);
+        },
+        _ => img_to_pdf(image::ImageFormat::Png, png_path.clone(), pdf_path.clone(), dpi, image_encoding)?
+    }
+
+    fs::remove_file(&png_path)?;
+    let mut page_doc = lopdf::Document::load(&pdf_path)?;
+    fs::remove_file(&pdf_path)?;
+
+    if let Some(page_text) = opt_page_text {
+        textlayer::add_text_layer(&mut page_doc, &page_text)?;
+    }
+
+    Ok(page_doc)
+}
+
+// Poppler documents cannot be shared across threads, so each worker opens its own copy of the PDF file
+// Workers pick the next page to render from a shared counter, and only the calling thread writes progress logs
+#[allow(clippy::too_many_arguments)]
+fn split_pdf_pages_into_images(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_numbers: &[usize], input_file_path: &Path, document_password: Option<&str>, render_size: RenderSize, color_mode: colormode::ColorMode, jobs: usize, limits: &limits::ResourceLimits, dest_folder: PathBuf, l10n: l10n::Translations) -> Result<BTreeMap<usize, f64>, Box<dyn Error>> {
+    let progress_value: usize = progress_range.min;
+    let page_count = page_numbers.len();
+
+    logger.log_event(events::ProgressEvent::phase_started(progress_value,
+                                                          l10n.ngettext("Extract PDF file into one image",
+                                                                        "Extract PDF file into few images",
+                                                                        page_count as u64),
+                                                          events::Phase::PageRendering,
+                                                          Some(page_count)));
+
+    if page_count == 0 {
+        return Ok(BTreeMap::new());
+    }
+
+    let worker_count = jobs.clamp(1, page_count);
+    let progress_delta = progress_range.delta();
+    let input_file_uri = input_file_path.display().to_string();
+    let next_page_index = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    // Errors cannot be sent across threads, only their error code and message
+    let (tx, rx) = mpsc::channel::<Result<(usize, f64), (errors::ErrorCode, String)>>();
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count {
+            let tx = tx.clone();
+            let input_file_uri = input_file_uri.as_str();
+            let next_page_index = &next_page_index;
+            let cancelled = &cancelled;
+            let dest_folder = dest_folder.as_path();
+            let l10n = &l10n;
+
+            scope.spawn(move || {
+                let doc = match Document::from_file(input_file_uri, document_password) {
+                    Ok(v) => v,
+                    Err(ex) => {
+                        let _ = tx.send(Err((errors::error_code(&ex), ex.to_string())));
+                        return;
+                    }
+                };
+
+                while !cancelled.load(Ordering::Acquire) {
+                    let i = next_page_index.fetch_add(1, Ordering::AcqRel);
+
+                    if i >= page_count {
+                        break;
+                    }
+
+                    let idx = page_numbers[i];
+                    let dest_path = dest_folder.join(format!("page-{}.png", idx));
+
+                    let page_result = match doc.page((idx - 1) as i32) {
+                        Some(page) => render_page_to_png(&page, idx, render_size, color_mode, limits, dest_path, l10n.clone()).map(|dpi| (idx, dpi)).map_err(|ex| (errors::error_code(ex.as_ref()), ex.to_string())),
+                        None => Err((errors::ErrorCode::ConversionFailed, l10n.gettext_fmt("Could not open page {0}", vec![&idx.to_string()])))
+                    };
+
+                    let page_failed = page_result.is_err();
+                    let _ = tx.send(page_result);
+
+                    if page_failed {
+                        break;
+                    }
+                }
+            });
+        }
+
+        // Only the workers hold senders from now on, the loop below ends once they're all done
+        drop(tx);
+
+        let mut completed_count = 0;
+        let mut failure_reason: Option<(errors::ErrorCode, String)> = None;
+        let mut page_dpis = BTreeMap::new();
+
+        for page_result in rx {
+            match page_result {
+                Ok((idx, dpi)) => {
+                    completed_count += 1;
+                    page_dpis.insert(idx, dpi);
+                    let idx_text = idx.to_string();
+                    let progress_value = progress_range.min + (completed_count * progress_delta / page_count);
+                    logger.log_event(events::ProgressEvent::page_done(progress_value, l10n.gettext_fmt("Extracting page {0} into a PNG image", vec![&idx_text]), events::Phase::PageRendering, idx, completed_count, page_count));
+                },
+                Err(ex) => {
+                    cancelled.store(true, Ordering::Release);
+
+                    if failure_reason.is_none() {
+                        failure_reason = Some(ex);
+                    }
+                }
+            }
+        }
+
+        if let Some((error_code, ex)) = failure_reason {
+            Err(errors::ConversionError::new(error_code, l10n.gettext_fmt("Could not render PDF pages into images! {0}", vec![&ex])).into())
+        } else {
+            Ok(page_dpis)
+        }
+    })
+}
+
+// Returns the effective rendering resolution in DPI, which varies per page when pages are scaled to fit a pixel box
+#[allow(clippy::too_many_arguments)]
+fn render_page_to_png(page: &poppler::Page, idx: usize, render_size: RenderSize, color_mode: colormode::ColorMode, limits: &limits::ResourceLimits, dest_path: PathBuf, l10n: l10n::Translations) -> Result<f64, Box<dyn Error>> {
     let antialias_setting = cairo::Antialias::Fast;
     let mut font_options = cairo::FontOptions::new()?;
     font_options.set_antialias(antialias_setting);
     font_options.set_hint_metrics(cairo::HintMetrics::Default);
     font_options.set_hint_style(cairo::HintStyle::Slight);
 
-    let progress_delta = progress_range.delta();
-
-    for i in 0..page_count {
-        let idx = i + 1;
-
-        if let Some(page) = doc.page(i as i32) {
-            let idx_text = idx.to_string();
-            progress_value = progress_range.min + (idx * progress_delta / page_count);
-            logger.log(progress_value, l10n.gettext_fmt("Extracting page {0} into a PNG image", vec![&idx_text]));
+    let current_size = page.size();
+    let (ratio, (new_width, new_height)) = render_size.scaling_data(current_size);
+    limits.check_page_pixels(idx, page_pixel_count(render_size, current_size), l10n)?;
 
-            let dest_path = dest_folder.join(format!("page-{}.png", idx));
-            let current_size = page.size();
+    let mut surface_png = ImageSurface::create(Format::Rgb24, new_width as i32, new_height as i32)?;
+    let ctx = Context::new(&surface_png)?;
 
-            let (ratio, (new_width, new_height)) = scaling_data(current_size, target_size);
+    ctx.scale(ratio, ratio);
+    ctx.set_source_rgb(1.0, 1.0, 1.0);
+    ctx.set_antialias(antialias_setting);
+    ctx.set_font_options(&font_options);
+    ctx.paint()?;
 
-            let surface_png = ImageSurface::create(Format::Rgb24, new_width as i32, new_height as i32)?;
-            let ctx = Context::new(&surface_png)?;
+    page.render(&ctx);
 
-            ctx.scale(ratio, ratio);
-            ctx.set_source_rgb(1.0, 1.0, 1.0);
-            ctx.set_antialias(antialias_setting);
-            ctx.set_font_options(&font_options);
-            ctx.paint()?;
+    // The surface pixels are only accessible once the drawing context is released
+    drop(ctx);
 
-            page.render(&ctx);
-            surface_png.write_to_png(&mut fs::File::create(dest_path)?)?;
-        }
+    if let colormode::ColorMode::Color = color_mode {
+        surface_png.write_to_png(&mut fs::File::create(dest_path)?)?;
+    } else {
+        colormode::write_gray_png(&mut surface_png, color_mode, &dest_path)?;
     }
 
-    Ok(())
+    Ok(ratio * POINTS_PER_INCH)
 }
 
 fn scaling_data(size_current: (f64, f64), size_target: (f64, f64)) -> (f64, (f64, f64)) {
@@ -751,240 +1914,352 @@ fn scaling_data(size_current: (f64, f64), size_target: (f64, f64)) -> (f64, (f64
     (ratio, (new_width, new_height))
 }
 
-fn pdf_combine_pdfs(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_count: usize, input_dir_path: PathBuf, output_path: PathBuf, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
-    logger.log(progress_range.min,
-               l10n.ngettext("Combining one PDF document",
-                             "Combining few PDF documents",
-                             page_count as u64));
-
-    let mut documents: Vec<lopdf::Document> = Vec::with_capacity(page_count);
-
-    let step_count = 7;
-    let mut step_num = 1;
-    let progress_delta = progress_range.delta();
+#[allow(clippy::too_many_arguments)]
+fn pdf_combine_pdfs(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_numbers: &[usize], input_dir_path: PathBuf, output_path: PathBuf, pdfa: bool, doc_metadata: &metadata::DocumentMetadata, doc_outline: &[outline::OutlineItem], l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    logger.log_event(events::ProgressEvent::phase_started(progress_range.min,
+                                                          l10n.ngettext("Combining one PDF document",
+                                                                        "Combining few PDF documents",
+                                                                        page_numbers.len() as u64),
+                                                          events::Phase::Combining,
+                                                          Some(page_numbers.len())));
 
     // step 1/7
-    let mut progress_value = progress_range.min + (step_num * progress_delta / step_count);
+    let progress_value = progress_range.min + (progress_range.delta() / PdfCombiner::STEP_COUNT);
     logger.log(progress_value, l10n.gettext("Collecting PDF pages"));
 
-    for i in 0..page_count {
-        let src_path = input_dir_path.join(format!("page-{}.pdf", i + 1));
+    let mut pdf_combiner = PdfCombiner::new();
+
+    for page_num in page_numbers.iter().copied() {
+        let src_path = input_dir_path.join(format!("page-{}.pdf", page_num));
         let document: lopdf::Document = lopdf::Document::load(src_path)?;
-        documents.push(document);
-    }
-
-    // Define a starting max_id (will be used as start index for object_ids)
-    let mut max_id = 1;
-    let mut pagenum = 1;
-
-    // Collect all Documents Objects grouped by a map
-    let mut documents_pages = BTreeMap::new();
-    let mut documents_objects = BTreeMap::new();
-    let mut document = lopdf::Document::with_version("1.5");
-
-    // step 2/7
-    step_num += 1;
-    progress_value = progress_range.min + (step_num * progress_delta / step_count);
-    logger.log(progress_value, l10n.gettext("Updating bookmarks and page numbering"));
-
-    for mut doc in documents {
-        let mut first = false;
-        doc.renumber_objects_with(max_id);
-        max_id = doc.max_id + 1;
-
-        doc.get_pages()
-            .into_values()
-            .map(|object_id| {
-                if !first {
-                    let bookmark = lopdf::Bookmark::new(format!("Page_{}", pagenum), [0.0, 0.0, 1.0], 0, object_id);
-                    document.add_bookmark(bookmark, None);
-                    first = true;
-                    pagenum += 1;
+        pdf_combiner.append(document, page_num);
+    }
+
+    pdf_combiner.save(logger, progress_range, output_path, pdfa, doc_metadata, doc_outline, l10n)
+}
+
+// Merges single page PDF documents, either all at once or as soon as each page is ready
+struct PdfCombiner {
+    document: lopdf::Document,
+    max_id: u32,
+    documents_pages: BTreeMap<lopdf::ObjectId, lopdf::Object>,
+    documents_objects: BTreeMap<lopdf::ObjectId, lopdf::Object>,
+    page_ids: BTreeMap<usize, lopdf::ObjectId>,
+    page_order: Vec<lopdf::ObjectId>,
+}
+
+impl PdfCombiner {
+    const STEP_COUNT: usize = 7;
+    const PIPELINE_BATCH_SIZE: usize = 10;
+
+    fn new() -> Self {
+        Self {
+            // Define a starting max_id (will be used as start index for object_ids)
+            max_id: 1,
+            document: lopdf::Document::with_version("1.5"),
+            documents_pages: BTreeMap::new(),
+            documents_objects: BTreeMap::new(),
+            page_ids: BTreeMap::new(),
+            page_order: Vec::new(),
+        }
+    }
+
+    // Only the first page of the document is registered as a bookmark destination
+    fn append(&mut self, doc: lopdf::Document, page_num: usize) {
+        self.append_document(doc, &|idx| if idx == 0 { Some(page_num) } else { None });
+    }
+
+    // Every page is registered as a bookmark destination, page numbers start at the given one
+    fn append_pages(&mut self, doc: lopdf::Document, first_page_num: usize) {
+        self.append_document(doc, &|idx| Some(first_page_num + idx));
+    }
+
+    // Every page is registered as a bookmark destination, with the page number at the same position
+    fn append_batch(&mut self, doc: lopdf::Document, page_nums: &[usize]) {
+        self.append_document(doc, &|idx| page_nums.get(idx).copied());
+    }
+
+    // Pages without a page number are not bookmark destinations
+    fn append_document(&mut self, mut doc: lopdf::Document, page_num_at: &dyn Fn(usize) -> Option<usize>) {
+        doc.renumber_objects_with(self.max_id);
+        self.max_id = doc.max_id + 1;
+
+        for (idx, object_id) in doc.get_pages().into_values().enumerate() {
+            if let Some(page_num) = page_num_at(idx) {
+                self.page_ids.insert(page_num, object_id);
+            }
+
+            if let Ok(object) = doc.get_object(object_id) {
+                self.documents_pages.insert(object_id, Self::with_inherited_attributes(&doc, object.to_owned()));
+                self.page_order.push(object_id);
+            }
+        }
+
+        self.documents_objects.extend(doc.objects);
+    }
+
+    // Multi-page documents (i.e. converted e-mail attachments) can declare page attributes in their page tree
+    // The original page tree is discarded, these attributes are copied into each page instead
+    fn with_inherited_attributes(doc: &lopdf::Document, mut page: lopdf::Object) -> lopdf::Object {
+        if let Ok(page_dict) = page.as_dict_mut() {
+            let mut opt_parent_id = page_dict.get(b"Parent").and_then(lopdf::Object::as_reference).ok();
+            let mut visited_ids = BTreeSet::new();
+
+            while let Some(parent_id) = opt_parent_id {
+                if !visited_ids.insert(parent_id) {
+                    break;
                 }
 
-                (object_id, doc.get_object(object_id).unwrap().to_owned())
-            })
-            .for_each(|(key, value)| {
-                documents_pages.insert(key, value);
-            });
+                let parent_dict = match doc.get_object(parent_id).and_then(lopdf::Object::as_dict) {
+                    Ok(v) => v,
+                    Err(_) => break
+                };
 
-        documents_objects.extend(doc.objects);
-    }
-
-    // Catalog and Pages are mandatory
-    let mut catalog_object: Option<(lopdf::ObjectId, lopdf::Object)> = None;
-    let mut pages_object: Option<(lopdf::ObjectId, lopdf::Object)>   = None;
-
-    // step 3/7 Process all objects except "Page" type
-    step_num += 1;
-    progress_value = progress_range.min + (step_num * progress_delta / step_count);
-    logger.log(progress_value, l10n.gettext("Processing PDF structure"));
-
-    for (object_id, object) in documents_objects.iter() {
-        // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects
-        // All other objects should be collected and inserted into the main Document
-        match object.type_name().unwrap_or("") {
-            "Catalog" => {
-                // Collect a first "Catalog" object and use it for the future "Pages"
-                catalog_object = Some((if let Some((id, _)) = catalog_object { id } else { *object_id }, object.clone()));
-            }
-            "Pages" => {
-                // Collect and update a first "Pages" object and use it for the future "Catalog"
-                // We have also to merge all dictionaries of the old and the new "Pages" object
-                if let Ok(dictionary) = object.as_dict() {
-                    let mut dictionary = dictionary.clone();
-                    if let Some((_, ref object)) = pages_object {
-                        if let Ok(old_dictionary) = object.as_dict() {
-                            dictionary.extend(old_dictionary);
+                for key in ["Resources", "MediaBox", "CropBox", "Rotate"] {
+                    if !page_dict.has(key.as_bytes()) {
+                        if let Ok(value) = parent_dict.get(key.as_bytes()) {
+                            page_dict.set(key, value.clone());
                         }
                     }
-
-                    pages_object = Some((
-                        if let Some((id, _)) = pages_object { id } else { *object_id },
-                        lopdf::Object::Dictionary(dictionary),
-                    ));
                 }
-            }
-            "Page" => {}     // Ignored, processed later and separately
-            "Outlines" => {} // Ignored, not supported yet
-            "Outline" => {}  // Ignored, not supported yet
-            _ => {
-                document.objects.insert(*object_id, object.clone());
+
+                opt_parent_id = parent_dict.get(b"Parent").and_then(lopdf::Object::as_reference).ok();
             }
         }
+
+        page
     }
 
-    // If no "Pages" found abort
-    if pages_object.is_none() {
-        return Err(l10n.gettext("No page found while combinding PDF pages!").into());
+    // Intermediate documents have neither bookmarks nor metadata, and progress isn't reported
+    fn save_pages(self, output_path: PathBuf, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        self.save(&SilentConversionLogger, &ProgressRange::new(0, 0), output_path, false, &metadata::DocumentMetadata::default(), &[], l10n)
     }
 
-    // step 4/7 Iter over all "Page" and collect with the parent "Pages" created before
-    step_num += 1;
-    progress_value = progress_range.min + (step_num * progress_delta / step_count);
-    logger.log(progress_value, l10n.gettext("Updating PDF dictionary"));
+    #[allow(clippy::too_many_arguments)]
+    fn save(self, logger: &dyn ConversionLogger, progress_range: &ProgressRange, output_path: PathBuf, pdfa: bool, doc_metadata: &metadata::DocumentMetadata, doc_outline: &[outline::OutlineItem], l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+        let mut document = self.document;
+        let documents_pages = self.documents_pages;
+        let documents_objects = self.documents_objects;
 
-    for (object_id, object) in documents_pages.iter() {
-        if let Ok(dictionary) = object.as_dict() {
-            let mut dictionary = dictionary.clone();
+        // The source document outline replaces the generic page bookmarks, if it points to any of the selected pages
+        outline::add_outline_bookmarks(&mut document, doc_outline, None, &self.page_ids);
 
-            if let Some(parent_obj) = pages_object.as_ref() {
-                dictionary.set("Parent", parent_obj.0);
+        if document.bookmarks.is_empty() {
+            for (page_num, page_id) in self.page_ids.iter() {
+                let bookmark = lopdf::Bookmark::new(format!("Page_{}", page_num), [0.0, 0.0, 1.0], 0, *page_id);
+                document.add_bookmark(bookmark, None);
             }
+        }
+
+        let step_count = Self::STEP_COUNT;
+        let mut step_num = 1;
+        let progress_delta = progress_range.delta();
+
+        // step 2/7
+        step_num += 1;
+        let mut progress_value = progress_range.min + (step_num * progress_delta / step_count);
+        logger.log(progress_value, l10n.gettext("Updating bookmarks and page numbering"));
+
+        // Catalog and Pages are mandatory
+        let mut catalog_object: Option<(lopdf::ObjectId, lopdf::Object)> = None;
+        let mut pages_object: Option<(lopdf::ObjectId, lopdf::Object)>   = None;
+
+        // step 3/7 Process all objects except "Page" type
+        step_num += 1;
+        progress_value = progress_range.min + (step_num * progress_delta / step_count);
+        logger.log(progress_value, l10n.gettext("Processing PDF structure"));
+
+        for (object_id, object) in documents_objects.iter() {
+            // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects
+            // All other objects should be collected and inserted into the main Document
+            match object.type_name().unwrap_or("") {
+                "Catalog" => {
+                    // Collect a first "Catalog" object and use it for the future "Pages"
+                    catalog_object = Some((if let Some((id, _)) = catalog_object { id } else { *object_id }, object.clone()));
+                }
+                "Pages" => {
+                    // Collect and update a first "Pages" object and use it for the future "Catalog"
+                    // We have also to merge all dictionaries of the old and the new "Pages" object
+                    if let Ok(dictionary) = object.as_dict() {
+                        let mut dictionary = dictionary.clone();
+                        if let Some((_, ref object)) = pages_object {
+                            if let Ok(old_dictionary) = object.as_dict() {
+                                dictionary.extend(old_dictionary);
+                            }
+                        }
 
-            document.objects.insert(*object_id, lopdf::Object::Dictionary(dictionary));
+                        pages_object = Some((
+                            if let Some((id, _)) = pages_object { id } else { *object_id },
+                            lopdf::Object::Dictionary(dictionary),
+                        ));
+                    }
+                }
+                "Page" => {}     // Ignored, processed later and separately
+                "Outlines" => {} // Ignored, not supported yet
+                "Outline" => {}  // Ignored, not supported yet
+                _ => {
+                    document.objects.insert(*object_id, object.clone());
+                }
+            }
         }
-    }
 
-    // If no "Catalog" found abort
-    if catalog_object.is_none() {
-        return Err(l10n.gettext("Root catalog was not found!").into());
-    }
+        // If no "Pages" found abort
+        if pages_object.is_none() {
+            return Err(l10n.gettext("No page found while combinding PDF pages!").into());
+        }
 
-    // step 5/7 Merge objects
-    step_num += 1;
-    progress_value = progress_range.min + (step_num * progress_delta / step_count);
-    logger.log(progress_value, l10n.gettext("Combining PDF objects"));
+        // step 4/7 Iter over all "Page" and collect with the parent "Pages" created before
+        step_num += 1;
+        progress_value = progress_range.min + (step_num * progress_delta / step_count);
+        logger.log(progress_value, l10n.gettext("Updating PDF dictionary"));
 
-    if let (Some(catalog_object), Some(pages_object)) = (catalog_object, pages_object) {
-        // Build a new "Pages" with updated fields
-        if let Ok(dictionary) = pages_object.1.as_dict() {
-            let mut dictionary = dictionary.clone();
+        for (object_id, object) in documents_pages.iter() {
+            if let Ok(dictionary) = object.as_dict() {
+                let mut dictionary = dictionary.clone();
 
-            // Set new pages count
-            dictionary.set("Count", documents_pages.len() as u32);
+                if let Some(parent_obj) = pages_object.as_ref() {
+                    dictionary.set("Parent", parent_obj.0);
+                }
 
-            // Set new "Kids" list (collected from documents pages) for "Pages"
-            dictionary.set(
-                "Kids",
-                documents_pages
-                    .into_keys()
-                    .map(lopdf::Object::Reference)
-                    .collect::<Vec<_>>(),
-            );
+                document.objects.insert(*object_id, lopdf::Object::Dictionary(dictionary));
+            }
+        }
 
-            document.objects.insert(pages_object.0, lopdf::Object::Dictionary(dictionary));
+        // If no "Catalog" found abort
+        if catalog_object.is_none() {
+            return Err(l10n.gettext("Root catalog was not found!").into());
         }
 
-        // Build a new "Catalog" with updated fields
-        if let Ok(dictionary) = catalog_object.1.as_dict() {
-            let mut dictionary = dictionary.clone();
-            dictionary.set("Pages", pages_object.0);
-            dictionary.set("PageMode", "UseOutlines");
-            dictionary.remove(b"Outlines"); // Outlines not supported in merged PDFs
+        // step 5/7 Merge objects
+        step_num += 1;
+        progress_value = progress_range.min + (step_num * progress_delta / step_count);
+        logger.log(progress_value, l10n.gettext("Combining PDF objects"));
+
+        if let (Some(catalog_object), Some(pages_object)) = (catalog_object, pages_object) {
+            // Build a new "Pages" with updated fields
+            if let Ok(dictionary) = pages_object.1.as_dict() {
+                let mut dictionary = dictionary.clone();
+
+                // Set new pages count
+                dictionary.set("Count", self.page_order.len() as u32);
+
+                // Set new "Kids" list (collected from documents pages, in their original order) for "Pages"
+                dictionary.set(
+                    "Kids",
+                    self.page_order
+                        .iter()
+                        .copied()
+                        .map(lopdf::Object::Reference)
+                        .collect::<Vec<_>>(),
+                );
+
+                document.objects.insert(pages_object.0, lopdf::Object::Dictionary(dictionary));
+            }
 
-            document.objects.insert(catalog_object.0, lopdf::Object::Dictionary(dictionary));
-        }
+            // Build a new "Catalog" with updated fields
+            if let Ok(dictionary) = catalog_object.1.as_dict() {
+                let mut dictionary = dictionary.clone();
+                dictionary.set("Pages", pages_object.0);
+                dictionary.set("PageMode", "UseOutlines");
+                dictionary.remove(b"Outlines"); // Outlines not supported in merged PDFs
+
+                document.objects.insert(catalog_object.0, lopdf::Object::Dictionary(dictionary));
+            }
 
-        document.trailer.set("Root", catalog_object.0);
+            document.trailer.set("Root", catalog_object.0);
 
-        // Update the max internal ID as wasn't updated before due to direct objects insertion
-        document.max_id = document.objects.len() as u32;
+            // Update the max internal ID as wasn't updated before due to direct objects insertion
+            document.max_id = document.objects.len() as u32;
 
-        // Reorder all new Document objects
-        document.renumber_objects();
+            // Reorder all new Document objects
+            document.renumber_objects();
 
-        //Set any Bookmarks to the First child if they are not set to a page
-        document.adjust_zero_pages();
+            //Set any Bookmarks to the First child if they are not set to a page
+            document.adjust_zero_pages();
 
-        //Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
-        if let Some(n) = document.build_outline() {
-            if let Ok(lopdf::Object::Dictionary(ref mut dict)) = document.get_object_mut(catalog_object.0) {
-                dict.set("Outlines", lopdf::Object::Reference(n));
+            //Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
+            if let Some(n) = document.build_outline() {
+                if let Ok(lopdf::Object::Dictionary(ref mut dict)) = document.get_object_mut(catalog_object.0) {
+                    dict.set("Outlines", lopdf::Object::Reference(n));
+                }
             }
+
+            outline::encode_outline_titles(&mut document);
         }
-    }
 
-    // step 6/7 Compress the document
-    step_num += 1;
-    progress_value = progress_range.min + (step_num * progress_delta / step_count);
-    logger.log(progress_value, l10n.gettext("Compressing PDF"));
+        if pdfa {
+            logger.log(progress_value, l10n.gettext("Converting PDF to PDF/A"));
 
-    document.prune_objects();
-    document.delete_zero_length_streams();
-    document.compress();
+            if let Err(ex) = pdfa::convert_to_pdfa(&mut document, doc_metadata) {
+                return Err(l10n.gettext_fmt("Could not convert PDF to PDF/A. {0}.", vec![&ex.to_string()]).into());
+            }
+        } else if !doc_metadata.is_empty() {
+            let info_id = document.add_object(doc_metadata.to_info_dict());
+            document.trailer.set("Info", info_id);
+        }
 
-    // step 7/7 Save the merged PDF
-    step_num += 1;
-    progress_value = progress_range.min + (step_num * progress_delta / step_count);
-    logger.log(progress_value, l10n.gettext("Saving PDF"));
+        // step 6/7 Compress the document
+        step_num += 1;
+        progress_value = progress_range.min + (step_num * progress_delta / step_count);
+        logger.log(progress_value, l10n.gettext("Compressing PDF"));
 
-    if let Err(ex) = document.save(&output_path) {
-        return Err(l10n.gettext_fmt("Could not save PDF file to {0}. {1}.", vec![&output_path.display().to_string(), &ex.to_string()]).into());
-    }
+        document.prune_objects();
+        document.delete_zero_length_streams();
+        document.compress();
 
-    if std::fs::metadata(&output_path).is_err() {
-        return Err(l10n.gettext_fmt("Could not save PDF file to {0}.", vec![&output_path.display().to_string()]).into());
-    }
+        // step 7/7 Save the merged PDF
+        step_num += 1;
+        progress_value = progress_range.min + (step_num * progress_delta / step_count);
+        logger.log(progress_value, l10n.gettext("Saving PDF"));
 
-    Ok(())
+        if let Err(ex) = document.save(&output_path) {
+            return Err(l10n.gettext_fmt("Could not save PDF file to {0}. {1}.", vec![&output_path.display().to_string(), &ex.to_string()]).into());
+        }
+
+        if std::fs::metadata(&output_path).is_err() {
+            return Err(l10n.gettext_fmt("Could not save PDF file to {0}.", vec![&output_path.display().to_string()]).into());
+        }
+
+        Ok(())
+    }
 }
 
-fn imgs_to_pdf(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_count: usize, input_path: PathBuf, output_path: PathBuf, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+#[allow(clippy::too_many_arguments)]
+fn imgs_to_pdf(logger: &dyn ConversionLogger, progress_range: &ProgressRange, page_numbers: &[usize], page_dpis: &BTreeMap<usize, f64>, image_encoding: imgencoding::ImageEncoding, input_path: PathBuf, output_path: PathBuf, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
     let progress_delta = progress_range.delta();
     let mut progress_value: usize = progress_range.min;
+    let page_count = page_numbers.len();
 
-    logger.log(progress_value, l10n.ngettext("Saving one PNG image to PDF",
-                                             "Saving few PNG images to PDF",
-                                             page_count as u64));
+    logger.log_event(events::ProgressEvent::phase_started(progress_value,
+                                                          l10n.ngettext("Saving one PNG image to PDF",
+                                                                        "Saving few PNG images to PDF",
+                                                                        page_count as u64),
+                                                          events::Phase::PageConversion,
+                                                          Some(page_count)));
 
-    for i in 0..page_count {
-        let idx = i + 1;
+    for (i, idx) in page_numbers.iter().copied().enumerate() {
         let idx_text = idx.to_string();
-        progress_value = progress_range.min + (idx * progress_delta / page_count);
-        logger.log(progress_value, l10n.gettext_fmt("Saving PNG image {0} to PDF", vec![&idx_text]));
+        progress_value = progress_range.min + ((i + 1) * progress_delta / page_count);
         let src = input_path.join(format!("page-{}.png", &idx));
         let dest = output_path.join(format!("page-{}.pdf", &idx));
-        img_to_pdf(image::ImageFormat::Png, src, dest)?;
+        let dpi = page_dpis.get(&idx).copied().unwrap_or(POINTS_PER_INCH);
+        img_to_pdf(image::ImageFormat::Png, src, dest, dpi, image_encoding)?;
+        logger.log_event(events::ProgressEvent::page_done(progress_value, l10n.gettext_fmt("Saving PNG image {0} to PDF", vec![&idx_text]), events::Phase::PageConversion, idx, i + 1, page_count));
     }
 
     Ok(())
 }
 
-fn img_to_pdf(src_format: image::ImageFormat, src_path: PathBuf, dest_path: PathBuf) -> Result<(), Box<dyn Error>> {
+// The PDF page size is derived from the image resolution, one pixel per point at 72 DPI
+fn img_to_pdf(src_format: image::ImageFormat, src_path: PathBuf, dest_path: PathBuf, dpi: f64, image_encoding: imgencoding::ImageEncoding) -> Result<(), Box<dyn Error>> {
     let f = fs::File::open(src_path)?;
     let reader = BufReader::new(f);
     let img = image::load(reader, src_format)?;
+
+    if !matches!(image_encoding, imgencoding::ImageEncoding::Lossless) {
+        return imgencoding::img_to_encoded_pdf(&img, image_encoding, dpi, &dest_path);
+    }
+
     let mut buffer: Vec<u8> = Vec::new();
     let buffer_cursor = &mut Cursor::new(&mut buffer);
 
@@ -994,9 +2269,11 @@ fn img_to_pdf(src_format: image::ImageFormat, src_path: PathBuf, dest_path: Path
 
     let surface_png = ImageSurface::create_from_png(buffer_cursor)?;
     let (w, h) = (surface_png.width() as f64, surface_png.height() as f64);
-    let surface_pdf = PdfSurface::new(w, h, dest_path)?;
+    let scale = POINTS_PER_INCH / dpi;
+    let surface_pdf = PdfSurface::new(w * scale, h * scale, dest_path)?;
     let ctx = Context::new(&surface_pdf)?;
 
+    ctx.scale(scale, scale);
     ctx.set_source_rgb(1.0, 1.0, 1.0);
     ctx.set_source_surface(&surface_png, 0.0, 0.0)?;
     ctx.paint()?;
@@ -1007,3 +2284,73 @@ fn img_to_pdf(src_format: image::ImageFormat, src_path: PathBuf, dest_path: Path
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_l10n() -> l10n::Translations {
+        let mut locale_data = HashMap::new();
+        locale_data.insert("en", include_bytes!("../translations/en/LC_MESSAGES/messages.mo").as_slice());
+        l10n::load_translations(locale_data);
+
+        l10n::new_translations("en".to_string())
+    }
+
+    fn assert_invalid_option<T: std::fmt::Debug>(result: Result<T, Box<dyn Error>>, input: &str) {
+        match result {
+            Ok(v) => panic!("{}: unexpected success {:?}", input, v),
+            Err(ex) => assert_eq!(errors::error_code(ex.as_ref()), errors::ErrorCode::InvalidOption, "{}", input),
+        }
+    }
+
+    #[test]
+    fn test_parse_page_ranges() {
+        let l10n = test_l10n();
+
+        assert_eq!(parse_page_ranges("1-3,5", 5, l10n.clone()).unwrap(), vec![1, 2, 3, 5]);
+        assert_eq!(parse_page_ranges(" 5 , 1 - 2 ", 5, l10n.clone()).unwrap(), vec![1, 2, 5]);
+        assert_eq!(parse_page_ranges("-2", 5, l10n.clone()).unwrap(), vec![1, 2]);
+        assert_eq!(parse_page_ranges("4-", 5, l10n.clone()).unwrap(), vec![4, 5]);
+        assert_eq!(parse_page_ranges("-", 3, l10n.clone()).unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_page_ranges("2-4,1-3,3", 5, l10n.clone()).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(parse_page_ranges("3-5", 5, l10n.clone()).unwrap(), vec![3, 4, 5]);
+        assert_eq!(parse_page_ranges("2,,", 5, l10n.clone()).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn test_parse_page_ranges_invalid() {
+        let l10n = test_l10n();
+        let samples = [
+            "", ",,", "0", "0-3", "5-2", "6", "6-", "3-99", "1-6", "6-9", "1,6", "a", "1-b", "1-2-3", "--3", "1.5",
+            "99999999999999999999999", "1-99999999999999999999999",
+        ];
+
+        for sample in samples {
+            assert_invalid_option(parse_page_ranges(sample, 5, l10n.clone()), sample);
+        }
+
+        for sample in ["3-99", "6-"] {
+            let ex = parse_page_ranges(sample, 5, l10n.clone()).unwrap_err();
+            assert_eq!(ex.to_string(), format!("The page selection {} is outside of the document pages (1-5)", sample));
+        }
+    }
+
+    #[test]
+    fn test_parse_image_size() {
+        let l10n = test_l10n();
+
+        assert_eq!(parse_image_size("2480x3508", l10n.clone()).unwrap(), (2480.0, 3508.0));
+        assert_eq!(parse_image_size(" 800 X 600 ", l10n.clone()).unwrap(), (800.0, 600.0));
+        assert_eq!(parse_image_size("1x4294967295", l10n.clone()).unwrap(), (1.0, 4294967295.0));
+
+        let samples = [
+            "", "x", "800", "800x", "x600", "0x600", "800x0", "-800x600", "800x-600",
+            "800.5x600", "800x600x400", "800*600", "4294967296x600", "800x99999999999999999999",
+        ];
+
+        for sample in samples {
+            assert_invalid_option(parse_image_size(sample, l10n.clone()), sample);
+        }
+    }
+}
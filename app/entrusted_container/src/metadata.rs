@@ -0,0 +1,262 @@
+use lopdf::{dictionary, Object, StringFormat};
+use poppler::Document;
+
+// Longer values are truncated, they're meant for document management systems, not for storage
+const MAX_TEXT_LEN: usize = 512;
+
+// A creation date, as found in PDF dates (D:YYYYMMDDHHmmSSOHH'mm')
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetadataDate {
+    year: u32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    utc_offset_minutes: Option<i32>, // unknown timezone when unset
+}
+
+impl MetadataDate {
+    // Date-time with its UTC offset, as returned by poppler
+    pub fn from_date_time(date_time: &glib::DateTime) -> Option<Self> {
+        let ret = Self {
+            year: u32::try_from(date_time.year()).ok()?,
+            month: u32::try_from(date_time.month()).ok()?,
+            day: u32::try_from(date_time.day_of_month()).ok()?,
+            hour: u32::try_from(date_time.hour()).ok()?,
+            minute: u32::try_from(date_time.minute()).ok()?,
+            second: u32::try_from(date_time.second()).ok()?,
+            utc_offset_minutes: Some(i32::try_from(date_time.utc_offset().as_minutes()).ok()?),
+        };
+
+        if ret.is_valid() {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    // Seconds since the Unix epoch, as found in Outlook messages
+    pub fn from_unix_timestamp(timestamp: i64) -> Option<Self> {
+        if timestamp < 0 {
+            return None;
+        }
+
+        let (days, secs) = (timestamp / 86400, timestamp % 86400);
+
+        // Civil from days, Howard Hinnant's algorithm
+        let z = days + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        // Timestamps far in the future would wrap around as 32 bits years
+        let ret = Self {
+            year: u32::try_from(year).ok()?,
+            month: month as u32,
+            day: day as u32,
+            hour: (secs / 3600) as u32,
+            minute: (secs % 3600 / 60) as u32,
+            second: (secs % 60) as u32,
+            utc_offset_minutes: Some(0),
+        };
+
+        if ret.is_valid() {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        (1..=9999).contains(&self.year) && (1..=12).contains(&self.month) && (1..=self.days_in_month()).contains(&self.day)
+            && self.hour < 24 && self.minute < 60 && self.second < 60
+    }
+
+    // u32::is_multiple_of is more recent than the Rust version of the builder images
+    #[allow(clippy::manual_is_multiple_of)]
+    fn days_in_month(&self) -> u32 {
+        match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    pub fn to_pdf_date(self) -> String {
+        let mut ret = format!("D:{:04}{:02}{:02}{:02}{:02}{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second);
+
+        match self.utc_offset_minutes {
+            Some(0) => ret.push('Z'),
+            Some(offset) => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                ret.push_str(&format!("{}{:02}'{:02}'", sign, offset.abs() / 60, offset.abs() % 60));
+            },
+            None => {}
+        }
+
+        ret
+    }
+
+    // ISO 8601 date, used by XMP metadata
+    pub fn to_xmp_date(self) -> String {
+        let mut ret = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second);
+
+        match self.utc_offset_minutes {
+            Some(0) => ret.push('Z'),
+            Some(offset) => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                ret.push_str(&format!("{}{:02}:{:02}", sign, offset.abs() / 60, offset.abs() % 60));
+            },
+            None => {}
+        }
+
+        ret
+    }
+}
+
+// Allowlisted document properties carried over to the sanitized document
+#[derive(Clone, Debug, Default)]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub creation_date: Option<MetadataDate>,
+}
+
+impl DocumentMetadata {
+    // Office documents properties are already in the PDF Info dictionary after the LibreOffice export
+    // Poppler decodes text strings and keeps the timezone of the creation date, even for encrypted documents
+    pub fn read(doc: &Document) -> Self {
+        Self {
+            title: doc.title().and_then(|v| sanitize_text(&v)),
+            author: doc.author().and_then(|v| sanitize_text(&v)),
+            subject: doc.subject().and_then(|v| sanitize_text(&v)),
+            creation_date: doc.creation_date_time().and_then(|v| MetadataDate::from_date_time(&v)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.author.is_none() && self.subject.is_none() && self.creation_date.is_none()
+    }
+
+    pub fn to_info_dict(&self) -> lopdf::Dictionary {
+        let mut ret = dictionary! {};
+
+        if let Some(title) = self.title.as_deref() {
+            ret.set("Title", text_string(title));
+        }
+
+        if let Some(author) = self.author.as_deref() {
+            ret.set("Author", text_string(author));
+        }
+
+        if let Some(subject) = self.subject.as_deref() {
+            ret.set("Subject", text_string(subject));
+        }
+
+        if let Some(creation_date) = self.creation_date {
+            ret.set("CreationDate", Object::string_literal(creation_date.to_pdf_date()));
+        }
+
+        ret
+    }
+}
+
+// Control characters are removed (i.e. hidden line breaks or terminal escape sequences) and whitespaces are collapsed
+pub fn sanitize_text(text: &str) -> Option<String> {
+    let ret: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+        .take(MAX_TEXT_LEN)
+        .collect();
+    let ret = ret.trim_end();
+
+    if ret.is_empty() {
+        None
+    } else {
+        Some(ret.to_string())
+    }
+}
+
+// PDF text strings are either in PDFDocEncoding or in UTF-16BE with a byte order mark
+pub fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::string_literal(text)
+    } else {
+        let mut bytes = vec![0xfe, 0xff];
+
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u32, month: u32, day: u32, utc_offset_minutes: Option<i32>) -> MetadataDate {
+        MetadataDate { year, month, day, hour: 6, minute: 15, second: 30, utc_offset_minutes }
+    }
+
+    #[test]
+    fn test_to_pdf_date() {
+        assert_eq!(date(2023, 4, 5, Some(150)).to_pdf_date(), "D:20230405061530+02'30'");
+        assert_eq!(date(2023, 4, 5, Some(-300)).to_pdf_date(), "D:20230405061530-05'00'");
+        assert_eq!(date(2023, 4, 5, Some(0)).to_pdf_date(), "D:20230405061530Z");
+        assert_eq!(date(2023, 4, 5, None).to_pdf_date(), "D:20230405061530");
+        assert_eq!(date(2023, 4, 5, Some(-90)).to_xmp_date(), "2023-04-05T06:15:30-01:30");
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(date(2024, 2, 29, Some(0)).is_valid());
+        assert!(date(2000, 2, 29, Some(0)).is_valid());
+
+        for invalid_date in [date(0, 1, 1, None), date(2023, 13, 1, None), date(2023, 0, 1, None), date(2023, 4, 31, None), date(2023, 2, 29, None), date(2100, 2, 29, None), date(10000, 1, 1, None)] {
+            assert!(!invalid_date.is_valid(), "{:?}", invalid_date);
+        }
+    }
+
+    #[test]
+    fn test_from_unix_timestamp() {
+        assert_eq!(MetadataDate::from_unix_timestamp(0).map(|v| v.to_pdf_date()), Some("D:19700101000000Z".to_string()));
+        assert_eq!(MetadataDate::from_unix_timestamp(951782400).map(|v| v.to_pdf_date()), Some("D:20000229000000Z".to_string()));
+        assert_eq!(MetadataDate::from_unix_timestamp(253402300799).map(|v| v.to_pdf_date()), Some("D:99991231235959Z".to_string()));
+
+        assert_eq!(MetadataDate::from_unix_timestamp(-1), None);
+        assert_eq!(MetadataDate::from_unix_timestamp(253402300800), None);
+        assert_eq!(MetadataDate::from_unix_timestamp(135536014634284800), None);
+        assert_eq!(MetadataDate::from_unix_timestamp(i64::MAX), None);
+    }
+
+    #[test]
+    fn test_sanitize_text() {
+        assert_eq!(sanitize_text("  Quarterly\r\n report\t2023 "), Some("Quarterly report 2023".to_string()));
+        assert_eq!(sanitize_text("\u{1b}[31mred\u{1b}[0m"), Some("[31mred [0m".to_string()));
+        assert_eq!(sanitize_text("a\0b\u{7f}c\u{85}d"), Some("a b c d".to_string()));
+        assert_eq!(sanitize_text("Résumé 履歴書"), Some("Résumé 履歴書".to_string()));
+        assert_eq!(sanitize_text(""), None);
+        assert_eq!(sanitize_text(" \r\n\t\0 "), None);
+
+        let long_text = "é".repeat(MAX_TEXT_LEN + 10);
+        assert_eq!(sanitize_text(&long_text), Some("é".repeat(MAX_TEXT_LEN)));
+
+        let long_text = format!("{} end", "a".repeat(MAX_TEXT_LEN - 1));
+        assert_eq!(sanitize_text(&long_text), Some("a".repeat(MAX_TEXT_LEN - 1)));
+    }
+}
@@ -1,38 +1,297 @@
-use std::io::{Cursor, BufReader, Read};
+use std::cmp::Reverse;
+use std::io::{Cursor, BufReader, Read, Seek, SeekFrom};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::Path;
 use std::fs;
 
-// Ideally this code should be replaced by a robust enough mime-detection library
-// The 'mime-detection library' should be able to "reliably" perform mime-detection from bytes.
-
-#[allow(clippy::unused_io_amount)]    
-pub fn detect_from_path<'a> (path: PathBuf) -> Result<Option<&'a str>, Box<dyn Error>> {
-    let mut data = [0u8; 8];
-    let mut f: fs::File = fs::File::open(&path)?;
-    f.read(&mut data)?;
-
-    if is_png(&data) {
-        return Ok(Some("image/png"));
-    } else if is_gif(&data) {
-        return Ok(Some("image/gif"));
-    } else if is_jpeg(&data) {
-        return Ok(Some("image/jpeg"));
-    } else if is_tiff(&data) {
-        return Ok(Some("image/tiff"));
-    } else if is_rtf(&data) {
-        return Ok(Some("application/rtf"));
-    } else if is_pdf(&data) {
-        return Ok(Some("application/pdf"));
-    } else if is_zip(&data) {        
-        let ndata = fs::read(&path)?;
-        return office_mime(ndata);
-    } else if is_cfb(&data) {
-        let ndata = fs::read(&path)?;
-        return legacy_office_mime(ndata);
+use entrusted_l10n as l10n;
+use crate::archives;
+use crate::emails;
+use crate::errors;
+use crate::textdocs;
+
+// Formats are recognized from the file content, the file extension only helps with text formats
+// A file can be valid as several formats at once (i.e. a PDF file with a ZIP archive appended)
+// Such polyglot files are rejected, different programs would otherwise see different documents
+
+// Confidence scores are percentages, short signatures are more likely to match by accident
+const CONFIDENCE_STRUCTURE: u8 = 100; // signature and structure checks (i.e. Office documents)
+const CONFIDENCE_SIGNATURE: u8 = 90;  // signature of at least 3 bytes
+const CONFIDENCE_PREFIXED: u8  = 80;  // signature after some unrecognized data
+const CONFIDENCE_WEAK: u8      = 70;  // short signature or text heuristics
+const CONFIDENCE_GUESS: u8     = 50;  // text format guessed from the file extension
+
+// Two matches at or above this score make a file ambiguous
+const POLYGLOT_MIN_CONFIDENCE: u8 = CONFIDENCE_PREFIXED;
+
+// PDF readers accept a header anywhere in the first 1024 bytes
+const HEAD_SIZE: usize = 1024;
+
+// The end of central directory record of ZIP archives can be followed by a comment of up to 64KB
+const ZIP_EOCD_SIZE: usize = 22;
+const TAIL_SIZE: usize     = ZIP_EOCD_SIZE + u16::MAX as usize;
+
+// Compound documents are only recognized once their content is inspected
+const MIME_TYPE_CFB: &str = "application/x-cfb";
+
+type SignatureCheck = fn(&[u8]) -> bool;
+
+const SIGNATURES: [(SignatureCheck, &str, u8); 14] = [
+    (is_png,        "image/png",                CONFIDENCE_SIGNATURE),
+    (is_gif,        "image/gif",                CONFIDENCE_SIGNATURE),
+    (is_jpeg,       "image/jpeg",               CONFIDENCE_SIGNATURE),
+    (is_tiff,       "image/tiff",               CONFIDENCE_SIGNATURE),
+    (is_webp,       "image/webp",               CONFIDENCE_SIGNATURE),
+    (is_bmp,        "image/bmp",                CONFIDENCE_WEAK),
+    (is_ico,        "image/vnd.microsoft.icon", CONFIDENCE_WEAK),
+    (is_pnm,        "image/x-portable-anymap",  CONFIDENCE_WEAK),
+    (is_rtf,        "application/rtf",          CONFIDENCE_SIGNATURE),
+    (is_pdf,        "application/pdf",          CONFIDENCE_SIGNATURE),
+    (is_zip,        archives::MIME_TYPE_ZIP,    CONFIDENCE_SIGNATURE),
+    (is_gzip,       archives::MIME_TYPE_GZIP,   CONFIDENCE_SIGNATURE),
+    (is_cfb,        MIME_TYPE_CFB,              CONFIDENCE_SIGNATURE),
+    (archives::is_tar, archives::MIME_TYPE_TAR, CONFIDENCE_STRUCTURE),
+];
+
+// Expected mime types per file extension, other extensions aren't checked
+const MIME_TYPES_BY_EXTENSION: [(&str, &[&str]); 40] = [
+    ("pdf",      &["application/pdf"]),
+    ("jpg",      &["image/jpeg"]),
+    ("jpeg",     &["image/jpeg"]),
+    ("png",      &["image/png"]),
+    ("gif",      &["image/gif"]),
+    ("tif",      &["image/tiff"]),
+    ("tiff",     &["image/tiff"]),
+    ("webp",     &["image/webp"]),
+    ("bmp",      &["image/bmp"]),
+    ("ico",      &["image/vnd.microsoft.icon"]),
+    ("pbm",      &["image/x-portable-bitmap"]),
+    ("pgm",      &["image/x-portable-graymap"]),
+    ("ppm",      &["image/x-portable-pixmap"]),
+    ("pnm",      &["image/x-portable-bitmap", "image/x-portable-graymap", "image/x-portable-pixmap"]),
+    ("rtf",      &["application/rtf"]),
+    ("doc",      &["application/msword"]),
+    ("docx",     &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"]),
+    ("odt",      &["application/vnd.oasis.opendocument.text"]),
+    ("odg",      &["application/vnd.oasis.opendocument.graphics"]),
+    ("xls",      &["application/vnd.ms-excel"]),
+    ("xlsx",     &["application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"]),
+    ("ods",      &["application/vnd.oasis.opendocument.spreadsheet"]),
+    ("ppt",      &["application/vnd.ms-powerpoint"]),
+    ("pptx",     &["application/vnd.openxmlformats-officedocument.presentationml.presentation"]),
+    ("odp",      &["application/vnd.oasis.opendocument.presentation"]),
+    ("epub",     &["application/epub+zip"]),
+    ("fb2",      &["application/x-fictionbook+xml"]),
+    ("txt",      &["text/plain"]),
+    ("md",       &["text/markdown"]),
+    ("markdown", &["text/markdown"]),
+    ("csv",      &["text/csv"]),
+    ("html",     &["text/html"]),
+    ("htm",      &["text/html"]),
+    ("eml",      &[emails::MIME_TYPE_EML]),
+    ("msg",      &[emails::MIME_TYPE_MSG]),
+    ("zip",      &[archives::MIME_TYPE_ZIP]),
+    ("tar",      &[archives::MIME_TYPE_TAR]),
+    ("gz",       &[archives::MIME_TYPE_GZIP]),
+    ("tgz",      &[archives::MIME_TYPE_GZIP]),
+    ("gzip",     &[archives::MIME_TYPE_GZIP]),
+];
+
+// A format recognized in the file content
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MimeMatch {
+    pub mime_type: &'static str,
+    pub confidence: u8,
+    pub offset: u64, // position of the signature in the file
+}
+
+// The most likely format, along with every format that matched (the most likely one first)
+#[derive(Clone, Debug)]
+pub struct MimeDetection {
+    pub mime_type: &'static str,
+    pub confidence: u8,
+    pub matches: Vec<MimeMatch>,
+    pub extension_mismatch: Option<String>, // the file extension, when it doesn't match the content
+}
+
+impl MimeDetection {
+    // Weaker matches and unexpected file extensions don't prevent conversions, they're only reported
+    pub fn warnings(&self, l10n: l10n::Translations) -> Vec<String> {
+        let mut ret = Vec::new();
+
+        if let Some(primary) = self.matches.first().filter(|m| m.offset > 0) {
+            ret.push(l10n.gettext_fmt("The {0} content starts after {1} bytes of unrecognized data", vec![primary.mime_type, &primary.offset.to_string()]));
+        }
+
+        for other in self.matches.iter().skip(1) {
+            ret.push(l10n.gettext_fmt("The file also looks like {0}, it could be a polyglot file", vec![other.mime_type]));
+        }
+
+        if let Some(file_ext) = &self.extension_mismatch {
+            ret.push(l10n.gettext_fmt("The file extension '{0}' doesn't match its {1} content", vec![file_ext, self.mime_type]));
+        }
+
+        ret
     }
+}
 
-    Ok(None)
+// Returns None for unknown formats, and an error for files that are valid as several formats
+// The original file extension is provided when the file was renamed (i.e. mounted without extension in the container)
+pub fn detect(path: &Path, opt_file_ext: Option<&str>, l10n: l10n::Translations) -> Result<Option<MimeDetection>, Box<dyn Error>> {
+    let file_ext = opt_file_ext
+        .and_then(sanitize_file_extension)
+        .or_else(|| path.extension().and_then(|v| v.to_str()).map(|v| v.to_ascii_lowercase()));
+    let mut matches = content_matches(path, file_ext.as_deref())?;
+
+    if matches.is_empty() {
+        return Ok(None);
+    }
+
+    matches.sort_by_key(|m| Reverse(m.confidence));
+
+    let ambiguous_mime_types: Vec<&str> = matches
+        .iter()
+        .filter(|m| m.confidence >= POLYGLOT_MIN_CONFIDENCE)
+        .map(|m| m.mime_type)
+        .collect();
+
+    if ambiguous_mime_types.len() > 1 {
+        return Err(errors::ConversionError::new(errors::ErrorCode::PolyglotInput, l10n.gettext_fmt("The file is valid as several formats ({0}), polyglot files are not supported", vec![&ambiguous_mime_types.join(", ")])).into());
+    }
+
+    let primary = matches[0];
+    let extension_mismatch = file_ext
+        .filter(|file_ext| {
+            MIME_TYPES_BY_EXTENSION
+                .iter()
+                .find(|(ext, _)| ext == file_ext)
+                .map(|(_, mime_types)| !mime_types.contains(&primary.mime_type))
+                .unwrap_or(false)
+        });
+
+    Ok(Some(MimeDetection {
+        mime_type: primary.mime_type,
+        confidence: primary.confidence,
+        matches,
+        extension_mismatch
+    }))
+}
+
+// File extension of an embedded file name (i.e. e-mail attachments), text formats are recognized from it
+pub fn safe_file_extension(name: &str) -> Option<String> {
+    sanitize_file_extension(Path::new(name).extension()?.to_str()?)
+}
+
+pub fn sanitize_file_extension(ext: &str) -> Option<String> {
+    if !ext.is_empty() && ext.len() <= 10 && ext.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(ext.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+// Signatures at the start of the file are checked first, followed by signatures found elsewhere
+// Containers (archives and compound documents) embed other files, their content isn't searched for other signatures
+fn content_matches(path: &Path, file_ext: Option<&str>) -> Result<Vec<MimeMatch>, Box<dyn Error>> {
+    let mut f = fs::File::open(path)?;
+    let file_size = f.metadata()?.len();
+
+    let mut head = Vec::with_capacity(HEAD_SIZE);
+    f.by_ref().take(HEAD_SIZE as u64).read_to_end(&mut head)?;
+
+    let mut ret = Vec::new();
+    let mut container_found = false;
+
+    for (signature_matches, mime_type, confidence) in SIGNATURES {
+        if signature_matches(&head) {
+            if let Some(m) = refine_signature_match(path, &head, mime_type, confidence)? {
+                container_found = container_found || is_container(mime_type);
+                ret.push(m);
+            }
+        }
+    }
+
+    if container_found {
+        return Ok(ret);
+    }
+
+    let tail_size = file_size.min(TAIL_SIZE as u64);
+    let mut tail = Vec::with_capacity(tail_size as usize);
+    f.seek(SeekFrom::Start(file_size - tail_size))?;
+    f.take(tail_size).read_to_end(&mut tail)?;
+
+    // Text documents can mention a PDF header, an end of file marker is expected as well
+    if let Some(offset) = pdf_header_offset(&head).filter(|v| *v > 0 && has_pdf_trailer(&tail)) {
+        ret.push(MimeMatch { mime_type: "application/pdf", confidence: CONFIDENCE_PREFIXED, offset: offset as u64 });
+    }
+
+    // ZIP readers look for the central directory from the end, anything can come before it
+    if let Some((idx, confidence)) = zip_eocd_position(&tail) {
+        let offset = file_size - tail_size + idx as u64;
+
+        if !ret.is_empty() {
+            ret.push(MimeMatch { mime_type: archives::MIME_TYPE_ZIP, confidence, offset });
+        } else if confidence == CONFIDENCE_SIGNATURE {
+            // Self-extracting archives or Office documents with some data prepended
+            let mime_type = office_mime(fs::read(path)?).ok().flatten().unwrap_or(archives::MIME_TYPE_ZIP);
+            ret.push(MimeMatch { mime_type, confidence: CONFIDENCE_PREFIXED, offset });
+        }
+    }
+
+    if ret.is_empty() {
+        if let Some((mime_type, confidence)) = text_mime(file_ext, fs::read(path)?) {
+            ret.push(MimeMatch { mime_type, confidence, offset: 0 });
+        }
+    }
+
+    Ok(ret)
+}
+
+// Some signatures are shared by several formats, the actual format depends on the file content
+fn refine_signature_match(path: &Path, head: &[u8], mime_type: &'static str, confidence: u8) -> Result<Option<MimeMatch>, Box<dyn Error>> {
+    let refined: Option<(&'static str, u8)> = match mime_type {
+        archives::MIME_TYPE_ZIP => {
+            // Any other ZIP file is a generic archive
+            match office_mime(fs::read(path)?) {
+                Ok(Some(office_mime_type)) => Some((office_mime_type, CONFIDENCE_STRUCTURE)),
+                Ok(None) => Some((mime_type, confidence)),
+                Err(_) => None
+            }
+        },
+        MIME_TYPE_CFB => legacy_office_mime(fs::read(path)?).ok().flatten().map(|v| (v, CONFIDENCE_STRUCTURE)),
+        "image/x-portable-anymap" => pnm_mime(head).map(|v| (v, confidence)),
+        _ => Some((mime_type, confidence))
+    };
+
+    Ok(refined.map(|(mime_type, confidence)| MimeMatch { mime_type, confidence, offset: 0 }))
+}
+
+fn is_container(mime_type: &str) -> bool {
+    [archives::MIME_TYPE_ZIP, archives::MIME_TYPE_TAR, archives::MIME_TYPE_GZIP, MIME_TYPE_CFB].contains(&mime_type)
+}
+
+fn pdf_header_offset(data: &[u8]) -> Option<usize> {
+    data.windows(5).position(|w| w == b"%PDF-")
+}
+
+fn has_pdf_trailer(tail: &[u8]) -> bool {
+    tail[tail.len().saturating_sub(HEAD_SIZE)..].windows(5).any(|w| w == b"%%EOF")
+}
+
+// The record is expected to end the file, unless some data was appended after its comment
+fn zip_eocd_position(tail: &[u8]) -> Option<(usize, u8)> {
+    if tail.len() < ZIP_EOCD_SIZE {
+        return None;
+    }
+
+    let idx = (0..=tail.len() - ZIP_EOCD_SIZE).rev().find(|idx| tail[*idx..].starts_with(b"PK\x05\x06"))?;
+    let comment_len = u16::from_le_bytes([tail[idx + 20], tail[idx + 21]]) as usize;
+
+    if idx + ZIP_EOCD_SIZE + comment_len == tail.len() {
+        Some((idx, CONFIDENCE_SIGNATURE))
+    } else {
+        Some((idx, CONFIDENCE_GUESS))
+    }
 }
 
 fn byte_range_matches(data: &[u8], lo: usize, hi: usize, sig_expected_raw: &str) -> bool {
@@ -47,8 +306,8 @@ fn byte_range_matches(data: &[u8], lo: usize, hi: usize, sig_expected_raw: &str)
         let hex = format!("{:02X}", data[idx]);
         sig_actual.push_str(&hex);
         idx += 1;
-    }    
-    
+    }
+
     let sig_expected = sig_expected_raw.replace(' ', "");
 
     sig_actual == sig_expected
@@ -58,6 +317,10 @@ fn is_zip(data: &[u8]) -> bool {
     byte_range_matches(data, 0, 4, "50 4B 03 04")
 }
 
+fn is_gzip(data: &[u8]) -> bool {
+    byte_range_matches(data, 0, 3, "1F 8B 08")
+}
+
 fn is_cfb(data: &[u8]) -> bool {
     byte_range_matches(data, 0, 8, "D0 CF 11 E0 A1 B1 1A E1")
 }
@@ -67,26 +330,85 @@ fn is_rtf(data: &[u8]) -> bool {
 }
 
 fn is_pdf(data: &[u8]) -> bool {
-    byte_range_matches(data, 0, 4, "25 50 44 46")
+    byte_range_matches(data, 0, 5, "25 50 44 46 2D")
 }
 
 fn is_png(data: &[u8]) -> bool {
     byte_range_matches(data, 0, 8, "89 50 4E 47 0D 0A 1A 0A")
 }
 
+// Both GIF87a and GIF89a
 fn is_gif(data: &[u8]) -> bool {
-    byte_range_matches(data, 0, 6, "47 49 46 38 39 61")
+    byte_range_matches(data, 0, 6, "47 49 46 38 39 61") || byte_range_matches(data, 0, 6, "47 49 46 38 37 61")
 }
 
 fn is_jpeg(data: &[u8]) -> bool {
-    byte_range_matches(data, 0, 2, "FF D8")
+    byte_range_matches(data, 0, 3, "FF D8 FF")
 }
 
+// Little-endian and big-endian byte orders
 fn is_tiff(data: &[u8]) -> bool {
-    byte_range_matches(data, 0, 4, "49 49 2A 00")
+    byte_range_matches(data, 0, 4, "49 49 2A 00") || byte_range_matches(data, 0, 4, "4D 4D 00 2A")
+}
+
+fn is_webp(data: &[u8]) -> bool {
+    byte_range_matches(data, 0, 4, "52 49 46 46") && byte_range_matches(data, 8, 12, "57 45 42 50")
+}
+
+// The 2 bytes signature is short, the reserved header fields are expected to be empty
+fn is_bmp(data: &[u8]) -> bool {
+    byte_range_matches(data, 0, 2, "42 4D") && byte_range_matches(data, 6, 10, "00 00 00 00")
+}
+
+// At least one image is expected in the icon directory
+fn is_ico(data: &[u8]) -> bool {
+    byte_range_matches(data, 0, 4, "00 00 01 00") && !byte_range_matches(data, 4, 6, "00 00")
 }
 
-fn office_mime<'a>(data: Vec<u8>) -> Result<Option<&'a str>, Box<dyn Error>> {
+fn is_pnm(data: &[u8]) -> bool {
+    pnm_mime(data).is_some()
+}
+
+// Netpbm formats, with a 'P' followed by the format number and a whitespace
+fn pnm_mime(data: &[u8]) -> Option<&'static str> {
+    if data.len() < 3 || data[0] != b'P' || !data[2].is_ascii_whitespace() {
+        return None;
+    }
+
+    match data[1] {
+        b'1' | b'4' => Some("image/x-portable-bitmap"),
+        b'2' | b'5' => Some("image/x-portable-graymap"),
+        b'3' | b'6' => Some("image/x-portable-pixmap"),
+        _           => None
+    }
+}
+
+// Text formats don't have any signature, HTML is recognized from its content and other formats from the file extension
+fn text_mime(file_ext: Option<&str>, data: Vec<u8>) -> Option<(&'static str, u8)> {
+    // Message bodies can be in any charset, they're recognized before decoding any text
+    if emails::looks_like_eml(&data) {
+        return Some((emails::MIME_TYPE_EML, CONFIDENCE_WEAK));
+    }
+
+    let text = textdocs::decode_text(&data)?;
+
+    if textdocs::looks_like_fictionbook(&text) {
+        return Some(("application/x-fictionbook+xml", CONFIDENCE_WEAK));
+    }
+
+    if textdocs::looks_like_html(&text) {
+        return Some(("text/html", CONFIDENCE_WEAK));
+    }
+
+    match file_ext.unwrap_or_default() {
+        "html" | "htm"    => Some(("text/html", CONFIDENCE_GUESS)),
+        "md" | "markdown" => Some(("text/markdown", CONFIDENCE_GUESS)),
+        "csv"             => Some(("text/csv", CONFIDENCE_GUESS)),
+        _                 => Some(("text/plain", CONFIDENCE_GUESS)),
+    }
+}
+
+fn office_mime(data: Vec<u8>) -> Result<Option<&'static str>, Box<dyn Error>> {
     let reader = Cursor::new(data);
     let mut zip = zip::ZipArchive::new(reader)?;
     let probe_count_expected = 2;
@@ -128,6 +450,9 @@ fn office_mime<'a>(data: Vec<u8>) -> Result<Option<&'a str>, Box<dyn Error>> {
                             ret_odt = "application/vnd.oasis.opendocument.presentation";
                         } else if tmp_buf.contains("application/vnd.oasis.opendocument.graphics") {
                             ret_odt = "application/vnd.oasis.opendocument.graphics";
+                        } else if tmp_buf.trim() == "application/epub+zip" {
+                            // EPUB uses the same 'mimetype' entry convention, without any 'content.xml' file
+                            return Ok(Some("application/epub+zip"));
                         }
                     }
 
@@ -162,18 +487,299 @@ fn office_mime<'a>(data: Vec<u8>) -> Result<Option<&'a str>, Box<dyn Error>> {
     Ok(None)
 }
 
-fn legacy_office_mime<'a>(data: Vec<u8>) -> Result<Option<&'a str>, Box<dyn Error>> {
+fn legacy_office_mime(data: Vec<u8>) -> Result<Option<&'static str>, Box<dyn Error>> {
     match cfb::CompoundFile::open(Cursor::new(data)) {
         Ok(file) => {
+            // Outlook doesn't always set the class identifier of messages, their property streams are distinctive
+            if file.exists("/__properties_version1.0") && file.exists("/__nameid_version1.0") {
+                return Ok(Some(emails::MIME_TYPE_MSG));
+            }
+
             return match file.root_entry().clsid().to_string().as_str() {
                 "00020810-0000-0000-c000-000000000046" | "00020820-0000-0000-c000-000000000046" => {
                     Ok(Some("application/vnd.ms-excel"))
                 },
                 "00020906-0000-0000-c000-000000000046" => Ok(Some("application/msword")),
                 "64818d10-4f9b-11cf-86ea-00aa00b929e8" => Ok(Some("application/vnd.ms-powerpoint")),
+                "00020d0b-0000-0000-c000-000000000046" => Ok(Some(emails::MIME_TYPE_MSG)),
                 _ => Ok(None),
             };
         },
         Err(ex) => Err(ex.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    const TEST_DATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../test_data");
+
+    fn test_l10n() -> l10n::Translations {
+        let mut locale_data = HashMap::new();
+        locale_data.insert("en", include_bytes!("../translations/en/LC_MESSAGES/messages.mo").as_slice());
+        l10n::load_translations(locale_data);
+
+        l10n::new_translations("en".to_string())
+    }
+
+    fn sample_data(name: &str) -> Vec<u8> {
+        fs::read(Path::new(TEST_DATA_DIR).join(name)).unwrap()
+    }
+
+    fn write_sample(name: &str, data: &[u8]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("entrusted-mimetypes-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+
+        path
+    }
+
+    fn detect_sample(name: &str, data: &[u8]) -> Result<Option<MimeDetection>, Box<dyn Error>> {
+        let path = write_sample(name, data);
+        let ret = detect(&path, None, test_l10n());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        ret
+    }
+
+    fn assert_polyglot(name: &str, data: &[u8]) {
+        match detect_sample(name, data) {
+            Err(ex) => assert!(ex.to_string().contains("polyglot"), "{}: {}", name, ex),
+            Ok(_) => panic!("{} wasn't rejected", name)
+        }
+    }
+
+    fn concat(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn test_supported_samples() {
+        let samples = [
+            ("sample-bmp.bmp",   "image/bmp"),
+            ("sample-csv.csv",   "text/csv"),
+            ("sample-doc.doc",   "application/msword"),
+            ("sample-docx.docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+            ("sample-eml.eml",   emails::MIME_TYPE_EML),
+            ("sample-epub.epub", "application/epub+zip"),
+            ("sample-fb2.fb2",   "application/x-fictionbook+xml"),
+            ("sample-html.html", "text/html"),
+            ("sample-ico.ico",   "image/vnd.microsoft.icon"),
+            ("sample-md.md",     "text/markdown"),
+            ("sample-msg.msg",   emails::MIME_TYPE_MSG),
+            ("sample-odg.odg",   "application/vnd.oasis.opendocument.graphics"),
+            ("sample-odp.odp",   "application/vnd.oasis.opendocument.presentation"),
+            ("sample-ods.ods",   "application/vnd.oasis.opendocument.spreadsheet"),
+            ("sample-odt.odt",   "application/vnd.oasis.opendocument.text"),
+            ("sample-pbm.pbm",   "image/x-portable-bitmap"),
+            ("sample-pdf.pdf",   "application/pdf"),
+            ("sample-png.png",   "image/png"),
+            ("sample-ppm.ppm",   "image/x-portable-pixmap"),
+            ("sample-ppt.ppt",   "application/vnd.ms-powerpoint"),
+            ("sample-pptx.pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+            ("sample-tar.tar",   archives::MIME_TYPE_TAR),
+            ("sample-txt.txt",   "text/plain"),
+            ("sample-webp.webp", "image/webp"),
+            ("sample-xls.xls",   "application/vnd.ms-excel"),
+            ("sample-xlsx.xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+            ("sample-zip.zip",   archives::MIME_TYPE_ZIP),
+        ];
+
+        for (name, mime_type) in samples {
+            let detection = detect_sample(name, &sample_data(name)).unwrap().unwrap();
+
+            assert_eq!(detection.mime_type, mime_type, "{}", name);
+            assert_eq!(detection.matches.len(), 1, "{}", name);
+            assert_eq!(detection.extension_mismatch, None, "{}", name);
+            assert!(detection.warnings(test_l10n()).is_empty(), "{}", name);
+        }
+    }
+
+    // Some image samples are PNG images under another file extension
+    #[test]
+    fn test_mislabeled_samples() {
+        let samples = [
+            ("sample-gif.gif",   "gif"),
+            ("sample-jpeg.jpeg", "jpeg"),
+            ("sample-jpg.jpg",   "jpg"),
+            ("sample-tiff.tiff", "tiff"),
+        ];
+
+        for (name, file_ext) in samples {
+            let detection = detect_sample(name, &sample_data(name)).unwrap().unwrap();
+
+            assert_eq!(detection.mime_type, "image/png", "{}", name);
+            assert_eq!(detection.extension_mismatch, Some(file_ext.to_string()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_gif87a() {
+        let data = concat(&[b"GIF87a", &[1, 0, 1, 0, 0, 0, 0, 0x2C]]);
+        let detection = detect_sample("old.gif", &data).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "image/gif");
+    }
+
+    #[test]
+    fn test_big_endian_tiff() {
+        let data = concat(&[b"MM\x00\x2A", &[0, 0, 0, 8], &[0; 8]]);
+        let detection = detect_sample("motorola.tif", &data).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "image/tiff");
+    }
+
+    #[test]
+    fn test_short_jpeg_signature() {
+        let detection = detect_sample("notes.txt", b"\xFF\xD8 isn't enough").unwrap();
+
+        assert_eq!(detection.map(|v| v.mime_type), None);
+    }
+
+    #[test]
+    fn test_pdf_with_leading_junk() {
+        let junk = b"\x00\x01junk before the PDF header\r\n";
+        let data = concat(&[junk, &sample_data("sample-pdf.pdf")]);
+        let detection = detect_sample("prefixed.pdf", &data).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "application/pdf");
+        assert_eq!(detection.confidence, CONFIDENCE_PREFIXED);
+        assert_eq!(detection.matches[0].offset, junk.len() as u64);
+        assert_eq!(detection.warnings(test_l10n()).len(), 1);
+    }
+
+    #[test]
+    fn test_text_mentioning_pdf_header() {
+        let detection = detect_sample("notes.md", b"# PDF versions\n\nFiles start with '%PDF-1.7'.\n").unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "text/markdown");
+    }
+
+    #[test]
+    fn test_pdf_header_after_1024_bytes() {
+        let data = concat(&[&[b'x'; HEAD_SIZE], &sample_data("sample-pdf.pdf")]);
+
+        assert!(detect_sample("late.pdf", &data).unwrap().map(|v| v.mime_type) != Some("application/pdf"));
+    }
+
+    #[test]
+    fn test_pdf_zip_polyglot() {
+        let data = concat(&[&sample_data("sample-pdf.pdf"), &sample_data("sample-zip.zip")]);
+
+        assert_polyglot("polyglot.pdf", &data);
+    }
+
+    #[test]
+    fn test_jpeg_zip_polyglot() {
+        let data = concat(&[b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00", &sample_data("sample-zip.zip")]);
+
+        assert_polyglot("polyglot.jpg", &data);
+    }
+
+    #[test]
+    fn test_png_pdf_polyglot() {
+        let png_data = sample_data("sample-png.png");
+        let data = concat(&[&png_data[..33], b"%PDF-1.4\n", &png_data[33..], b"trailer\n<<>>\n%%EOF\n"]);
+
+        assert_polyglot("polyglot.png", &data);
+    }
+
+    #[test]
+    fn test_truncated_zip_record_in_pdf() {
+        let data = concat(&[&sample_data("sample-pdf.pdf"), b"PK\x05\x06", &[0; 18], b"trailing data"]);
+        let detection = detect_sample("suspicious.pdf", &data).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "application/pdf");
+        assert_eq!(detection.matches.len(), 2);
+        assert_eq!(detection.matches[1].mime_type, archives::MIME_TYPE_ZIP);
+        assert_eq!(detection.warnings(test_l10n()).len(), 1);
+    }
+
+    #[test]
+    fn test_prefixed_zip() {
+        let data = concat(&[b"#!/bin/sh\nexit 0\n", &sample_data("sample-docx.docx")]);
+        let detection = detect_sample("installer.sh", &data).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+        assert_eq!(detection.confidence, CONFIDENCE_PREFIXED);
+    }
+
+    #[test]
+    fn test_archive_with_embedded_pdf() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("sample-pdf.pdf", options).unwrap();
+        zip.write_all(&sample_data("sample-pdf.pdf")).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        let detection = detect_sample("documents.zip", &data).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, archives::MIME_TYPE_ZIP);
+        assert_eq!(detection.matches.len(), 1);
+    }
+
+    #[test]
+    fn test_extension_mismatch() {
+        let detection = detect_sample("invoice.pdf", &sample_data("sample-png.png")).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "image/png");
+        assert_eq!(detection.extension_mismatch, Some("pdf".to_string()));
+        assert_eq!(detection.warnings(test_l10n()).len(), 1);
+
+        let detection = detect_sample("notes.txt", b"<!DOCTYPE html><html><body>Hello</body></html>").unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "text/html");
+        assert_eq!(detection.extension_mismatch, Some("txt".to_string()));
+    }
+
+    // Inputs are mounted without their extension in the container, the original one is passed along
+    #[test]
+    fn test_original_extension() {
+        let path = write_sample("input_file", &sample_data("sample-png.png"));
+
+        assert_eq!(detect(&path, None, test_l10n()).unwrap().unwrap().extension_mismatch, None);
+        assert_eq!(detect(&path, Some("png"), test_l10n()).unwrap().unwrap().extension_mismatch, None);
+
+        let detection = detect(&path, Some("PDF"), test_l10n()).unwrap().unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(detection.mime_type, "image/png");
+        assert_eq!(detection.extension_mismatch, Some("pdf".to_string()));
+    }
+
+    #[test]
+    fn test_text_formats_from_original_extension() {
+        let path = write_sample("input_file", b"name,amount\nfoo,1\n");
+        let mime_type = |opt_file_ext: Option<&str>| detect(&path, opt_file_ext, test_l10n()).unwrap().unwrap().mime_type;
+
+        assert_eq!(mime_type(None), "text/plain");
+        assert_eq!(mime_type(Some("CSV")), "text/csv");
+        assert_eq!(mime_type(Some("md")), "text/markdown");
+        assert_eq!(mime_type(Some("../csv")), "text/plain");
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_unknown_extension_is_not_checked() {
+        let detection = detect_sample("upload.bin", &sample_data("sample-png.png")).unwrap().unwrap();
+
+        assert_eq!(detection.mime_type, "image/png");
+        assert_eq!(detection.extension_mismatch, None);
+    }
+
+    #[test]
+    fn test_unknown_binary() {
+        assert!(detect_sample("random.bin", &[0x00, 0x9F, 0x13, 0x07, 0xFE, 0x00, 0x01]).unwrap().is_none());
+    }
+}
@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const OCR_SIDECAR_TXT: &str  = "txt";
+pub const OCR_SIDECAR_HOCR: &str = "hocr";
+pub const OCR_SIDECAR_ALTO: &str = "alto";
+pub const OCR_SIDECAR_CHOICES: [&str; 3] = [OCR_SIDECAR_TXT, OCR_SIDECAR_HOCR, OCR_SIDECAR_ALTO];
+
+// Same page separator as the tesseract text renderer
+const TXT_PAGE_SEPARATOR: &str = "\x0c";
+
+const HOCR_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN"
+    "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en">
+ <head>
+  <title></title>
+  <meta http-equiv="Content-Type" content="text/html;charset=utf-8"/>
+  <meta name='ocr-system' content='tesseract'/>
+  <meta name='ocr-capabilities' content='ocr_page ocr_carea ocr_par ocr_line ocrx_word ocrp_wconf'/>
+ </head>
+ <body>
+"#;
+
+const HOCR_FOOTER: &str = " </body>\n</html>\n";
+
+const ALTO_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<alto xmlns="http://www.loc.gov/standards/alto/ns-v3#" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.loc.gov/standards/alto/ns-v3# http://www.loc.gov/alto/v3/alto-3-0.xsd">
+	<Description>
+		<MeasurementUnit>pixel</MeasurementUnit>
+		<OCRProcessing ID="OCR_0">
+			<ocrProcessingStep>
+				<processingSoftware>
+					<softwareName>tesseract</softwareName>
+				</processingSoftware>
+			</ocrProcessingStep>
+		</OCRProcessing>
+	</Description>
+	<Layout>
+"#;
+
+const ALTO_FOOTER: &str = "\t</Layout>\n</alto>\n";
+
+// Recognized text outside of the PDF result, for search indexers and similar tools
+// Tesseract keeps the recognition results of the last processed page, they're collected page by page
+pub struct OcrSidecars {
+    txt: Option<String>,
+    hocr: Option<String>,
+    alto: Option<String>,
+}
+
+impl OcrSidecars {
+    pub fn new(names: &[String]) -> Self {
+        let select = |name: &str| if names.iter().any(|v| v == name) { Some(String::new()) } else { None };
+
+        Self {
+            txt: select(OCR_SIDECAR_TXT),
+            hocr: select(OCR_SIDECAR_HOCR),
+            alto: select(OCR_SIDECAR_ALTO),
+        }
+    }
+
+    // The page index is zero-based, it determines the page identifiers in hOCR and ALTO documents
+    pub fn collect_page(&mut self, api: *mut tesseract_plumbing::tesseract_sys::TessBaseAPI, page_index: usize) {
+        if let Some(txt) = self.txt.as_mut() {
+            txt.push_str(&tesseract_text(unsafe { tesseract_plumbing::tesseract_sys::TessBaseAPIGetUTF8Text(api) }));
+            txt.push_str(TXT_PAGE_SEPARATOR);
+        }
+
+        if let Some(hocr) = self.hocr.as_mut() {
+            hocr.push_str(&tesseract_text(unsafe { tesseract_plumbing::tesseract_sys::TessBaseAPIGetHOCRText(api, page_index as i32) }));
+        }
+
+        if let Some(alto) = self.alto.as_mut() {
+            alto.push_str(&tesseract_text(unsafe { tesseract_plumbing::tesseract_sys::TessBaseAPIGetAltoText(api, page_index as i32) }));
+        }
+    }
+
+    // Sidecar files are named after the PDF result, i.e. 'document.txt', 'document.hocr' and 'document.alto.xml' for 'document.pdf'
+    pub fn save(&self, pdf_path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut ret = Vec::new();
+
+        if let Some(txt) = self.txt.as_ref() {
+            let dest_path = pdf_path.with_extension("txt");
+            fs::write(&dest_path, txt)?;
+            ret.push(dest_path);
+        }
+
+        if let Some(hocr) = self.hocr.as_ref() {
+            let dest_path = pdf_path.with_extension("hocr");
+            fs::write(&dest_path, format!("{}{}{}", HOCR_HEADER, hocr, HOCR_FOOTER))?;
+            ret.push(dest_path);
+        }
+
+        if let Some(alto) = self.alto.as_ref() {
+            let dest_path = pdf_path.with_extension("alto.xml");
+            fs::write(&dest_path, format!("{}{}{}", ALTO_HEADER, alto, ALTO_FOOTER))?;
+            ret.push(dest_path);
+        }
+
+        Ok(ret)
+    }
+}
+
+// Copies and releases text allocated by tesseract
+fn tesseract_text(text_ptr: *mut std::os::raw::c_char) -> String {
+    if text_ptr.is_null() {
+        return String::new();
+    }
+
+    unsafe {
+        let ret = CStr::from_ptr(text_ptr).to_string_lossy().into_owned();
+        tesseract_plumbing::tesseract_sys::TessDeleteText(text_ptr);
+        ret
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+
+use glib::translate::{FromGlibPtrFull, ToGlibPtr, ToGlibPtrMut};
+use poppler::{Document, IndexIter};
+
+use crate::metadata;
+
+// Hostile documents can have very deep or very large outlines
+const MAX_OUTLINE_DEPTH: usize = 32;
+const MAX_OUTLINE_ITEMS: usize = 10000;
+
+// A table of contents entry, without its original action
+pub struct OutlineItem {
+    pub title: String,
+    pub page_num: Option<usize>, // 1-based page number in the source document
+    pub children: Vec<OutlineItem>,
+}
+
+// Only 'go to page' entries of the source outline keep their destination
+// Other entries (i.e. links, launch actions or JavaScript) are kept as plain headings if they have children
+pub fn read_outline(doc: &Document) -> Vec<OutlineItem> {
+    let iter_ptr = unsafe { poppler_sys::poppler_index_iter_new(doc.to_glib_none().0) };
+
+    if iter_ptr.is_null() {
+        return Vec::new();
+    }
+
+    let mut iter: IndexIter = unsafe { IndexIter::from_glib_full(iter_ptr) };
+    let mut item_count = 0;
+
+    read_outline_level(doc, &mut iter, 0, &mut item_count)
+}
+
+fn read_outline_level(doc: &Document, iter: &mut IndexIter, depth: usize, item_count: &mut usize) -> Vec<OutlineItem> {
+    let mut ret = Vec::new();
+
+    loop {
+        if *item_count >= MAX_OUTLINE_ITEMS {
+            break;
+        }
+
+        *item_count += 1;
+
+        let children = if depth + 1 < MAX_OUTLINE_DEPTH {
+            iter.child().map(|mut child_iter| read_outline_level(doc, &mut child_iter, depth + 1, item_count)).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        if let Some((title, page_num)) = outline_entry(doc, iter) {
+            if page_num.is_some() || !children.is_empty() {
+                ret.push(OutlineItem { title, page_num, children });
+            }
+        }
+
+        if !iter.next() {
+            break;
+        }
+    }
+
+    ret
+}
+
+// Returns the sanitized title and the destination page number of the current entry
+fn outline_entry(doc: &Document, iter: &mut IndexIter) -> Option<(String, Option<usize>)> {
+    unsafe {
+        let action = poppler_sys::poppler_index_iter_get_action(iter.to_glib_none_mut().0);
+
+        if action.is_null() {
+            return None;
+        }
+
+        let title_ptr = (*action).any.title;
+        let opt_title = if title_ptr.is_null() {
+            None
+        } else {
+            metadata::sanitize_text(&CStr::from_ptr(title_ptr).to_string_lossy())
+        };
+
+        let page_num = if (*action).type_ == poppler_sys::POPPLER_ACTION_GOTO_DEST {
+            dest_page_num(doc, (*action).goto_dest.dest)
+        } else {
+            None
+        };
+
+        poppler_sys::poppler_action_free(action);
+
+        opt_title.map(|title| (title, page_num))
+    }
+}
+
+// Named destinations are resolved through the document names dictionary
+unsafe fn dest_page_num(doc: &Document, dest: *mut poppler_sys::PopplerDest) -> Option<usize> {
+    if dest.is_null() {
+        return None;
+    }
+
+    let page_num = if (*dest).type_ == poppler_sys::POPPLER_DEST_NAMED {
+        if (*dest).named_dest.is_null() {
+            return None;
+        }
+
+        let resolved_dest = poppler_sys::poppler_document_find_dest(doc.to_glib_none().0, (*dest).named_dest);
+
+        if resolved_dest.is_null() {
+            return None;
+        }
+
+        let ret = (*resolved_dest).page_num;
+        poppler_sys::poppler_dest_free(resolved_dest);
+        ret
+    } else {
+        (*dest).page_num
+    };
+
+    if page_num > 0 {
+        Some(page_num as usize)
+    } else {
+        None
+    }
+}
+
+// Rebuilds the outline with bookmarks pointing to the rasterized pages
+// Destinations of pages that were not selected are dropped
+pub fn add_outline_bookmarks(document: &mut lopdf::Document, items: &[OutlineItem], parent: Option<u32>, page_ids: &BTreeMap<usize, lopdf::ObjectId>) {
+    for item in items {
+        if !has_destination(item, page_ids) {
+            continue;
+        }
+
+        let opt_page_id = item.page_num.and_then(|page_num| page_ids.get(&page_num).copied());
+
+        // Headings without a destination point to their first child page, see lopdf::Document::adjust_zero_pages
+        let page_id = opt_page_id.unwrap_or((0, 0));
+        let bookmark = lopdf::Bookmark::new(item.title.clone(), [0.0, 0.0, 0.0], 0, page_id);
+        let bookmark_id = document.add_bookmark(bookmark, parent);
+
+        add_outline_bookmarks(document, &item.children, Some(bookmark_id), page_ids);
+    }
+}
+
+fn has_destination(item: &OutlineItem, page_ids: &BTreeMap<usize, lopdf::ObjectId>) -> bool {
+    item.page_num.map(|page_num| page_ids.contains_key(&page_num)).unwrap_or(false)
+        || item.children.iter().any(|child| has_destination(child, page_ids))
+}
+
+// lopdf writes bookmark titles as raw UTF-8 bytes, non-ASCII titles are converted to PDF text strings
+pub fn encode_outline_titles(document: &mut lopdf::Document) {
+    for object in document.objects.values_mut() {
+        if let lopdf::Object::Dictionary(dict) = object {
+            if !dict.has(b"Parent") {
+                continue;
+            }
+
+            let opt_title = match dict.get(b"Title") {
+                Ok(lopdf::Object::String(bytes, _)) if !bytes.is_ascii() => Some(String::from_utf8_lossy(bytes).into_owned()),
+                _ => None
+            };
+
+            if let Some(title) = opt_title {
+                dict.set("Title", metadata::text_string(&title));
+            }
+        }
+    }
+}
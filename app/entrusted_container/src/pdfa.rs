@@ -0,0 +1,240 @@
+use std::error::Error;
+
+use lopdf::{dictionary, Object, StringFormat, Stream};
+use uuid::Uuid;
+
+use crate::metadata::DocumentMetadata;
+
+// PDF/A-2 is based on PDF 1.7, the header must be followed by a comment with at least 4 bytes above 127
+// lopdf only writes the version line, so the comment is appended to the version (non-ASCII UTF-8 bytes are all above 127)
+const PDFA_VERSION: &str = "1.7\n%\u{e2}\u{e3}\u{cf}\u{d3}";
+
+const SRGB_PROFILE_NAME: &str = "sRGB IEC61966-2.1";
+const SRGB_CURVE_POINTS: usize = 1024;
+
+// D50 adapted sRGB primaries and white point, as expected by the ICC profile connection space
+const D50_WHITE_POINT: [f64; 3] = [0.9642, 1.0, 0.8249];
+const SRGB_RED: [f64; 3]        = [0.4361, 0.2225, 0.0139];
+const SRGB_GREEN: [f64; 3]      = [0.3851, 0.7169, 0.0971];
+const SRGB_BLUE: [f64; 3]       = [0.1431, 0.0606, 0.7141];
+
+fn producer_name() -> String {
+    format!("{} {}",
+            option_env!("CARGO_PKG_NAME").unwrap_or("Unknown"),
+            option_env!("CARGO_PKG_VERSION").unwrap_or("Unknown"))
+}
+
+// Turns the combined document into a PDF/A-2b document
+// Page images are opaque, so only the transparency groups added by cairo need to be removed
+// Document properties must be present both in the document information dictionary and in the XMP metadata
+pub fn convert_to_pdfa(document: &mut lopdf::Document, doc_metadata: &DocumentMetadata) -> Result<(), Box<dyn Error>> {
+    let catalog_id = document.trailer.get(b"Root")?.as_reference()?;
+    let producer = producer_name();
+
+    for object in document.objects.values_mut() {
+        match object {
+            Object::Dictionary(dict) if dict.type_is(b"Page") => {
+                dict.remove(b"Group");
+            },
+            Object::Stream(stream) if stream.dict.has(b"Subtype") && stream.dict.get(b"Subtype")?.as_name()? == b"Image" => {
+                // Image interpolation is forbidden
+                stream.dict.remove(b"Interpolate");
+            },
+            _ => {}
+        }
+    }
+
+    let mut icc_stream = Stream::new(dictionary! {
+        "N" => 3,
+    }, srgb_icc_profile());
+    icc_stream.compress()?;
+    let icc_id = document.add_object(icc_stream);
+
+    let output_intent_id = document.add_object(dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal(SRGB_PROFILE_NAME),
+        "Info" => Object::string_literal(SRGB_PROFILE_NAME),
+        "DestOutputProfile" => icc_id,
+    });
+
+    // Metadata streams must stay uncompressed
+    let metadata_stream = Stream::new(dictionary! {
+        "Type" => "Metadata",
+        "Subtype" => "XML",
+    }, xmp_metadata(&producer, doc_metadata).into_bytes()).with_compression(false);
+    let metadata_id = document.add_object(metadata_stream);
+
+    let mut info_dict = doc_metadata.to_info_dict();
+    info_dict.set("Producer", Object::string_literal(producer));
+    let info_id = document.add_object(info_dict);
+
+    if let Ok(Object::Dictionary(catalog)) = document.get_object_mut(catalog_id) {
+        catalog.set("Metadata", metadata_id);
+        catalog.set("OutputIntents", vec![output_intent_id.into()]);
+    }
+
+    let file_id = Object::String(Uuid::new_v4().as_bytes().to_vec(), StringFormat::Hexadecimal);
+    document.trailer.set("Info", info_id);
+    document.trailer.set("ID", vec![file_id.clone(), file_id]);
+    document.version = PDFA_VERSION.to_string();
+
+    Ok(())
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The Producer and document properties must match the ones of the document information dictionary
+fn xmp_metadata(producer: &str, doc_metadata: &DocumentMetadata) -> String {
+    let mut properties = String::new();
+
+    if let Some(title) = doc_metadata.title.as_deref() {
+        properties.push_str(&format!("      <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n", xml_escape(title)));
+    }
+
+    if let Some(author) = doc_metadata.author.as_deref() {
+        properties.push_str(&format!("      <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", xml_escape(author)));
+    }
+
+    if let Some(subject) = doc_metadata.subject.as_deref() {
+        properties.push_str(&format!("      <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n", xml_escape(subject)));
+    }
+
+    if let Some(creation_date) = doc_metadata.creation_date {
+        properties.push_str(&format!("      <xmp:CreateDate>{}</xmp:CreateDate>\n", creation_date.to_xmp_date()));
+    }
+
+    format!(r#"<?xpacket begin="{}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about=""
+        xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/"
+        xmlns:pdf="http://ns.adobe.com/pdf/1.3/"
+        xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+      <pdfaid:part>2</pdfaid:part>
+      <pdfaid:conformance>B</pdfaid:conformance>
+      <pdf:Producer>{}</pdf:Producer>
+      <dc:format>application/pdf</dc:format>
+{}    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#, '\u{feff}', producer, properties)
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn icc_xyz_tag(xyz: [f64; 3]) -> Vec<u8> {
+    let mut ret = b"XYZ \0\0\0\0".to_vec();
+
+    for v in xyz {
+        ret.extend_from_slice(&s15_fixed16(v));
+    }
+
+    ret
+}
+
+fn icc_text_tag(text: &str) -> Vec<u8> {
+    let mut ret = b"text\0\0\0\0".to_vec();
+    ret.extend_from_slice(text.as_bytes());
+    ret.push(0);
+
+    ret
+}
+
+fn icc_desc_tag(text: &str) -> Vec<u8> {
+    let mut ret = b"desc\0\0\0\0".to_vec();
+    ret.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+    ret.extend_from_slice(text.as_bytes());
+    ret.push(0);
+    // Empty Unicode and ScriptCode descriptions
+    ret.extend_from_slice(&[0u8; 8]);
+    ret.extend_from_slice(&[0u8; 3]);
+    ret.extend_from_slice(&[0u8; 67]);
+
+    ret
+}
+
+// The sRGB transfer function, sampled for the red, green and blue tone reproduction curves
+fn icc_curve_tag() -> Vec<u8> {
+    let mut ret = b"curv\0\0\0\0".to_vec();
+    ret.extend_from_slice(&(SRGB_CURVE_POINTS as u32).to_be_bytes());
+
+    for i in 0..SRGB_CURVE_POINTS {
+        let v = i as f64 / (SRGB_CURVE_POINTS - 1) as f64;
+        let linear = if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) };
+        ret.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    ret
+}
+
+// Builds a version 2 RGB display profile for sRGB, instead of shipping a binary ICC file
+fn srgb_icc_profile() -> Vec<u8> {
+    let curve = icc_curve_tag();
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", icc_desc_tag(SRGB_PROFILE_NAME)),
+        (b"cprt", icc_text_tag("No copyright, use freely")),
+        (b"wtpt", icc_xyz_tag(D50_WHITE_POINT)),
+        (b"rXYZ", icc_xyz_tag(SRGB_RED)),
+        (b"gXYZ", icc_xyz_tag(SRGB_GREEN)),
+        (b"bXYZ", icc_xyz_tag(SRGB_BLUE)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut tag_table = Vec::new();
+    let mut tag_data = Vec::new();
+    let data_start = 128 + 4 + tags.len() * 12;
+
+    tag_table.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+
+    for (signature, data) in tags.iter() {
+        tag_table.extend_from_slice(*signature);
+        tag_table.extend_from_slice(&((data_start + tag_data.len()) as u32).to_be_bytes());
+        tag_table.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        tag_data.extend_from_slice(data);
+
+        // Tag data is aligned on 4 bytes
+        while tag_data.len() % 4 != 0 {
+            tag_data.push(0);
+        }
+    }
+
+    let profile_size = data_start + tag_data.len();
+    let mut ret = Vec::with_capacity(profile_size);
+
+    ret.extend_from_slice(&(profile_size as u32).to_be_bytes());
+    ret.extend_from_slice(&[0u8; 4]);                      // preferred CMM
+    ret.extend_from_slice(&0x0210_0000u32.to_be_bytes());  // version 2.1
+    ret.extend_from_slice(b"mntr");
+    ret.extend_from_slice(b"RGB ");
+    ret.extend_from_slice(b"XYZ ");
+
+    for v in [2023u16, 1, 1, 0, 0, 0] {                    // creation date
+        ret.extend_from_slice(&v.to_be_bytes());
+    }
+
+    ret.extend_from_slice(b"acsp");
+    ret.extend_from_slice(&[0u8; 24]);                     // platform, flags, manufacturer, model and attributes
+    ret.extend_from_slice(&[0u8; 4]);                      // perceptual rendering intent
+
+    for v in D50_WHITE_POINT {
+        ret.extend_from_slice(&s15_fixed16(v));
+    }
+
+    ret.extend_from_slice(&[0u8; 4]);                      // creator
+    ret.extend_from_slice(&[0u8; 44]);                     // profile ID and reserved bytes
+    ret.extend_from_slice(&tag_table);
+    ret.extend_from_slice(&tag_data);
+
+    ret
+}
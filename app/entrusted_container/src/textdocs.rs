@@ -0,0 +1,337 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use entrusted_l10n as l10n;
+
+// Byte order marks
+const BOM_UTF8: &[u8]     = &[0xEF, 0xBB, 0xBF];
+const BOM_UTF16_LE: &[u8] = &[0xFF, 0xFE];
+const BOM_UTF16_BE: &[u8] = &[0xFE, 0xFF];
+
+// Elements removed along with their contents: scripts, embedded documents and styles that could load remote resources
+const HTML_DROPPED_CONTAINERS: [&str; 11] = ["script", "style", "iframe", "object", "embed", "applet", "frameset", "noembed", "noframes", "template", "svg"];
+
+// Elements removed without any content, they only reference or describe external resources
+const HTML_DROPPED_ELEMENTS: [&str; 9] = ["link", "base", "meta", "frame", "param", "source", "track", "portal", "img"];
+
+// Attributes with URL values, only internal links are kept
+const HTML_URL_ATTRIBUTES: [&str; 14] = ["href", "src", "srcset", "lowsrc", "dynsrc", "background", "data", "poster", "action", "formaction", "cite", "longdesc", "codebase", "xlink:href"];
+
+// Returns the text of UTF-8 or UTF-16 input, binary data and unknown encodings are rejected
+pub fn decode_text(data: &[u8]) -> Option<String> {
+    let text = if let Some(v) = data.strip_prefix(BOM_UTF8) {
+        String::from_utf8(v.to_vec()).ok()?
+    } else if let Some(v) = data.strip_prefix(BOM_UTF16_LE) {
+        decode_utf16(v, u16::from_le_bytes)?
+    } else if let Some(v) = data.strip_prefix(BOM_UTF16_BE) {
+        decode_utf16(v, u16::from_be_bytes)?
+    } else {
+        String::from_utf8(data.to_vec()).ok()?
+    };
+
+    // Tabs, line breaks and form feeds are the only expected control characters
+    if text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c')) {
+        return None;
+    }
+
+    Some(text)
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    let chunks = data.chunks_exact(2);
+
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+
+    let units: Vec<u16> = chunks.map(|v| from_bytes([v[0], v[1]])).collect();
+    String::from_utf16(&units).ok()
+}
+
+// Only the beginning of the document is inspected, after any leading comment or whitespace
+pub fn looks_like_html(text: &str) -> bool {
+    let prefix: String = text.chars().take(1024).collect::<String>().to_ascii_lowercase();
+    let prefix = prefix.trim_start();
+
+    ["<!doctype html", "<html", "<head", "<body"].iter().any(|v| prefix.starts_with(v))
+        || (prefix.starts_with("<!--") && ["<html", "<head", "<body"].iter().any(|v| prefix.contains(v)))
+}
+
+// FictionBook e-books are XML documents with a 'FictionBook' root element
+pub fn looks_like_fictionbook(text: &str) -> bool {
+    let prefix: String = text.chars().take(1024).collect();
+    prefix.trim_start().starts_with("<?xml") && prefix.contains("<FictionBook")
+}
+
+// Text inputs are saved as UTF-8 with a byte order mark, so that LibreOffice doesn't guess their encoding
+// HTML documents are sanitized first, LibreOffice would otherwise fetch remote images and stylesheets
+pub fn write_office_input(mime_type: &str, src_path: &Path, dest_path: &Path, l10n: l10n::Translations) -> Result<(), Box<dyn Error>> {
+    let text = match decode_text(&fs::read(src_path)?) {
+        Some(v) => v,
+        None => return Err(l10n.gettext("Could not decode the text of the input document").into())
+    };
+
+    let text = if mime_type == "text/html" {
+        sanitize_html(&text)
+    } else {
+        text
+    };
+
+    let mut data = BOM_UTF8.to_vec();
+    data.extend(text.into_bytes());
+    fs::write(dest_path, data)?;
+
+    Ok(())
+}
+
+// Removes scripts, event handlers, embedded objects and any reference to external resources
+// Documents are rewritten tag by tag, the text between tags is kept as-is
+pub fn sanitize_html(html: &str) -> String {
+    let mut ret = String::with_capacity(html.len());
+    let mut rest = html;
+    let mut opt_dropped_container: Option<(String, usize)> = None; // element name and nesting depth
+
+    while let Some(tag_start) = rest.find('<') {
+        if opt_dropped_container.is_none() {
+            ret.push_str(&rest[..tag_start]);
+        }
+
+        rest = &rest[tag_start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|idx| &comment[idx + 3..]).unwrap_or("");
+            continue;
+        }
+
+        let is_markup = rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?');
+        let opt_tag_end = if is_markup { html_tag_end(rest) } else { None };
+
+        let tag_end = match opt_tag_end {
+            Some(v) => v,
+            None => {
+                if opt_dropped_container.is_none() {
+                    ret.push_str("&lt;");
+                }
+
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        let is_closing = tag.starts_with('/');
+        let tag_body = tag.trim_start_matches('/');
+        let name_len = tag_body.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag_body.len());
+        let name = tag_body[..name_len].to_ascii_lowercase();
+
+        // Nested elements of the same name (i.e. svg inside svg) don't end the dropped container early
+        if let Some((dropped_name, depth)) = opt_dropped_container.as_mut() {
+            if &name == dropped_name {
+                if is_closing {
+                    *depth -= 1;
+                } else if !tag.ends_with('/') {
+                    *depth += 1;
+                }
+            }
+
+            if *depth == 0 {
+                opt_dropped_container = None;
+            }
+
+            continue;
+        }
+
+        // Doctypes, processing instructions and CDATA sections
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+
+        if HTML_DROPPED_CONTAINERS.contains(&name.as_str()) {
+            if !is_closing && !tag.ends_with('/') {
+                opt_dropped_container = Some((name, 1));
+            }
+
+            continue;
+        }
+
+        if HTML_DROPPED_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+
+        if is_closing {
+            ret.push_str(&format!("</{}>", name));
+        } else {
+            ret.push('<');
+            ret.push_str(&name);
+
+            for (attr_name, attr_value) in html_attributes(&tag_body[name_len..]) {
+                if is_safe_html_attribute(&attr_name, &attr_value) {
+                    ret.push_str(&format!(" {}=\"{}\"", attr_name, attr_value.replace('"', "&quot;")));
+                }
+            }
+
+            ret.push('>');
+        }
+    }
+
+    if opt_dropped_container.is_none() {
+        ret.push_str(rest);
+    }
+
+    ret
+}
+
+// Complete documents (i.e. e-book chapters or e-mail bodies) are merged into another document, only their body is kept
+pub fn html_body(html: &str) -> &str {
+    let lowercase_html = html.to_ascii_lowercase();
+
+    let body_start = lowercase_html.find("<body")
+        .and_then(|idx| html_tag_end(&html[idx..]).map(|tag_end| idx + tag_end + 1));
+
+    match body_start {
+        Some(start) => {
+            let end = lowercase_html[start..].rfind("</body").map(|idx| start + idx).unwrap_or(html.len());
+            &html[start..end]
+        },
+        None => html
+    }
+}
+
+// Position of the closing bracket of a tag, ignoring brackets in quoted attribute values
+pub fn html_tag_end(tag: &str) -> Option<usize> {
+    let mut opt_quote: Option<char> = None;
+
+    for (idx, c) in tag.char_indices().skip(1) {
+        match opt_quote {
+            Some(quote) if c == quote => opt_quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => opt_quote = Some(c),
+            None if c == '>' => return Some(idx),
+            None => {}
+        }
+    }
+
+    None
+}
+
+// Attribute names are lowercased, values are returned without their quotes
+pub fn html_attributes(text: &str) -> Vec<(String, String)> {
+    let mut ret = Vec::new();
+    let mut chars = text.trim_end_matches('/').chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == '/').is_some() {}
+
+        let mut name = String::new();
+
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=' && *c != '/') {
+            name.push(c.to_ascii_lowercase());
+        }
+
+        if name.is_empty() {
+            break;
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            match chars.next_if(|c| *c == '"' || *c == '\'') {
+                Some(quote) => {
+                    for c in chars.by_ref() {
+                        if c == quote {
+                            break;
+                        }
+
+                        value.push(c);
+                    }
+                },
+                None => {
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                        value.push(c);
+                    }
+                }
+            }
+        }
+
+        ret.push((name, value));
+    }
+
+    ret
+}
+
+// Inline styles are dropped, HTML entities and CSS escapes can hide remote resources from any simple check (i.e. 'u&#114;l(')
+fn is_safe_html_attribute(name: &str, value: &str) -> bool {
+    if name.starts_with("on") || name == "http-equiv" || name == "style" {
+        return false;
+    }
+
+    if HTML_URL_ATTRIBUTES.contains(&name) {
+        return value.trim_start().starts_with('#');
+    }
+
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_html_drops_style_attributes() {
+        let samples = [
+            "<p style=\"background:url(http://x)\">text</p>",
+            "<p style=\"background:u&#114;l(http://x)\">text</p>",
+            "<p style=\"background:u\\rl(http://x)\">text</p>",
+            "<p style=\"background:&#x75;&#x72;&#x6c;(http://x)\">text</p>",
+            "<p STYLE='@\\69mport \"http://x\"'>text</p>",
+        ];
+
+        for sample in samples {
+            assert_eq!(sanitize_html(sample), "<p>text</p>", "{}", sample);
+        }
+    }
+
+    #[test]
+    fn test_sanitize_html_nested_dropped_containers() {
+        assert_eq!(sanitize_html("<svg><svg></svg><a href=\"http://x\">link</a></svg>after"), "after");
+        assert_eq!(sanitize_html("<svg><svg/><p>inside</p></svg>after"), "after");
+        assert_eq!(sanitize_html("<svg><svg><svg></svg></svg><image href=\"http://x\"/></svg><p>after</p>"), "<p>after</p>");
+        assert_eq!(sanitize_html("<script>if (a < b) { document.write('</p>'); }</script>text"), "text");
+        assert_eq!(sanitize_html("<svg>never closed<p>text</p>"), "");
+    }
+
+    #[test]
+    fn test_sanitize_html_attribute_quoting() {
+        assert_eq!(sanitize_html("<a href=http://x title=hello>link</a>"), "<a title=\"hello\">link</a>");
+        assert_eq!(sanitize_html("<a href=#top>link</a>"), "<a href=\"#top\">link</a>");
+        assert_eq!(sanitize_html("<p title='a\"b' onclick=\"x\">text</p>"), "<p title=\"a&quot;b\">text</p>");
+        assert_eq!(sanitize_html("<p title=\"a'onclick='x\">text</p>"), "<p title=\"a'onclick='x\">text</p>");
+        assert_eq!(sanitize_html("<p title=a\"onclick=x>text</p>"), "&lt;p title=a\"onclick=x>text</p>"); // unmatched quote, not a tag
+        assert_eq!(sanitize_html("<p title=\"a>b\" onmouseover=x>text</p>"), "<p title=\"a>b\">text</p>");
+        assert_eq!(sanitize_html("<p/onclick=x>text</p>"), "<p>text</p>");
+        assert_eq!(sanitize_html("<img\nsrc=http://x>text"), "text");
+    }
+
+    #[test]
+    fn test_sanitize_html_urls() {
+        assert_eq!(sanitize_html("<a href=\" http://x\">link</a>"), "<a>link</a>");
+        assert_eq!(sanitize_html("<a href=\"&#35;top\">link</a>"), "<a>link</a>");
+        assert_eq!(sanitize_html("<form action=\"http://x\"><input formaction=\"http://x\"></form>"), "<form><input></form>");
+        assert_eq!(sanitize_html("<table background=\"http://x\"><tr><td>cell</td></tr></table>"), "<table><tr><td>cell</td></tr></table>");
+        assert_eq!(sanitize_html("<meta http-equiv=\"refresh\" content=\"0;url=http://x\">text"), "text");
+    }
+
+    #[test]
+    fn test_sanitize_html_text() {
+        assert_eq!(sanitize_html("a < b and <!-- <script> --> c"), "a &lt; b and  c");
+        assert_eq!(sanitize_html("<p>unterminated <a href=\"#x"), "<p>unterminated &lt;a href=\"#x");
+        assert_eq!(sanitize_html("<!DOCTYPE html><?xml version=\"1.0\"?><p>text</p>"), "<p>text</p>");
+    }
+}
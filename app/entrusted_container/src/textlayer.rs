@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use glib::translate::ToGlibPtr;
+use lopdf::{dictionary, Object, Stream};
+use poppler::Document;
+
+pub const TEXT_LAYER_OCR: &str      = "ocr";
+pub const TEXT_LAYER_ORIGINAL: &str = "original";
+pub const TEXT_LAYER_CHOICES: [&str; 2] = [TEXT_LAYER_OCR, TEXT_LAYER_ORIGINAL];
+
+const FONT_RESOURCE_NAME: &str = "FEntrustedText";
+
+// Every glyph of the invisible font is half as wide as it's tall, words are stretched to their original width
+const GLYPH_WIDTH: f64 = 0.5;
+
+// Where the searchable text of the result comes from
+// OCR is only performed when a language is selected, the original text falls back to OCR for pages without text
+#[derive(Clone, Copy, Debug)]
+pub enum TextLayer {
+    Ocr,
+    Original,
+}
+
+impl TextLayer {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            TEXT_LAYER_ORIGINAL => TextLayer::Original,
+            _                   => TextLayer::Ocr
+        }
+    }
+}
+
+// A word of the source page, in points with the origin at the top left corner
+struct TextWord {
+    text: String,
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+}
+
+// Plain text and word boxes of a source page, fonts and any other page contents are left behind
+pub struct PageText {
+    page_size: (f64, f64),
+    words: Vec<TextWord>,
+}
+
+// Returns the text of the selected pages that have extractable text
+pub fn extract_pages_text(doc: &Document, page_numbers: &[usize]) -> BTreeMap<usize, PageText> {
+    page_numbers
+        .iter()
+        .filter_map(|page_num| {
+            doc.page((*page_num - 1) as i32)
+                .and_then(|page| extract_page_text(&page))
+                .map(|page_text| (*page_num, page_text))
+        })
+        .collect()
+}
+
+// Poppler returns one box per character of the page text, including line breaks
+pub fn extract_page_text(page: &poppler::Page) -> Option<PageText> {
+    let text = page.text()?;
+
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    let mut rects_ptr: *mut poppler_sys::PopplerRectangle = std::ptr::null_mut();
+    let mut rect_count: std::os::raw::c_uint = 0;
+
+    let char_boxes: Vec<(f64, f64, f64, f64)> = unsafe {
+        if poppler_sys::poppler_page_get_text_layout(page.to_glib_none().0, &mut rects_ptr, &mut rect_count) == 0 || rects_ptr.is_null() {
+            return None;
+        }
+
+        let ret = std::slice::from_raw_parts(rects_ptr, rect_count as usize)
+            .iter()
+            .map(|rect| (rect.x1, rect.y1, rect.x2, rect.y2))
+            .collect();
+        glib::ffi::g_free(rects_ptr as glib::ffi::gpointer);
+        ret
+    };
+
+    let mut words: Vec<TextWord> = Vec::new();
+    let mut opt_word: Option<TextWord> = None;
+
+    for (c, (x1, y1, x2, y2)) in text.chars().zip(char_boxes) {
+        if c.is_whitespace() || c.is_control() {
+            if let Some(word) = opt_word.take() {
+                words.push(word);
+            }
+
+            continue;
+        }
+
+        // Characters of a new line or column can follow each other without any whitespace in between
+        if let Some(word) = opt_word.as_ref() {
+            if x1 < word.x1 || y1 >= word.y2 || y2 <= word.y1 {
+                words.extend(opt_word.take());
+            }
+        }
+
+        match opt_word.as_mut() {
+            Some(word) => {
+                word.text.push(c);
+                word.x2 = word.x2.max(x2);
+                word.y1 = word.y1.min(y1);
+                word.y2 = word.y2.max(y2);
+            },
+            None => {
+                opt_word = Some(TextWord { text: c.to_string(), x1, y1, x2, y2 });
+            }
+        }
+    }
+
+    words.extend(opt_word);
+
+    if words.is_empty() {
+        None
+    } else {
+        Some(PageText { page_size: page.size(), words })
+    }
+}
+
+pub fn add_text_layer_to_file(pdf_path: &Path, page_text: &PageText) -> Result<(), Box<dyn Error>> {
+    let mut page_doc = lopdf::Document::load(pdf_path)?;
+    add_text_layer(&mut page_doc, page_text)?;
+    page_doc.save(pdf_path)?;
+
+    Ok(())
+}
+
+// Draws the words with the invisible text rendering mode over the single page of a rasterized page document
+// The page is scaled from the source page size, the rasterized page has the same aspect ratio
+pub fn add_text_layer(page_doc: &mut lopdf::Document, page_text: &PageText) -> Result<(), Box<dyn Error>> {
+    let page_id = match page_doc.get_pages().into_values().next() {
+        Some(v) => v,
+        None => return Ok(())
+    };
+
+    let page_dict = page_doc.get_object(page_id)?.as_dict()?;
+    let media_box: Vec<f64> = page_dict.get(b"MediaBox")?.as_array()?.iter().map(|v| v.as_float().map(f64::from)).collect::<Result<_, _>>()?;
+
+    if media_box.len() != 4 {
+        return Ok(());
+    }
+
+    let (page_width, page_height) = (media_box[2] - media_box[0], media_box[3] - media_box[1]);
+    let (scale_x, scale_y) = (page_width / page_text.page_size.0, page_height / page_text.page_size.1);
+
+    // The graphics state of the page contents is restored first, so that the text isn't affected by their transformations
+    let mut content = format!("Q\nBT 3 Tr /{} 1 Tf\n", FONT_RESOURCE_NAME);
+
+    for word in page_text.words.iter() {
+        let font_size = (word.y2 - word.y1) * scale_y;
+        let word_width = (word.x2 - word.x1) * scale_x;
+        let char_count = word.text.chars().count() as f64;
+
+        if font_size <= 0.0 || word_width <= 0.0 {
+            continue;
+        }
+
+        let horizontal_scaling = 100.0 * word_width / (char_count * GLYPH_WIDTH * font_size);
+        let (x, y) = (media_box[0] + word.x1 * scale_x, media_box[1] + page_height - word.y2 * scale_y);
+
+        content.push_str(&format!("{:.2} Tz {:.4} 0 0 {:.4} {:.2} {:.2} Tm <{}> Tj\n",
+                                  horizontal_scaling, font_size, font_size, x, y, encode_cids(&word.text)));
+    }
+
+    content.push_str("ET\n");
+
+    // The text is drawn last, on top of the page image
+    let mut contents = match page_dict.get(b"Contents") {
+        Ok(Object::Array(v)) => v.clone(),
+        Ok(v) => vec![v.clone()],
+        Err(_) => vec![],
+    };
+
+    // Indirect resources are copied into the page, before adding the font
+    let mut resources = resolve_dict(page_doc, page_dict.get(b"Resources").ok())?;
+    let mut fonts = resolve_dict(page_doc, resources.get(b"Font").ok())?;
+
+    let mut content_stream = Stream::new(dictionary! {}, content.into_bytes());
+    content_stream.compress()?;
+    contents.push(page_doc.add_object(content_stream).into());
+    contents.insert(0, page_doc.add_object(Stream::new(dictionary! {}, b"q\n".to_vec())).into());
+
+    fonts.set(FONT_RESOURCE_NAME, add_invisible_font(page_doc)?);
+    resources.set("Font", fonts);
+
+    let page_dict = page_doc.get_object_mut(page_id)?.as_dict_mut()?;
+    page_dict.set("Contents", contents);
+    page_dict.set("Resources", resources);
+
+    Ok(())
+}
+
+fn resolve_dict(page_doc: &lopdf::Document, opt_object: Option<&Object>) -> Result<lopdf::Dictionary, Box<dyn Error>> {
+    match opt_object {
+        Some(Object::Dictionary(v)) => Ok(v.clone()),
+        Some(Object::Reference(id)) => Ok(page_doc.get_object(*id)?.as_dict()?.clone()),
+        _ => Ok(dictionary! {}),
+    }
+}
+
+// Character codes are Unicode code points, only the Basic Multilingual Plane is supported
+fn encode_cids(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            let code_point = c as u32;
+
+            if code_point > 0xFFFF {
+                0xFFFD
+            } else {
+                code_point
+            }
+        })
+        .map(|code_point| format!("{:04X}", code_point))
+        .collect()
+}
+
+// A font without glyphs, that only maps character codes back to Unicode for text extraction and search
+// Fonts used only for invisible text don't need to be embedded, including in PDF/A-2 documents
+fn add_invisible_font(page_doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Box<dyn Error>> {
+    let font_descriptor_id = page_doc.add_object(dictionary! {
+        "Type" => "FontDescriptor",
+        "FontName" => "GlyphLessFont",
+        "Flags" => 5,
+        "FontBBox" => vec![0.into(), 0.into(), ((GLYPH_WIDTH * 1000.0) as i64).into(), 1000.into()],
+        "ItalicAngle" => 0,
+        "Ascent" => 1000,
+        "Descent" => 0,
+        "CapHeight" => 1000,
+        "StemV" => 80,
+    });
+
+    let cid_font_id = page_doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "CIDFontType2",
+        "BaseFont" => "GlyphLessFont",
+        "CIDSystemInfo" => dictionary! {
+            "Registry" => Object::string_literal("Adobe"),
+            "Ordering" => Object::string_literal("Identity"),
+            "Supplement" => 0,
+        },
+        "FontDescriptor" => font_descriptor_id,
+        "DW" => (GLYPH_WIDTH * 1000.0) as i64,
+        "CIDToGIDMap" => "Identity",
+    });
+
+    let mut to_unicode_stream = Stream::new(dictionary! {}, to_unicode_cmap().into_bytes());
+    to_unicode_stream.compress()?;
+    let to_unicode_id = page_doc.add_object(to_unicode_stream);
+
+    Ok(page_doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type0",
+        "BaseFont" => "GlyphLessFont",
+        "Encoding" => "Identity-H",
+        "DescendantFonts" => vec![cid_font_id.into()],
+        "ToUnicode" => to_unicode_id,
+    }))
+}
+
+// Identity mapping of 2 bytes codes to UTF-16, ranges can't span more than the last byte and surrogates are skipped
+fn to_unicode_cmap() -> String {
+    let ranges: Vec<String> = (0x00..=0xFFu32)
+        .filter(|high_byte| !(0xD8..=0xDF).contains(high_byte))
+        .map(|high_byte| format!("<{0:02X}00> <{0:02X}FF> <{0:02X}00>", high_byte))
+        .collect();
+
+    let mut ret = String::from("/CIDInit /ProcSet findresource begin\n\
+                                12 dict begin\n\
+                                begincmap\n\
+                                /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+                                /CMapName /Adobe-Identity-UCS def\n\
+                                /CMapType 2 def\n\
+                                1 begincodespacerange\n\
+                                <0000> <FFFF>\n\
+                                endcodespacerange\n");
+
+    // At most 100 entries per block
+    for chunk in ranges.chunks(100) {
+        ret.push_str(&format!("{} beginbfrange\n{}\nendbfrange\n", chunk.len(), chunk.join("\n")));
+    }
+
+    ret.push_str("endcmap\n\
+                  CMapName currentdict /CMap defineresource pop\n\
+                  end\n\
+                  end\n");
+
+    ret
+}
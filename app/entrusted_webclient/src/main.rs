@@ -26,6 +26,8 @@ const CFG_FILENAME: &str = "config.toml";
 
 pub const IMAGE_QUALITY_CHOICES: [&str; 3] = ["low", "medium", "high"];
 pub const IMAGE_QUALITY_DEFAULT_CHOICE_INDEX: usize = 1;
+pub const TEXT_LAYER_CHOICES: [&str; 2] = ["ocr", "original"];
+pub const IMAGE_ENCODING_CHOICES: [&str; 3] = ["lossless", "jpeg", "bilevel"];
 
 static INSTANCE_HOST: OnceCell<String> = OnceCell::new();
 static INSTANCE_PORT: OnceCell<String> = OnceCell::new();
@@ -117,6 +119,13 @@ pub struct ConversionOptions {
     pub visual_quality: String,
     pub opt_ocr_lang: Option<String>,
     pub opt_passwd: Option<String>,
+    pub opt_page_ranges: Option<String>,
+    pub preserve_page_size: bool,
+    pub opt_image_encoding: Option<String>,
+    pub opt_jpeg_quality: Option<u8>,
+    pub pdfa: bool,
+    pub strip_metadata: bool,
+    pub opt_text_layer: Option<String>,
     pub file_suffix: String,
 }
 
@@ -132,10 +141,32 @@ pub struct DownloadResponse {
     pub data: String,
 }
 
+// Progress events of entrusted-webserver, the data field is the human-readable message
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct LogMessage {
+    #[serde(default)]
+    pub event: String,
     pub data: String,
     pub percent_complete: usize,
+    #[serde(default)]
+    pub page_index: Option<usize>,
+    #[serde(default)]
+    pub page_count: Option<usize>,
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+impl LogMessage {
+    // Pages are followed by their position in the phase, and failure messages by their error code
+    pub fn display_text(&self) -> String {
+        if let Some(error_code) = self.error_code.as_ref() {
+            format!("{} [{}]", self.data, error_code)
+        } else if let ("page_done", Some(page_index), Some(page_count)) = (self.event.as_str(), self.page_index, self.page_count) {
+            format!("{} ({}/{})", self.data, page_index, page_count)
+        } else {
+            self.data.clone()
+        }
+    }
 }
 
 #[tokio::main]
@@ -168,6 +199,13 @@ async fn process_cli_args() -> Result<(), Box<dyn Error + Send + Sync>> {
     let help_file_suffix = trans.gettext("Default file suffix (entrusted)");
     let help_password_prompt = trans.gettext("Prompt for document password");
     let help_visual_quality = trans.gettext("PDF result visual quality");
+    let help_preserve_page_size = trans.gettext("Keep the original size and orientation of each page");
+    let help_image_encoding = trans.gettext("Optional encoding of the page images in the PDF result (lossless, jpeg or bilevel for text documents)");
+    let help_jpeg_quality = trans.gettext("Optional JPEG quality between 1 and 100 for the jpeg image encoding");
+    let help_pdfa = trans.gettext("Produce a PDF/A-2b document for long-term archiving");
+    let help_text_layer = trans.gettext("Optional source of the searchable text (ocr or original, which falls back to OCR for pages without text)");
+    let help_strip_metadata = trans.gettext("Do not carry over the document title, author, subject and creation date");
+    let help_pages = trans.gettext("Optional selection of pages to sanitize (i.e. '1-5,9,12-')");
     
     INSTANCE_HOST.set(appconfig.host.to_owned())?;
     INSTANCE_PORT.set(appconfig.port.to_string())?;
@@ -240,6 +278,54 @@ async fn process_cli_args() -> Result<(), Box<dyn Error + Send + Sync>> {
                 .help(&help_password_prompt)
                 .required(false)
                 .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("preserve-page-size")
+                .long("preserve-page-size")
+                .help(&help_preserve_page_size)
+                .required(false)
+                .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("image-encoding")
+                .long("image-encoding")
+                .help(&help_image_encoding)
+                .required(false)
+                .value_parser([
+                    PossibleValue::new(IMAGE_ENCODING_CHOICES[0]),
+                    PossibleValue::new(IMAGE_ENCODING_CHOICES[1]),
+                    PossibleValue::new(IMAGE_ENCODING_CHOICES[2]),
+                ])
+        ).arg(
+            Arg::new("jpeg-quality")
+                .long("jpeg-quality")
+                .help(&help_jpeg_quality)
+                .value_parser(clap::value_parser!(u8).range(1..=100))
+                .required(false)
+        ).arg(
+            Arg::new("pdfa")
+                .long("pdfa")
+                .help(&help_pdfa)
+                .required(false)
+                .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("text-layer")
+                .long("text-layer")
+                .help(&help_text_layer)
+                .value_parser([
+                    PossibleValue::new(TEXT_LAYER_CHOICES[0]),
+                    PossibleValue::new(TEXT_LAYER_CHOICES[1]),
+                ])
+                .required(false)
+        ).arg(
+            Arg::new("strip-metadata")
+                .long("strip-metadata")
+                .help(&help_strip_metadata)
+                .required(false)
+                .action(ArgAction::SetTrue)
+        ).arg(
+            Arg::new("pages")
+                .long("pages")
+                .help(&help_pages)
+                .required(false)
         );
 
     let run_matches = app.to_owned().get_matches();
@@ -284,6 +370,13 @@ async fn process_cli_args() -> Result<(), Box<dyn Error + Send + Sync>> {
     }
 
     let output_path_opt = run_matches.get_one::<String>("output-filename").map(PathBuf::from);
+    let opt_page_ranges = run_matches.get_one::<String>("pages").cloned();
+    let preserve_page_size = run_matches.get_flag("preserve-page-size");
+    let opt_image_encoding = run_matches.get_one::<String>("image-encoding").cloned();
+    let opt_jpeg_quality = run_matches.get_one::<u8>("jpeg-quality").copied();
+    let pdfa = run_matches.get_flag("pdfa");
+    let strip_metadata = run_matches.get_flag("strip-metadata");
+    let opt_text_layer = run_matches.get_one::<String>("text-layer").cloned();
 
     let file_suffix = if let Some(proposed_file_suffix) = run_matches.get_one::<String>("file-suffix") {
         proposed_file_suffix.to_string()
@@ -326,6 +419,13 @@ async fn process_cli_args() -> Result<(), Box<dyn Error + Send + Sync>> {
                 visual_quality: visual_quality.to_string(),
                 opt_ocr_lang,
                 opt_passwd,
+                opt_page_ranges,
+                preserve_page_size,
+                opt_image_encoding,
+                opt_jpeg_quality,
+                pdfa,
+                strip_metadata,
+                opt_text_layer,
                 file_suffix,
             };
             convert_file(conversion_options, output_dir.to_path_buf(), p.clone(), filename.to_string(), output_path_opt, trans.clone()).await
@@ -362,6 +462,34 @@ async fn convert_file (
         multipart_form = multipart_form.text("ocrlang", ocr_lang);
     }
 
+    if conversion_options.preserve_page_size {
+        multipart_form = multipart_form.text("preservepagesize", "true");
+    }
+
+    if let Some(image_encoding) = conversion_options.opt_image_encoding {
+        multipart_form = multipart_form.text("imageencoding", image_encoding);
+    }
+
+    if let Some(jpeg_quality) = conversion_options.opt_jpeg_quality {
+        multipart_form = multipart_form.text("jpegquality", jpeg_quality.to_string());
+    }
+
+    if conversion_options.pdfa {
+        multipart_form = multipart_form.text("pdfa", "true");
+    }
+
+    if conversion_options.strip_metadata {
+        multipart_form = multipart_form.text("stripmetadata", "true");
+    }
+
+    if let Some(text_layer) = conversion_options.opt_text_layer {
+        multipart_form = multipart_form.text("textlayer", text_layer);
+    }
+
+    if let Some(page_ranges) = conversion_options.opt_page_ranges {
+        multipart_form = multipart_form.text("pages", page_ranges);
+    }
+
     if let Some(passwd) = conversion_options.opt_passwd {
         multipart_form = multipart_form.text("docpasswd", passwd);
     }
@@ -420,7 +548,7 @@ async fn process_notifications(tracking_url: String,
 
                         if let Ok(log_msg) = log_msg_ret {
                             pb.set_position(log_msg.percent_complete as u64);
-                            pb.println(&log_msg.data);
+                            pb.println(log_msg.display_text());
                         }
                     } else if msg.event == "processing_success" {
                         let log_msg_ret: serde_json::Result<LogMessage> = serde_json::from_str(&msg.data);
@@ -436,14 +564,19 @@ async fn process_notifications(tracking_url: String,
                         return Ok(download_uri);
                     } else if msg.event == "processing_failure" {
                         let log_msg_ret: serde_json::Result<LogMessage> = serde_json::from_str(&msg.data);
+                        let mut err_msg = l10n.gettext("Conversion failed!");
 
                         if let Ok(log_msg) = log_msg_ret {
                             pb.set_position(log_msg.percent_complete as u64);
+
+                            if let Some(error_code) = log_msg.error_code {
+                                err_msg = format!("{} [{}]", err_msg, error_code);
+                            }
                         }
 
                         es.close();
 
-                        return Err(l10n.gettext("Conversion failed!").into());
+                        return Err(err_msg.into());
                     }
                 },
                 Err(err) => {
@@ -16,6 +16,14 @@ pub struct AppConfig {
         deserialize = "container-image-name"
     ))]
     pub container_image_name: String,
+    #[serde(rename(serialize = "max-input-size", deserialize = "max-input-size"))]
+    pub max_input_size: Option<u64>,
+    #[serde(rename(serialize = "max-pages", deserialize = "max-pages"))]
+    pub max_pages: Option<u64>,
+    #[serde(rename(serialize = "max-page-area", deserialize = "max-page-area"))]
+    pub max_page_area: Option<u64>,
+    #[serde(rename(serialize = "max-total-pixels", deserialize = "max-total-pixels"))]
+    pub max_total_pixels: Option<u64>,
 }
 
 pub fn default_container_image_name() -> String {
@@ -33,6 +41,10 @@ impl Default for AppConfig {
             host: "localhost".to_string(),
             port: 13000,
             container_image_name: default_container_image_name(),
+            max_input_size: None,
+            max_pages: None,
+            max_page_area: None,
+            max_total_pixels: None,
         }
     }
 }
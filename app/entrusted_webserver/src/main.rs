@@ -1,4 +1,5 @@
 use entrusted_l10n as l10n;
+use entrusted_common::limits;
 use std::collections::HashMap;
 use std::env;
 use once_cell::sync::OnceCell;
@@ -53,6 +54,10 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let help_host = l10n.gettext("Server host");
     let help_port = l10n.gettext("Server port");
     let help_container_image_name = l10n.gettext("Container image name");
+    let help_max_input_size = l10n.gettext("Optional maximum input file size in megabytes");
+    let help_max_pages = l10n.gettext("Optional maximum number of pages to process");
+    let help_max_page_area = l10n.gettext("Optional maximum page area in square inches");
+    let help_max_total_pixels = l10n.gettext("Optional maximum number of pixels to render for all pages");
 
     let appconfig: config::AppConfig = config::load_config()?;
     let port_number_text = format!("{}", appconfig.port);
@@ -95,6 +100,34 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help(&help_container_image_name)
                 .required(false)
                 .default_value(default_container_image_to_str())
+        )
+        .arg(
+            clap::Arg::new("max-input-size")
+                .long("max-input-size")
+                .help(&help_max_input_size)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        )
+        .arg(
+            clap::Arg::new("max-pages")
+                .long("max-pages")
+                .help(&help_max_pages)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        )
+        .arg(
+            clap::Arg::new("max-page-area")
+                .long("max-page-area")
+                .help(&help_max_page_area)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+        )
+        .arg(
+            clap::Arg::new("max-total-pixels")
+                .long("max-total-pixels")
+                .help(&help_max_total_pixels)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
         );
 
     let run_matches = app.to_owned().get_matches();
@@ -104,6 +137,13 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
         _              => appconfig.container_image_name.clone(),
     };
 
+    let resource_limits = limits::ResourceLimits {
+        opt_max_input_size: run_matches.get_one::<u64>("max-input-size").copied().or(appconfig.max_input_size),
+        opt_max_pages: run_matches.get_one::<u64>("max-pages").copied().or(appconfig.max_pages),
+        opt_max_page_area: run_matches.get_one::<u64>("max-page-area").copied().or(appconfig.max_page_area),
+        opt_max_total_pixels: run_matches.get_one::<u64>("max-total-pixels").copied().or(appconfig.max_total_pixels),
+    };
+
     if let (Some(host), Some(port)) = (run_matches.get_one::<String>("host"), run_matches.get_one::<String>("port")) {
         if let Err(ex) = port.parse::<u16>() {
             return Err(format!(
@@ -115,7 +155,7 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .into());
         }
 
-        if let Err(ex) = server::serve(host, port, ci_image_name, l10n.clone()).await {
+        if let Err(ex) = server::serve(host, port, ci_image_name, resource_limits, l10n.clone()).await {
             Err(ex)
         } else {
             Ok(())
@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 
+use entrusted_common::limits;
+
+// The failure of a conversion is kept with its last notification, until the result is downloaded
 #[derive(Debug, Clone)]
 pub struct Notification {
     pub event: String,
     pub id: String,
     pub data: String,
+    pub failure: Option<ConversionFailure>,
 }
 
 #[derive(Debug, Clone)]
@@ -12,7 +16,15 @@ pub struct ConversionOptions {
     pub ci_image_name: String,
     pub opt_ocr_lang: Option<String>,
     pub opt_passwd: Option<String>,
-    pub visualquality: String
+    pub visualquality: String,
+    pub opt_page_ranges: Option<String>,
+    pub resource_limits: limits::ResourceLimits,
+    pub preserve_page_size: bool,
+    pub opt_image_encoding: Option<String>,
+    pub opt_jpeg_quality: Option<u8>,
+    pub pdfa: bool,
+    pub strip_metadata: bool,
+    pub opt_text_layer: Option<String>
 }
 
 impl ConversionOptions {
@@ -26,14 +38,22 @@ impl ConversionOptions {
             ci_image_name,
             opt_ocr_lang,
             opt_passwd,
-            visualquality
+            visualquality,
+            opt_page_ranges: None,
+            resource_limits: limits::ResourceLimits::default(),
+            preserve_page_size: false,
+            opt_image_encoding: None,
+            opt_jpeg_quality: None,
+            pdfa: false,
+            strip_metadata: false,
+            opt_text_layer: None
         }
     }
 }
 
 impl Notification {
     pub fn new(event: String, id: String, data: String) -> Self {
-        Self { event, id, data }
+        Self { event, id, data, failure: None }
     }
 }
 
@@ -41,6 +61,10 @@ impl Notification {
 pub struct CompletionMessage {
     pub percent_complete: usize,
     pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ConversionResult>,
 }
 
 impl CompletionMessage {
@@ -48,10 +72,46 @@ impl CompletionMessage {
         Self {
             data: new_data,
             percent_complete: 100,
+            error_code: None,
+            result: None,
         }
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ConversionResult {
+    pub page_count: usize,
+    pub output_size: u64,
+}
+
+// Progress events of entrusted-cli, only the fields needed to report the conversion outcome are read
+// Event types are 'phase_started', 'progress', 'page_done', 'warning', 'error' and 'result'
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct LogMessage {
+    #[serde(default)]
+    pub event: String,
+    pub data: String,
+    pub percent_complete: usize,
+    #[serde(default)]
+    pub error_code: Option<String>,
+    #[serde(default)]
+    pub result: Option<ConversionResult>,
+}
+
+impl LogMessage {
+    // Messages without an event type only have an error code when the conversion failed
+    pub fn is_error(&self) -> bool {
+        self.error_code.is_some() && (self.event == "error" || self.event.is_empty())
+    }
+}
+
+// Failed conversions are reported as problem details when downloading their result
+#[derive(Debug, Clone)]
+pub struct ConversionFailure {
+    pub error_code: Option<String>,
+    pub reason: String,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct UploadResponse {
     pub request_id: String,
@@ -77,5 +137,12 @@ pub struct UploadedFile {
     pub location: String,
     pub ocrlang: String,
     pub fileext: String,
-    pub visualquality: String
+    pub visualquality: String,
+    pub pages: String,
+    pub preservepagesize: bool,
+    pub imageencoding: String,
+    pub jpegquality: Option<u8>,
+    pub pdfa: bool,
+    pub stripmetadata: bool,
+    pub textlayer: String
 }
@@ -42,6 +42,7 @@ use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
 
 use crate::process;
 use entrusted_l10n as l10n;
+use entrusted_common::{errors, limits};
 
 use crate::config;
 use crate::model;
@@ -59,6 +60,7 @@ pub async fn serve(
     host: &str,
     port: &str,
     ci_image_name: String,
+    resource_limits: limits::ResourceLimits,
     trans: l10n::Translations,
 ) -> Result<(), Box<dyn Error>> {
     tracing_subscriber::fmt().with_ansi(false).init();
@@ -66,6 +68,7 @@ pub async fn serve(
     let state_trans = Arc::new(trans.clone());
     let state_bc = Broadcaster::create();
     let state_ci_image = Arc::new(ci_image_name.clone());
+    let state_resource_limits = Arc::new(resource_limits);
 
     let addr = format!("{}:{}", host, port);
     tracing::info!("{}: {}", trans.gettext("Starting server at address"), &addr);
@@ -84,6 +87,7 @@ pub async fn serve(
         ))
         .layer(CorsLayer::permissive())
         .layer(Extension(state_ci_image))
+        .layer(Extension(state_resource_limits))
         .layer(Extension(state_bc))
         .layer(Extension(state_trans));
 
@@ -140,6 +144,7 @@ async fn upload(
     headers: HeaderMap,
     uri: Uri,
     ci_image_name: Extension<Arc<String>>,
+    resource_limits: Extension<Arc<limits::ResourceLimits>>,
     trans_ref: Extension<Arc<l10n::Translations>>,
     payload: Multipart,
 ) -> Result<impl IntoResponse, AppError> {
@@ -199,9 +204,28 @@ async fn upload(
             let input_path = PathBuf::from(&new_upload_info.location);
             let output_path = tmpdir.join(output_filename_for(new_upload_info.location.clone()));
             let container_image_name = ci_image_name.to_string();
-            let conversion_options =
+            let mut conversion_options =
                 model::ConversionOptions::new(container_image_name, ocr_lang_opt, opt_passwd, image_quality);
 
+            if !new_upload_info.pages.is_empty() {
+                conversion_options.opt_page_ranges = Some(new_upload_info.pages.clone());
+            }
+
+            conversion_options.resource_limits = (*resource_limits.0).clone();
+            conversion_options.preserve_page_size = new_upload_info.preservepagesize;
+
+            if !new_upload_info.imageencoding.is_empty() {
+                conversion_options.opt_image_encoding = Some(new_upload_info.imageencoding.clone());
+            }
+
+            conversion_options.opt_jpeg_quality = new_upload_info.jpegquality;
+            conversion_options.pdfa = new_upload_info.pdfa;
+            conversion_options.strip_metadata = new_upload_info.stripmetadata;
+
+            if !new_upload_info.textlayer.is_empty() {
+                conversion_options.opt_text_layer = Some(new_upload_info.textlayer.clone());
+            }
+
             if let Err(ex) = run_entrusted(
                 request_id,
                 input_path,
@@ -301,6 +325,20 @@ async fn downloads(
             problem_bad_request(l10n_ref.gettext("Invalid request identifier or perhaps the file name atrociously long"), &uri)));
     }
 
+    let opt_failure = NOTIFICATIONS_PER_REFID.lock().ok().and_then(|notifs_per_refid| {
+        notifs_per_refid.get(&request_id).and_then(|notifications_group| {
+            notifications_group.lock().unwrap().iter().find_map(|n| n.failure.clone())
+        })
+    });
+
+    if let Some(failure) = opt_failure {
+        if let Ok(mut notifs_per_refid) = NOTIFICATIONS_PER_REFID.lock() {
+            notifs_per_refid.remove(&request_id);
+        }
+
+        return Err(problem_conversion_failure(failure, &uri));
+    }
+
     let file_loc = env::temp_dir()
         .join(config::PROGRAM_GROUP)
         .join(&fileid);
@@ -401,6 +439,38 @@ fn problem_internal_server_error(reason: String, uri: &Uri) -> http_api_problem:
         .instance(uri.to_string())
 }
 
+// Invalid options are client errors, and so are documents that can't be converted as-is
+fn problem_conversion_failure(failure: model::ConversionFailure, uri: &Uri) -> AppError {
+    let error_code = failure.error_code.unwrap_or_else(|| errors::ErrorCode::ConversionFailed.name().to_string());
+    let status = errors::ErrorCode::from_name(&error_code)
+        .map(conversion_failure_status)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let problem = http_api_problem::HttpApiProblem::with_title_and_type(status)
+        .detail(failure.reason)
+        .instance(uri.to_string())
+        .value("error_code", &error_code);
+
+    match status {
+        StatusCode::BAD_REQUEST          => AppError::BadRequest(problem),
+        StatusCode::UNPROCESSABLE_ENTITY => AppError::UnprocessableEntity(problem),
+        _                                => AppError::InternalServerError(problem),
+    }
+}
+
+fn conversion_failure_status(error_code: errors::ErrorCode) -> StatusCode {
+    match error_code {
+        errors::ErrorCode::InvalidOption           => StatusCode::BAD_REQUEST,
+        errors::ErrorCode::InputNotFound           => StatusCode::UNPROCESSABLE_ENTITY,
+        errors::ErrorCode::UnsupportedMime         => StatusCode::UNPROCESSABLE_ENTITY,
+        errors::ErrorCode::PolyglotInput           => StatusCode::UNPROCESSABLE_ENTITY,
+        errors::ErrorCode::WrongPassword           => StatusCode::UNPROCESSABLE_ENTITY,
+        errors::ErrorCode::ResourceLimit           => StatusCode::UNPROCESSABLE_ENTITY,
+        errors::ErrorCode::ConversionFailed        => StatusCode::INTERNAL_SERVER_ERROR,
+        errors::ErrorCode::LibreofficeExportFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        errors::ErrorCode::OcrInitFailed           => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 fn parse_accept_language(req_language: &HeaderValue, fallback_lang: String) -> String {
     if let Ok(req_language_str) = req_language.to_str() {
         let language_list = req_language_str.split(',').collect::<Vec<&str>>();
@@ -529,6 +599,7 @@ impl Stream for Client {
 enum AppError {
     NotFound(http_api_problem::HttpApiProblem),
     BadRequest(http_api_problem::HttpApiProblem),
+    UnprocessableEntity(http_api_problem::HttpApiProblem),
     InternalServerError(http_api_problem::HttpApiProblem),
 }
 
@@ -538,6 +609,7 @@ impl IntoResponse for AppError {
         let (status, problem) = match self {
             AppError::NotFound(reason)            => (StatusCode::NOT_FOUND, reason),
             AppError::BadRequest(reason)          => (StatusCode::BAD_REQUEST, reason),
+            AppError::UnprocessableEntity(reason) => (StatusCode::UNPROCESSABLE_ENTITY, reason),
             AppError::InternalServerError(reason) => (StatusCode::INTERNAL_SERVER_ERROR, reason),
         };
 
@@ -571,6 +643,13 @@ pub async fn save_file(
     let mut ocrlang       = String::new();
     let mut docpassword   = String::new();
     let mut visualquality = "medium".to_string();
+    let mut pages         = String::new();
+    let mut preservepagesize = false;
+    let mut imageencoding = String::new();
+    let mut jpegquality   = None;
+    let mut pdfa          = false;
+    let mut stripmetadata = false;
+    let mut textlayer     = String::new();
 
     while let Ok(Some(field)) = payload.next_field().await {
         if let Some(fname) = field.name() {
@@ -601,6 +680,65 @@ pub async fn save_file(
                         }
                     }
                 }
+            } else if fname == "preservepagesize" {
+                if let Ok(chunk) = field.text().await {
+                    preservepagesize = chunk.trim() == "true";
+                }
+            } else if fname == "pdfa" {
+                if let Ok(chunk) = field.text().await {
+                    pdfa = chunk.trim() == "true";
+                }
+            } else if fname == "stripmetadata" {
+                if let Ok(chunk) = field.text().await {
+                    stripmetadata = chunk.trim() == "true";
+                }
+            } else if fname == "textlayer" {
+                if let Ok(chunk) = field.text().await {
+                    let text_layer = chunk.trim();
+
+                    if !text_layer.is_empty() {
+                        if !(text_layer == "ocr" || text_layer == "original") {
+                            return Err(l10n.gettext_fmt("Invalid 'textlayer' {0} in form data. It should be either 'ocr' or 'original'.", vec![text_layer]).into());
+                        }
+
+                        textlayer.push_str(text_layer);
+                    }
+                }
+            } else if fname == "imageencoding" {
+                if let Ok(chunk) = field.text().await {
+                    let image_encoding = chunk.trim();
+
+                    if !image_encoding.is_empty() {
+                        if !(image_encoding == "lossless" || image_encoding == "jpeg" || image_encoding == "bilevel") {
+                            return Err(l10n.gettext_fmt("Invalid 'imageencoding' {0} in form data. It should be one of 'lossless', 'jpeg' or 'bilevel'.", vec![image_encoding]).into());
+                        }
+
+                        imageencoding.push_str(image_encoding);
+                    }
+                }
+            } else if fname == "jpegquality" {
+                if let Ok(chunk) = field.text().await {
+                    let jpeg_quality = chunk.trim();
+
+                    if !jpeg_quality.is_empty() {
+                        match jpeg_quality.parse::<u8>() {
+                            Ok(v) if (1..=100).contains(&v) => jpegquality = Some(v),
+                            _ => return Err(l10n.gettext_fmt("Invalid 'jpegquality' {0} in form data. It should be a number between 1 and 100.", vec![jpeg_quality]).into())
+                        }
+                    }
+                }
+            } else if fname == "pages" {
+                if let Ok(chunk) = field.text().await {
+                    let page_ranges = chunk.trim();
+
+                    if !page_ranges.is_empty() {
+                        if !page_ranges.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '-' || c == ' ') {
+                            return Err(l10n.gettext_fmt("Invalid 'pages' {0} in form data. Use page numbers and ranges such as '1-5,9,12-'.", vec![page_ranges]).into());
+                        }
+
+                        pages.push_str(page_ranges);
+                    }
+                }
             } else {
                 tracing::warn!(
                     "{}: {}",
@@ -655,7 +793,14 @@ pub async fn save_file(
         location,
         ocrlang,
         fileext,
-        visualquality
+        visualquality,
+        pages,
+        preservepagesize,
+        imageencoding,
+        jpegquality,
+        pdfa,
+        stripmetadata,
+        textlayer
     })
 }
 
@@ -666,10 +811,12 @@ fn progress_made(
     counter: i32,
     err_find_notif: String,
     err_notif_handle: String,
+    opt_failure: Option<model::ConversionFailure>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let nevent = event.to_string();
     let nid = format!("{}", counter);
-    let n = model::Notification::new(nevent, nid, data);
+    let mut n = model::Notification::new(nevent, nid, data);
+    n.failure = opt_failure;
 
     if let Ok(bc) = NOTIFICATIONS_PER_REFID.lock() {
         if let Some(v) = bc.get(&refid) {
@@ -726,6 +873,40 @@ async fn run_entrusted(
         cmd_args.push(ocr_lang);
     }
 
+    if let Some(page_ranges) = conversion_options.opt_page_ranges {
+        cmd_args.push("--pages".to_string());
+        cmd_args.push(page_ranges);
+    }
+
+    cmd_args.append(&mut conversion_options.resource_limits.to_args());
+
+    if conversion_options.preserve_page_size {
+        cmd_args.push("--preserve-page-size".to_string());
+    }
+
+    if let Some(image_encoding) = conversion_options.opt_image_encoding {
+        cmd_args.push("--image-encoding".to_string());
+        cmd_args.push(image_encoding);
+    }
+
+    if let Some(jpeg_quality) = conversion_options.opt_jpeg_quality {
+        cmd_args.push("--jpeg-quality".to_string());
+        cmd_args.push(jpeg_quality.to_string());
+    }
+
+    if conversion_options.pdfa {
+        cmd_args.push("--pdfa".to_string());
+    }
+
+    if conversion_options.strip_metadata {
+        cmd_args.push("--strip-metadata".to_string());
+    }
+
+    if let Some(text_layer) = conversion_options.opt_text_layer {
+        cmd_args.push("--text-layer".to_string());
+        cmd_args.push(text_layer);
+    }
+
     if conversion_options.opt_passwd.is_some() {
         cmd_args.push("--passwd-prompt".to_string());
     }
@@ -756,13 +937,24 @@ async fn run_entrusted(
     let mut stderr_reader = BufReader::new(stderr).lines();
 
     let mut success = false;
+    let mut failure = model::ConversionFailure { error_code: None, reason: l10n.gettext("Processing failure") };
+    let mut opt_result: Option<model::ConversionResult> = None;
 
     loop {
         tokio::select! {
             result = stdout_reader.next_line() => {
                 match result {
                     Ok(Some(line)) => {
-                        progress_made(refid.clone(), "processing_update", line, counter, err_find_notif.clone(), err_notif_handle.clone())?;
+                        // The error event with an error code explains why the conversion failed
+                        if let Ok(log_msg) = serde_json::from_str::<model::LogMessage>(&line) {
+                            if log_msg.is_error() {
+                                failure = model::ConversionFailure { error_code: log_msg.error_code, reason: log_msg.data };
+                            } else if log_msg.event == "result" {
+                                opt_result = log_msg.result;
+                            }
+                        }
+
+                        progress_made(refid.clone(), "processing_update", line, counter, err_find_notif.clone(), err_notif_handle.clone(), None)?;
                         counter += 1;
                     }, Err(_) => break,
                     _ => (),
@@ -771,7 +963,7 @@ async fn run_entrusted(
             result = stderr_reader.next_line() => {
                 match result {
                     Ok(Some(line)) => {
-                        progress_made(refid.clone(), "processing_update", line, counter, err_find_notif.clone(), err_notif_handle.clone())?;
+                        progress_made(refid.clone(), "processing_update", line, counter, err_find_notif.clone(), err_notif_handle.clone(), None)?;
                         counter += 1;
                     }, Err(_) => break,
                     _ => (),
@@ -788,7 +980,8 @@ async fn run_entrusted(
     }
 
     if success {
-        let msg = model::CompletionMessage::new(format!("/api/v1/downloads/{}", refid.clone()));
+        let mut msg = model::CompletionMessage::new(format!("/api/v1/downloads/{}", refid.clone()));
+        msg.result = opt_result;
 
         if let Ok(msg_json) = serde_json::to_string(&msg) {
             progress_made(
@@ -798,6 +991,7 @@ async fn run_entrusted(
                 counter,
                 err_find_notif,
                 err_notif_handle,
+                None,
             )?;
 
             if let Err(ex) = fs::remove_file(&input_path) {
@@ -807,7 +1001,8 @@ async fn run_entrusted(
 
         Ok(())
     } else {
-        let msg = model::CompletionMessage::new("failure".to_string());
+        let mut msg = model::CompletionMessage::new("failure".to_string());
+        msg.error_code = failure.error_code.clone();
 
         if let Ok(msg_json) = serde_json::to_string(&msg) {
             progress_made(
@@ -817,6 +1012,7 @@ async fn run_entrusted(
                 counter,
                 err_find_notif,
                 err_notif_handle,
+                Some(failure),
             )?;
 
             if let Err(ex) = fs::remove_file(&input_path) {
@@ -11,7 +11,8 @@ rm -rf  ${PROJECTDIR}/entrusted_client/target    \
         ${PROJECTDIR}/entrusted_webclient/target \
         ${PROJECTDIR}/entrusted_webserver/target \
         ${PROJECTDIR}/entrusted_container/target \
-        ${PROJECTDIR}/entrusted_l10n/target
+        ${PROJECTDIR}/entrusted_l10n/target \
+        ${PROJECTDIR}/entrusted_common/target
 
 podman rmi --force docker.io/uycyjnzgntrn/entrusted_container:${APPVERSION}-amd64
 podman rmi --force docker.io/uycyjnzgntrn/entrusted_container:${APPVERSION}-arm64
@@ -61,6 +61,7 @@ for CPU_ARCH in $CPU_ARCHS ; do
 
     echo "Cleanup software components build folders"
     test -d ${PROJECTDIR}/entrusted_l10n/target      && rm -rf ${PROJECTDIR}/entrusted_l10n/target
+    test -d ${PROJECTDIR}/entrusted_common/target    && rm -rf ${PROJECTDIR}/entrusted_common/target
     test -d ${PROJECTDIR}/entrusted_container/target && rm -rf ${PROJECTDIR}/entrusted_container/target
     test -d ${PROJECTDIR}/entrusted_client/target    && rm -rf ${PROJECTDIR}/entrusted_client/target
     test -d ${PROJECTDIR}/entrusted_webclient/target && rm -rf ${PROJECTDIR}/entrusted_webclient/target